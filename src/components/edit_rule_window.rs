@@ -1,12 +1,14 @@
 //! A window for adding and editing rules.
 use std::{
+    cmp::Ordering,
     path::PathBuf,
+    rc::Rc,
     sync::{Arc, Mutex},
 };
 
 use gtk::prelude::{
-    BoxExt, ButtonExt, EditableExt, EntryBufferExtManual, EntryExt, GtkWindowExt, OrientableExt,
-    WidgetExt,
+    BoxExt, ButtonExt, CheckButtonExt, EditableExt, EntryBufferExtManual, EntryExt, GtkWindowExt,
+    OrientableExt, WidgetExt,
 };
 use relm4::{
     adw::{
@@ -14,11 +16,19 @@ use relm4::{
         traits::{ActionRowExt, BinExt},
     },
     gtk::{self, prelude::IsA},
-    view, ComponentParts, ComponentSender, RelmRemoveAllExt, Sender, SimpleComponent, WidgetPlus,
+    view, Component, ComponentParts, ComponentSender, RelmRemoveAllExt, Sender, SimpleComponent,
+    WidgetPlus,
 };
 
+use crate::components::plan_window::PlanWindow;
 use crate::lib::Base;
-use crate::lib::{all_tags_sorted_by_columns, Event, Rule, Tag, TagExpr, Var};
+use crate::lib::{
+    custom_age_tag, custom_audio_tag, custom_camera_tag, custom_date_tag, custom_dimension_tag,
+    custom_mime_tag, custom_mode_tag, custom_name_tag, custom_orientation_tag, custom_owner_tag,
+    parse_query, AudioMatch, CommandLine, ContentSearch, DateMatch, DimensionMatch, Event,
+    NameMatch, QueryError, Rule, Tag, TagColumn, TagExpr, TagLibrary, Template, Timestamp, Var,
+    Walk, MODE_MATCHES, ORIENTATIONS,
+};
 use crate::util::Bind;
 use crate::AppMsg;
 use crate::SENDER;
@@ -28,8 +38,12 @@ pub struct EditRuleWindow {
     mode: EditMode,
     root: gtk::Window,
     rule: Rule,
+    /// Folder the rule belongs to.
+    dir: PathBuf,
+    tags: Arc<Mutex<TagLibrary>>,
     tag_select_multiple: Arc<Mutex<bool>>,
     tag_negate: Arc<Mutex<bool>>,
+    tag_group: Arc<Mutex<bool>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -41,12 +55,20 @@ pub enum EditMode {
 pub enum EditRuleInput {
     Save,
     Delete,
+    Simulate,
     SetTitle(String),
     RemoveEventAt(usize),
     AddEvent(Event),
+    AddDedupEvent,
     ClickedTag(usize, Tag),
+    SaveCustomTags(usize),
+    SetTagExpr(usize, TagExpr),
     ResetTag(usize),
     ChangedPath(usize, PathBuf),
+    ChangedTemplate(usize, Template),
+    ChangedCommand(usize, CommandLine),
+    ChangedChoice(usize, &'static str, usize),
+    ChangedWalk(usize, Walk),
 }
 
 #[derive(Debug)]
@@ -60,7 +82,7 @@ pub enum EditRuleOutput {
 impl SimpleComponent for EditRuleWindow {
     type Widgets = EditRuleWindowWidgets;
 
-    type InitParams = (Rule, EditMode);
+    type InitParams = (Rule, EditMode, PathBuf, Arc<Mutex<TagLibrary>>);
 
     type Input = EditRuleInput;
     type Output = EditRuleOutput;
@@ -89,6 +111,12 @@ impl SimpleComponent for EditRuleWindow {
                         sender.input(EditRuleInput::Save);
                     }
                 },
+                pack_start = &icon_label_button("Simulate", "media-playback-start-symbolic") -> gtk::Button {
+                    set_tooltip_text: Some("Show what the rule would do right now without changing any files"),
+                    connect_clicked[sender] => move |_| {
+                        sender.input(EditRuleInput::Simulate);
+                    }
+                },
                 pack_end = &gtk::MenuButton {
                     set_icon_name: "view-more-symbolic",
                     set_popover: view_more_popover = Some(&gtk::Popover) {
@@ -129,13 +157,16 @@ impl SimpleComponent for EditRuleWindow {
                             remove_all: (),
                             #[watch]
                             #[iterate]
-                            append: model
-                            .rule
-                            .events()
-                            .iter()
-                            .enumerate()
-                            .map(|(index, rule)| row_view(index, rule, &sender.input, model.tag_select_multiple.clone(), model.tag_negate.clone()))
-                            .collect::<Vec<_>>()
+                            append: {
+                                let tags = model.tags.lock().expect("unable to aquire mutex");
+                                model
+                                .rule
+                                .events()
+                                .iter()
+                                .enumerate()
+                                .map(|(index, rule)| row_view(index, rule, &tags, &sender.input, model.tag_select_multiple.clone(), model.tag_negate.clone(), model.tag_group.clone()))
+                                .collect::<Vec<_>>()
+                            }
                             .iter(),
                         },
                         gtk::MenuButton {
@@ -150,6 +181,21 @@ impl SimpleComponent for EditRuleWindow {
                                     append = &icon_label_button("Move", "go-jump-symbolic") -> gtk::Button {
                                         connect_clicked[sender, popover] => move |_| { sender.input(EditRuleInput::AddEvent(Event::mv()) ); popover.hide() },
                                     },
+                                    append = &icon_label_button("Rename", "insert-text-symbolic") -> gtk::Button {
+                                        connect_clicked[sender, popover] => move |_| { sender.input(EditRuleInput::AddEvent(Event::rename()) ); popover.hide() },
+                                    },
+                                    append = &icon_label_button("Run", "utilities-terminal-symbolic") -> gtk::Button {
+                                        connect_clicked[sender, popover] => move |_| { sender.input(EditRuleInput::AddEvent(Event::command()) ); popover.hide() },
+                                    },
+                                    append = &icon_label_button("Compress", "package-x-generic-symbolic") -> gtk::Button {
+                                        connect_clicked[sender, popover] => move |_| { sender.input(EditRuleInput::AddEvent(Event::compress()) ); popover.hide() },
+                                    },
+                                    append = &icon_label_button("Extract", "folder-open-symbolic") -> gtk::Button {
+                                        connect_clicked[sender, popover] => move |_| { sender.input(EditRuleInput::AddEvent(Event::extract()) ); popover.hide() },
+                                    },
+                                    append = &icon_label_button("Deduplicate", "edit-select-all-symbolic") -> gtk::Button {
+                                        connect_clicked[sender, popover] => move |_| { sender.input(EditRuleInput::AddDedupEvent); popover.hide() },
+                                    },
                                     append = &icon_label_button("Trash", "user-trash-symbolic") -> gtk::Button {
                                         connect_clicked[sender, popover] => move |_| { sender.input(EditRuleInput::AddEvent(Event::trash()) ); popover.hide() },
                                     },
@@ -163,16 +209,19 @@ impl SimpleComponent for EditRuleWindow {
     }
 
     fn init(
-        (rule, mode): Self::InitParams,
+        (rule, mode, dir, tags): Self::InitParams,
         root: &Self::Root,
         sender: &ComponentSender<Self>,
     ) -> ComponentParts<Self> {
         let model = EditRuleWindow {
             rule,
+            dir,
+            tags,
             root: root.clone(),
             mode,
             tag_select_multiple: Arc::new(Mutex::new(false)),
             tag_negate: Arc::new(Mutex::new(false)),
+            tag_group: Arc::new(Mutex::new(false)),
         };
         let widgets = view_output!();
         widgets.root.present();
@@ -190,6 +239,19 @@ impl SimpleComponent for EditRuleWindow {
                 sender.output(EditRuleOutput::Delete);
                 self.root.destroy();
             }
+            EditRuleInput::Simulate => match self.rule.plan(&self.dir) {
+                Ok(actions) => {
+                    PlanWindow::builder()
+                        .transient_for(&self.root)
+                        .launch((self.rule.title().to_owned(), actions));
+                }
+                Err(e) => {
+                    SENDER.send(AppMsg::Error(
+                        "Unable to simulate the rule".to_string(),
+                        e.to_string(),
+                    ));
+                }
+            },
             EditRuleInput::SetTitle(title) => {
                 *self.rule.title_mut() = title;
             }
@@ -199,32 +261,86 @@ impl SimpleComponent for EditRuleWindow {
             EditRuleInput::AddEvent(event) => {
                 self.rule.events_mut().push(event);
             }
+            EditRuleInput::AddDedupEvent => {
+                let tags = self.tags.lock().expect("unable to aquire mutex");
+                self.rule.events_mut().push(Event::dedup(&tags));
+            }
             EditRuleInput::ChangedPath(index, path) => {
                 if let Some(event) = self.rule.events_mut().get_mut(index) {
                     event.set_path(path);
                 }
             }
+            EditRuleInput::ChangedTemplate(index, template) => {
+                if let Some(event) = self.rule.events_mut().get_mut(index) {
+                    event.set_template(template);
+                }
+            }
+            EditRuleInput::ChangedCommand(index, command) => {
+                if let Some(event) = self.rule.events_mut().get_mut(index) {
+                    event.set_command(command);
+                }
+            }
+            EditRuleInput::ChangedChoice(index, key, selected) => {
+                if let Some(event) = self.rule.events_mut().get_mut(index) {
+                    event.set_choice(key, selected);
+                }
+            }
+            EditRuleInput::ChangedWalk(index, walk) => {
+                if let Some(event) = self.rule.events_mut().get_mut(index) {
+                    event.set_walk(walk);
+                }
+            }
             EditRuleInput::ClickedTag(index, tag) => {
                 if let Some(event) = self.rule.events_mut().get_mut(index) {
                     let mut tag_select_multiple = self.tag_select_multiple.lock().unwrap();
                     let mut tag_negate = self.tag_negate.lock().unwrap();
+                    let mut tag_group = self.tag_group.lock().unwrap();
+                    let expr = TagExpr::new(tag.clone(), !*tag_negate);
                     if event.tag_expr().has(&tag) {
                         event.tag_expr_mut().remove(&tag);
                     } else if *tag_select_multiple {
-                        event.tag_expr_mut().push(tag, !*tag_negate);
+                        event.tag_expr_mut().and(expr);
+                    } else if *tag_group {
+                        event.tag_expr_mut().or(expr);
                     } else {
-                        *event.tag_expr_mut() = TagExpr::new(tag, !*tag_negate);
+                        *event.tag_expr_mut() = expr;
                     }
                     *tag_select_multiple = false;
                     *tag_negate = false;
+                    *tag_group = false;
+                }
+            }
+            EditRuleInput::SaveCustomTags(index) => {
+                if let Some(event) = self.rule.events().get(index) {
+                    let mut tags = self.tags.lock().expect("unable to aquire mutex");
+                    let errors = event
+                        .tag_expr()
+                        .tags()
+                        .into_iter()
+                        .filter_map(|tag| tags.save(tag).err())
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>();
+                    if !errors.is_empty() {
+                        SENDER.send(AppMsg::Error(
+                            "Unable to save the tags to the library".to_string(),
+                            errors.join("\n"),
+                        ));
+                    }
+                }
+            }
+            EditRuleInput::SetTagExpr(index, expr) => {
+                if let Some(event) = self.rule.events_mut().get_mut(index) {
+                    *event.tag_expr_mut() = expr;
                 }
             }
             EditRuleInput::ResetTag(index) => {
                 if let Some(event) = self.rule.events_mut().get_mut(index) {
                     let mut tag_select_multiple = self.tag_select_multiple.lock().unwrap();
                     let mut tag_negate = self.tag_negate.lock().unwrap();
+                    let mut tag_group = self.tag_group.lock().unwrap();
                     *tag_select_multiple = false;
                     *tag_negate = false;
+                    *tag_group = false;
                     *event.tag_expr_mut() = TagExpr::default();
                 }
             }
@@ -232,20 +348,43 @@ impl SimpleComponent for EditRuleWindow {
     }
 }
 
+/// The error message followed by the query with the wrong part underlined.
+fn query_error_markup(query: &str, error: &QueryError) -> String {
+    let start = error.span.start.min(query.len());
+    let end = error.span.end.clamp(start, query.len());
+    // An error at the very end, such as a missing value, underlines a space
+    let wrong = if start == end {
+        " "
+    } else {
+        &query[start..end]
+    };
+    format!(
+        "{}\n<tt>{}<u>{}</u>{}</tt>",
+        gtk::glib::markup_escape_text(&error.message),
+        gtk::glib::markup_escape_text(&query[..start]),
+        gtk::glib::markup_escape_text(wrong),
+        gtk::glib::markup_escape_text(&query[end..]),
+    )
+}
+
 fn row_view(
     index: usize,
     event: &Event,
+    library: &TagLibrary,
     sender: &Sender<EditRuleInput>,
     tag_select_multiple: Arc<Mutex<bool>>,
     tag_negate: Arc<Mutex<bool>>,
+    tag_group: Arc<Mutex<bool>>,
 ) -> impl IsA<gtk::Widget> {
     let row = adw::ActionRow::new();
     row.add_prefix(&event_view(
         index,
         event,
+        library,
         sender,
         tag_select_multiple,
         tag_negate,
+        tag_group,
     ));
 
     view! {
@@ -267,9 +406,11 @@ fn row_view(
 fn event_view(
     index: usize,
     event: &Event,
+    library: &TagLibrary,
     sender: &Sender<EditRuleInput>,
     tag_select_multiple: Arc<Mutex<bool>>,
     tag_negate: Arc<Mutex<bool>>,
+    tag_group: Arc<Mutex<bool>>,
 ) -> impl IsA<gtk::Widget> {
     let vars = event
         .vars()
@@ -278,9 +419,11 @@ fn event_view(
             var_view(
                 index,
                 event,
+                library,
                 sender,
                 tag_select_multiple.clone(),
                 tag_negate.clone(),
+                tag_group.clone(),
             )
         })
         .collect::<Vec<_>>();
@@ -318,9 +461,11 @@ fn icon_label_button(label: &str, icon: &str) -> gtk::Button {
 pub fn var_view(
     index: usize,
     var: &Var,
+    library: &TagLibrary,
     sender: &Sender<EditRuleInput>,
     tag_select_multiple: Arc<Mutex<bool>>,
     tag_negate: Arc<Mutex<bool>>,
+    tag_group: Arc<Mutex<bool>>,
 ) -> impl IsA<gtk::Widget> {
     let bin = adw::Bin::new();
     match var {
@@ -331,7 +476,7 @@ pub fn var_view(
                 .build(),
         )),
         Var::TagExpr(expr) => bin.set_child(Some(&{
-            let columns = all_tags_sorted_by_columns();
+            let library = Rc::new(library.clone());
             view! {
                 button = gtk::MenuButton {
                     set_margin_top: 12,
@@ -341,6 +486,97 @@ pub fn var_view(
                     set_popover: popover = Some(&gtk::Popover) {
                         gtk::Box { set_orientation: gtk::Orientation::Vertical,
                             set_spacing: 10,
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_margin_start: 10,
+                                set_margin_end: 10,
+                                set_margin_top: 10,
+                                set_spacing: 5,
+                                gtk::Box {
+                                    set_orientation: gtk::Orientation::Horizontal,
+                                    set_spacing: 10,
+                                    append: query = &gtk::Entry {
+                                        set_hexpand: true,
+                                        add_css_class: "monospace",
+                                        set_text: &expr.to_query(&library),
+                                        set_placeholder_text: Some("type:file AND ext:pdf,djvu AND created>30d AND NOT name~\"^invoice\""),
+                                        connect_changed[query_error, query_confirm, library] => move |entry| {
+                                            let text = entry.buffer().text();
+                                            match parse_query(&text, &library) {
+                                                Ok(_) => {
+                                                    entry.remove_css_class("error");
+                                                    query_error.set_visible(false);
+                                                    query_confirm.set_sensitive(true);
+                                                }
+                                                Err(e) => {
+                                                    entry.add_css_class("error");
+                                                    query_error.set_markup(&query_error_markup(&text, &e));
+                                                    query_error.set_visible(true);
+                                                    query_confirm.set_sensitive(false);
+                                                }
+                                            }
+                                        },
+                                        connect_activate[sender, popover, library] => move |entry| {
+                                            if let Ok(expr) = parse_query(&entry.buffer().text(), &library) {
+                                                popover.hide();
+                                                sender.send(EditRuleInput::SetTagExpr(index, expr));
+                                            }
+                                        }
+                                    },
+                                    append: query_confirm = &gtk::Button {
+                                        set_icon_name: "emblem-ok-symbolic",
+                                        set_css_classes: &["flat", "circular"],
+                                        connect_clicked[sender, query, popover, library] => move |_| {
+                                            if let Ok(expr) = parse_query(&query.buffer().text(), &library) {
+                                                popover.hide();
+                                                sender.send(EditRuleInput::SetTagExpr(index, expr));
+                                            }
+                                        }
+                                    }
+                                },
+                                append: query_error = &gtk::Label {
+                                    set_visible: false,
+                                    set_xalign: 0.,
+                                    set_wrap: true,
+                                    add_css_class: "error",
+                                },
+                            },
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_margin_start: 10,
+                                set_margin_end: 10,
+                                set_spacing: 15,
+                                #[iterate]
+                                append: library
+                                    .columns()
+                                    .iter()
+                                    .map(|column| column_view(index, expr, column, sender, &popover))
+                                    .collect::<Vec<_>>()
+                                    .iter(),
+                            },
+                            gtk::Separator {},
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_margin_start: 10,
+                                set_margin_end: 10,
+                                set_spacing: 10,
+                                gtk::Label {
+                                    set_hexpand: true,
+                                    set_xalign: 0.,
+                                    set_markup: "<b>Custom conditions</b>",
+                                },
+                                gtk::Button {
+                                    set_label: "Save to library",
+                                    set_tooltip_text: Some("Add the custom tags of the event to the library"),
+                                    set_sensitive: expr.tags().iter().any(|tag| {
+                                        !library.tags().any(|saved| saved == *tag)
+                                    }),
+                                    connect_clicked[sender, popover] => move |_| {
+                                        popover.hide();
+                                        sender.send(EditRuleInput::SaveCustomTags(index));
+                                    }
+                                },
+                            },
                             gtk::Box {
                                 set_orientation: gtk::Orientation::Horizontal,
                                 set_margin_start: 10,
@@ -350,33 +586,103 @@ pub fn var_view(
                                     set_orientation: gtk::Orientation::Vertical,
                                     set_spacing: 10,
                                     set_width_request: 300,
-                                    gtk::Label { set_markup: "<b>Filetype</b>" },
-                                    gtk::FlowBox {
-                                        set_selection_mode: gtk::SelectionMode::None,
-                                        #[iterate]
-                                        insert[-1]: columns[0]
-                                            .iter()
-                                            .map(|tag| tag_view(index, expr, tag, sender, &popover))
-                                            .collect::<Vec<_>>()
-                                            .iter(),
-                                    }
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom camera", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: camera = &gtk::Entry {
+                                            set_hexpand: true,
+                                            set_placeholder_text: Some("Nikon"),
+                                            connect_changed[camera_confirm] => move |entry| {
+                                                camera_confirm.set_sensitive(!entry.buffer().text().trim().is_empty());
+                                            }
+                                        },
+                                        append: camera_confirm = &gtk::Button {
+                                            set_sensitive: false,
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, camera, popover] => move |_| {
+                                                popover.hide();
+                                                match custom_camera_tag(&camera.buffer().text()) {
+                                                    Ok(tag) => sender.send(EditRuleInput::ClickedTag(index, tag)),
+                                                    Err(e) => SENDER.send(AppMsg::Error("Wrong camera".to_string(), e.to_string())),
+                                                }
+                                            }
+                                        }
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom orientation", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: orientation = &gtk::DropDown::from_strings(&ORIENTATIONS) {
+                                            set_hexpand: true,
+                                        },
+                                        gtk::Button {
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, orientation, popover] => move |_| {
+                                                popover.hide();
+                                                sender.send(EditRuleInput::ClickedTag(index, custom_orientation_tag(orientation.selected() as usize)));
+                                            }
+                                        }
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom dimensions", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: dimension_match = &gtk::DropDown::from_strings(&DimensionMatch::ALL.map(|kind| kind.label())) {},
+                                        append: dimension = &gtk::Entry {
+                                            set_hexpand: true,
+                                            set_placeholder_text: Some("1920, 12 or 16/9"),
+                                        },
+                                        gtk::Button {
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, dimension_match, dimension, popover] => move |_| {
+                                                popover.hide();
+                                                match custom_dimension_tag(DimensionMatch::ALL[dimension_match.selected() as usize], &dimension.buffer().text()) {
+                                                    Ok(tag) => sender.send(EditRuleInput::ClickedTag(index, tag)),
+                                                    Err(e) => SENDER.send(AppMsg::Error("Wrong dimensions".to_string(), e.to_string())),
+                                                }
+                                            }
+                                        }
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom audio", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: audio_match = &gtk::DropDown::from_strings(&AudioMatch::ALL.map(|kind| kind.label())) {},
+                                        append: audio = &gtk::Entry {
+                                            set_hexpand: true,
+                                            set_placeholder_text: Some("Daft Punk"),
+                                            connect_changed[audio_confirm] => move |entry| {
+                                                audio_confirm.set_sensitive(!entry.buffer().text().trim().is_empty());
+                                            }
+                                        },
+                                        append: audio_confirm = &gtk::Button {
+                                            set_sensitive: false,
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, audio_match, audio, popover] => move |_| {
+                                                popover.hide();
+                                                match custom_audio_tag(AudioMatch::ALL[audio_match.selected() as usize], &audio.buffer().text()) {
+                                                    Ok(tag) => sender.send(EditRuleInput::ClickedTag(index, tag)),
+                                                    Err(e) => SENDER.send(AppMsg::Error("Wrong audio tag".to_string(), e.to_string())),
+                                                }
+                                            }
+                                        }
+                                    },
                                 },
                                 gtk::Separator {},
                                 gtk::Box {
                                     set_orientation: gtk::Orientation::Vertical,
                                     set_spacing: 10,
                                     set_width_request: 300,
-                                    gtk::Label { set_markup: "<b>Size</b>" },
-                                    gtk::FlowBox {
-                                        set_selection_mode: gtk::SelectionMode::None,
-                                        #[iterate]
-                                        insert[-1]: columns[1]
-                                            .iter()
-                                            .map(|tag| tag_view(index, expr, tag, sender, &popover))
-                                            .collect::<Vec<_>>()
-                                            .iter(),
-                                    },
-                                    gtk::Label { set_margin_start: 10, set_label: "Custom", set_xalign: 0. },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom size", set_xalign: 0. },
                                     gtk::Box {
                                         set_orientation: gtk::Orientation::Horizontal,
                                         set_margin_all: 10,
@@ -423,25 +729,26 @@ pub fn var_view(
                                     set_orientation: gtk::Orientation::Vertical,
                                     set_spacing: 10,
                                     set_width_request: 300,
-                                    gtk::Label { set_markup: "<b>Creation date</b>" },
-                                    gtk::FlowBox {
-                                        set_selection_mode: gtk::SelectionMode::None,
-                                        #[iterate]
-                                        insert[-1]: columns[2]
-                                        .iter()
-                                        .map(|tag| tag_view(index, expr, tag, sender, &popover))
-                                        .collect::<Vec<_>>()
-                                        .iter(),
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom age", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_start: 10,
+                                        set_margin_end: 10,
+                                        set_spacing: 10,
+                                        append: timestamp = &gtk::DropDown::from_strings(&["Created", "Modified", "Opened", "Taken"]) {
+                                            set_hexpand: true,
+                                        },
+                                        append: older = &gtk::DropDown::from_strings(&["more than", "less than"]) {
+                                            set_hexpand: true,
+                                        },
                                     },
-
-                                    gtk::Label { set_margin_start: 10, set_label: "Custom", set_xalign: 0. },
                                     gtk::Box {
                                         set_orientation: gtk::Orientation::Horizontal,
                                         set_margin_all: 10,
                                         set_spacing: 10,
                                         append: creation_date = &gtk::Entry {
                                             set_hexpand: true,
-                                            set_placeholder_text: Some("48h"),
+                                            set_placeholder_text: Some("90d ago"),
                                             connect_changed[creation_date_confirm] => move |entry| {
                                                 if entry.buffer().text().trim().is_empty() {
                                                     creation_date_confirm.set_sensitive(false);
@@ -455,40 +762,252 @@ pub fn var_view(
                                             set_sensitive: false,
                                             set_icon_name: "emblem-ok-symbolic",
                                             set_css_classes: &["flat", "circular"],
-                                            connect_clicked[sender, creation_date, popover] => move |_| {
-                                                let duration = duration_string::DurationString::try_from(creation_date.buffer().text());
+                                            connect_clicked[sender, creation_date, timestamp, older, popover] => move |_| {
+                                                let text = creation_date.buffer().text();
+                                                let duration = duration_string::DurationString::try_from(text.trim_end_matches("ago").trim().to_owned());
                                                 match duration {
                                                     Ok(duration) => {
                                                         popover.hide();
-                                                        sender.send(EditRuleInput::ClickedTag(index, Tag {
-                                                            name: format!("🕒 < {}", &duration),
-                                                            desc: format!("A custom tag which includes files that were created more than {} ago.", &duration),
-                                                            basis: Base::LifetimeGT(duration.into()),
-                                                        }));
+                                                        let ordering = if older.selected() == 0 { Ordering::Greater } else { Ordering::Less };
+                                                        let timestamp = Timestamp::ALL[timestamp.selected() as usize];
+                                                        sender.send(EditRuleInput::ClickedTag(index, custom_age_tag(timestamp, ordering, duration.into())));
                                                     }
                                                     Err(e) => {
                                                         popover.hide();
-                                                        SENDER.send(AppMsg::Error("Wrong file size formatting".to_string(), e));
+                                                        SENDER.send(AppMsg::Error("Wrong duration formatting".to_string(), e));
                                                     }
                                                 }
                                             }
                                         }
-                                    }
+                                    },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_start: 10,
+                                        set_margin_end: 10,
+                                        set_spacing: 10,
+                                        append: date_match = &gtk::DropDown::from_strings(&DateMatch::ALL.map(|kind| kind.label())) {
+                                            set_hexpand: true,
+                                            connect_selected_notify[calendar] => move |dropdown| {
+                                                calendar.set_sensitive(DateMatch::ALL[dropdown.selected() as usize].uses_date());
+                                            }
+                                        },
+                                        gtk::Button {
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, timestamp, date_match, calendar, popover] => move |_| {
+                                                let picked = calendar.date();
+                                                let date = chrono::NaiveDate::from_ymd_opt(picked.year(), picked.month() as u32, picked.day_of_month() as u32);
+                                                match date {
+                                                    Some(date) => {
+                                                        popover.hide();
+                                                        let timestamp = Timestamp::ALL[timestamp.selected() as usize];
+                                                        sender.send(EditRuleInput::ClickedTag(index, custom_date_tag(timestamp, DateMatch::ALL[date_match.selected() as usize], date)));
+                                                    }
+                                                    None => {
+                                                        popover.hide();
+                                                        SENDER.send(AppMsg::Error("Wrong date".to_string(), format!("{}-{}-{} is not a valid date", picked.year(), picked.month(), picked.day_of_month())));
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    },
+                                    append: calendar = &gtk::Calendar {
+                                        set_margin_start: 10,
+                                        set_margin_end: 10,
+                                        set_margin_bottom: 10,
+                                    },
                                 },
                                 gtk::Separator {},
                                 gtk::Box {
                                     set_orientation: gtk::Orientation::Vertical,
                                     set_spacing: 10,
                                     set_width_request: 300,
-                                    gtk::Label { set_markup: "<b>Other</b>" },
-                                    gtk::FlowBox {
-                                        set_selection_mode: gtk::SelectionMode::None,
-                                        #[iterate]
-                                        insert[-1]: columns[3]
-                                        .iter()
-                                        .map(|tag| tag_view(index, expr, tag, sender, &popover))
-                                        .collect::<Vec<_>>()
-                                        .iter(),
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom name", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Vertical,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: name_match = &gtk::DropDown::from_strings(&NameMatch::ALL.map(|kind| kind.label())) {},
+                                        gtk::Box {
+                                            set_orientation: gtk::Orientation::Horizontal,
+                                            set_spacing: 10,
+                                            append: custom_name = &gtk::Entry {
+                                                set_hexpand: true,
+                                                set_placeholder_text: Some("IMG_*.jpg"),
+                                                connect_changed[custom_name_confirm] => move |entry| {
+                                                    custom_name_confirm.set_sensitive(!entry.buffer().text().trim().is_empty());
+                                                }
+                                            },
+                                            append: custom_name_confirm = &gtk::Button {
+                                                set_sensitive: false,
+                                                set_icon_name: "emblem-ok-symbolic",
+                                                set_css_classes: &["flat", "circular"],
+                                                connect_clicked[sender, name_match, custom_name, ignore_case, popover] => move |_| {
+                                                    popover.hide();
+                                                    match custom_name_tag(NameMatch::ALL[name_match.selected() as usize], &custom_name.buffer().text(), ignore_case.is_active()) {
+                                                        Ok(tag) => sender.send(EditRuleInput::ClickedTag(index, tag)),
+                                                        Err(e) => SENDER.send(AppMsg::Error("Wrong name pattern".to_string(), e.to_string())),
+                                                    }
+                                                }
+                                            }
+                                        },
+                                        append: ignore_case = &gtk::CheckButton {
+                                            set_label: Some("Ignore letter case"),
+                                        },
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Unchanged for", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: stable = &gtk::Entry {
+                                            set_hexpand: true,
+                                            set_placeholder_text: Some("30s"),
+                                            connect_changed[stable_confirm] => move |entry| {
+                                                stable_confirm.set_sensitive(!entry.buffer().text().trim().is_empty());
+                                            }
+                                        },
+                                        append: stable_confirm = &gtk::Button {
+                                            set_sensitive: false,
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, stable, popover] => move |_| {
+                                                popover.hide();
+                                                match duration_string::DurationString::try_from(stable.buffer().text().to_string()) {
+                                                    Ok(duration) => sender.send(EditRuleInput::ClickedTag(index, Tag {
+                                                        name: format!("⏳ Unchanged for {duration}"),
+                                                        desc: format!("A custom tag which includes files that have not changed for {duration} and are not downloads in progress."),
+                                                        basis: Base::StableFor(duration.into()),
+                                                    })),
+                                                    Err(e) => SENDER.send(AppMsg::Error("Wrong duration formatting".to_string(), e)),
+                                                }
+                                            }
+                                        }
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom MIME type", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: mime = &gtk::Entry {
+                                            set_hexpand: true,
+                                            set_placeholder_text: Some("image/*"),
+                                            connect_changed[mime_confirm] => move |entry| {
+                                                mime_confirm.set_sensitive(!entry.buffer().text().trim().is_empty());
+                                            }
+                                        },
+                                        append: mime_confirm = &gtk::Button {
+                                            set_sensitive: false,
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, mime, popover] => move |_| {
+                                                popover.hide();
+                                                match custom_mime_tag(&mime.buffer().text()) {
+                                                    Ok(tag) => sender.send(EditRuleInput::ClickedTag(index, tag)),
+                                                    Err(e) => SENDER.send(AppMsg::Error("Wrong MIME type".to_string(), e.to_string())),
+                                                }
+                                            }
+                                        }
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom owner", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: owner_kind = &gtk::DropDown::from_strings(&["User", "Group"]) {},
+                                        append: owner = &gtk::Entry {
+                                            set_hexpand: true,
+                                            set_placeholder_text: Some("me, 1000 or a name"),
+                                            connect_changed[owner_confirm] => move |entry| {
+                                                owner_confirm.set_sensitive(!entry.buffer().text().trim().is_empty());
+                                            }
+                                        },
+                                        append: owner_confirm = &gtk::Button {
+                                            set_sensitive: false,
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, owner_kind, owner, popover] => move |_| {
+                                                popover.hide();
+                                                match custom_owner_tag(&owner.buffer().text(), owner_kind.selected() == 1) {
+                                                    Ok(tag) => sender.send(EditRuleInput::ClickedTag(index, tag)),
+                                                    Err(e) => SENDER.send(AppMsg::Error("Wrong owner".to_string(), e.to_string())),
+                                                }
+                                            }
+                                        }
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom permissions", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Horizontal,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: mode = &gtk::DropDown::from_strings(&MODE_MATCHES.map(|(name, _)| name)) {
+                                            set_hexpand: true,
+                                        },
+                                        gtk::Button {
+                                            set_icon_name: "emblem-ok-symbolic",
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked[sender, mode, popover] => move |_| {
+                                                popover.hide();
+                                                sender.send(EditRuleInput::ClickedTag(index, custom_mode_tag(mode.selected() as usize)));
+                                            }
+                                        }
+                                    },
+                                    gtk::Label { set_margin_start: 10, set_label: "Custom content", set_xalign: 0. },
+                                    gtk::Box {
+                                        set_orientation: gtk::Orientation::Vertical,
+                                        set_margin_all: 10,
+                                        set_spacing: 10,
+                                        append: content_match = &gtk::DropDown::from_strings(&["Text", "Regular expression"]) {},
+                                        gtk::Box {
+                                            set_orientation: gtk::Orientation::Horizontal,
+                                            set_spacing: 10,
+                                            append: content = &gtk::Entry {
+                                                set_hexpand: true,
+                                                set_placeholder_text: Some("FATAL"),
+                                                connect_changed[content_confirm] => move |entry| {
+                                                    content_confirm.set_sensitive(!entry.buffer().text().is_empty());
+                                                }
+                                            },
+                                            append: content_confirm = &gtk::Button {
+                                                set_sensitive: false,
+                                                set_icon_name: "emblem-ok-symbolic",
+                                                set_css_classes: &["flat", "circular"],
+                                                connect_clicked[sender, content_match, content, content_ignore_case, content_binary, content_limit, popover] => move |_| {
+                                                    popover.hide();
+                                                    let mut search = ContentSearch::new(content.buffer().text(), content_match.selected() == 1, content_ignore_case.is_active());
+                                                    search.binary = content_binary.is_active();
+                                                    let limit = content_limit.buffer().text();
+                                                    if !limit.trim().is_empty() {
+                                                        match byte_unit::Byte::from_str(limit) {
+                                                            Ok(limit) => search.max_size = limit,
+                                                            Err(e) => {
+                                                                SENDER.send(AppMsg::Error("Wrong file size formatting".to_string(), e.to_string()));
+                                                                return;
+                                                            }
+                                                        }
+                                                    }
+                                                    match search.validate() {
+                                                        Ok(()) => sender.send(EditRuleInput::ClickedTag(index, search.into_tag())),
+                                                        Err(e) => SENDER.send(AppMsg::Error("Wrong regular expression".to_string(), e.to_string())),
+                                                    }
+                                                }
+                                            }
+                                        },
+                                        append: content_ignore_case = &gtk::CheckButton {
+                                            set_label: Some("Ignore letter case"),
+                                        },
+                                        append: content_binary = &gtk::CheckButton {
+                                            set_label: Some("Search binary files too"),
+                                        },
+                                        gtk::Box {
+                                            set_orientation: gtk::Orientation::Horizontal,
+                                            set_spacing: 10,
+                                            gtk::Label { set_label: "Skip files over" },
+                                            append: content_limit = &gtk::Entry {
+                                                set_hexpand: true,
+                                                set_placeholder_text: Some(&ContentSearch::default_max_size().get_appropriate_unit(true).to_string()),
+                                            },
+                                        },
                                     }
                                 },
                             },
@@ -497,7 +1016,8 @@ pub fn var_view(
                                 set_start_widget = Some(&gtk::Box) {
                                     set_orientation: gtk::Orientation::Vertical,
                                     set_spacing: 10,
-                                    gtk::Label { set_markup: "Use <b>Shift</b> to select multiple tags.", set_xalign: 0.  },
+                                    gtk::Label { set_markup: "Use <b>Shift</b> to require another tag as well.", set_xalign: 0.  },
+                                    gtk::Label { set_markup: "Use <b>Alt</b> to allow another tag instead of the last one.", set_xalign: 0. },
                                     gtk::Label { set_markup: "Use <b>Ctrl</b> to exclude a tag from the set.", set_xalign: 0. },
                                 },
                                 set_end_widget = Some(&gtk::Box) {
@@ -513,7 +1033,7 @@ pub fn var_view(
                             }
                         },
                         add_controller = &gtk::EventControllerKey {
-                            connect_key_pressed[tag_select_multiple, tag_negate] => move |_, key, _, _| {
+                            connect_key_pressed[tag_select_multiple, tag_negate, tag_group] => move |_, key, _, _| {
                                 if key == gtk::gdk::Key::Shift_L || key == gtk::gdk::Key::Shift_R {
                                     if let Ok(mut b) = tag_select_multiple.lock() {
                                         *b = true;
@@ -522,14 +1042,22 @@ pub fn var_view(
                                     if let Ok(mut b) = tag_negate.lock() {
                                         *b = true;
                                     }
+                                } else if key == gtk::gdk::Key::Alt_L || key == gtk::gdk::Key::Alt_R {
+                                    if let Ok(mut b) = tag_group.lock() {
+                                        *b = true;
+                                    }
                                 }
                                 gtk::Inhibit(false)
                             },
-                            connect_key_released[tag_select_multiple, tag_negate] => move |_, key, _, _| {
+                            connect_key_released[tag_select_multiple, tag_negate, tag_group] => move |_, key, _, _| {
                                 if key == gtk::gdk::Key::Shift_L || key == gtk::gdk::Key::Shift_R {
                                     if let Ok(mut b) = tag_select_multiple.lock() {
                                         *b = false;
                                     }
+                                } else if key == gtk::gdk::Key::Alt_L || key == gtk::gdk::Key::Alt_R {
+                                    if let Ok(mut b) = tag_group.lock() {
+                                        *b = false;
+                                    }
                                 } else if key == gtk::gdk::Key::KP_Space {
                                     if let Ok(mut b) = tag_negate.lock() {
                                         *b = false;
@@ -579,23 +1107,283 @@ pub fn var_view(
             }
             button
         })),
+        Var::Template(template) => bin.set_child(Some(&{
+            view! {
+                button = gtk::MenuButton {
+                    set_margin_top: 10,
+                    set_margin_bottom: 10,
+                    set_label: template.as_str(),
+                    add_css_class: "link",
+                    set_popover: popover = Some(&gtk::Popover) {
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 15,
+                            append: entry = &gtk::Entry {
+                                set_width_chars: 40,
+                                connect_changed[ok_button] => move |entry| {
+                                    let template = Template::new(entry.buffer().text());
+                                    match template.validate() {
+                                        Ok(_) => {
+                                            ok_button.set_sensitive(true);
+                                            entry.remove_css_class("error");
+                                            entry.set_tooltip_text(None);
+                                        }
+                                        Err(e) => {
+                                            ok_button.set_sensitive(false);
+                                            entry.add_css_class("error");
+                                            entry.set_tooltip_text(Some(&e.to_string()));
+                                        }
+                                    }
+                                },
+                                bind: |entry| {
+                                    entry.buffer().set_text(template.as_str());
+                                }
+                            },
+                            append: ok_button = &gtk::Button {
+                                set_icon_name: "emblem-ok-symbolic",
+                                add_css_class: "circular",
+                                connect_clicked[sender, entry, popover] => move |_| {
+                                    let template = Template::new(entry.buffer().text());
+                                    if template.validate().is_ok() {
+                                        popover.hide();
+                                        sender.send(EditRuleInput::ChangedTemplate(index, template));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            button
+        })),
+        Var::Command(command) => bin.set_child(Some(&{
+            view! {
+                button = gtk::MenuButton {
+                    set_margin_top: 10,
+                    set_margin_bottom: 10,
+                    set_label: &command.line(),
+                    add_css_class: "link",
+                    set_popover: popover = Some(&gtk::Popover) {
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_spacing: 10,
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_spacing: 15,
+                                append: entry = &gtk::Entry {
+                                    set_width_chars: 40,
+                                    set_placeholder_text: Some("convert {path} {dir}/{stem}.png"),
+                                    connect_changed[ok_button, shell, timeout] => move |entry| {
+                                        ok_button.set_sensitive(parse_command(entry, &shell, &timeout).is_ok());
+                                    },
+                                    bind: |entry| {
+                                        entry.buffer().set_text(&command.line());
+                                    }
+                                },
+                                append: ok_button = &gtk::Button {
+                                    set_icon_name: "emblem-ok-symbolic",
+                                    add_css_class: "circular",
+                                    connect_clicked[sender, entry, shell, timeout, popover] => move |_| {
+                                        match parse_command(&entry, &shell, &timeout) {
+                                            Ok(command) => {
+                                                popover.hide();
+                                                sender.send(EditRuleInput::ChangedCommand(index, command));
+                                            }
+                                            Err(e) => {
+                                                popover.hide();
+                                                SENDER.send(AppMsg::Error("Wrong command formatting".to_string(), e.to_string()));
+                                            }
+                                        }
+                                    }
+                                }
+                            },
+                            append: shell = &gtk::CheckButton {
+                                set_label: Some("Run through the shell"),
+                                set_active: command.shell(),
+                            },
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_spacing: 10,
+                                gtk::Label { set_label: "Timeout" },
+                                append: timeout = &gtk::Entry {
+                                    set_placeholder_text: Some("1m"),
+                                    bind: |entry| {
+                                        entry.buffer().set_text(&duration_string::DurationString::from(command.timeout()).to_string());
+                                    }
+                                },
+                            }
+                        }
+                    }
+                }
+            }
+            button
+        })),
+        Var::Choice {
+            key,
+            options,
+            selected,
+        } => bin.set_child(Some(&{
+            let key = *key;
+            view! {
+                dropdown = gtk::DropDown::from_strings(&options[..]) {
+                    set_margin_top: 10,
+                    set_margin_bottom: 10,
+                    set_selected: *selected as u32,
+                    connect_selected_notify[sender] => move |dropdown| {
+                        sender.send(EditRuleInput::ChangedChoice(index, key, dropdown.selected() as usize));
+                    }
+                }
+            }
+            dropdown
+        })),
+        Var::Walk(walk) => bin.set_child(Some(&{
+            view! {
+                button = gtk::MenuButton {
+                    set_margin_top: 10,
+                    set_margin_bottom: 10,
+                    set_label: &walk.summary(),
+                    add_css_class: "flat",
+                    set_popover: popover = Some(&gtk::Popover) {
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_spacing: 10,
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_spacing: 10,
+                                gtk::Label { set_label: "Depth", set_hexpand: true, set_xalign: 0. },
+                                append: depth = &gtk::SpinButton::with_range(0., 100., 1.) {
+                                    set_tooltip_text: Some("1 reads only the rule folder, 0 reads all of the subfolders"),
+                                    set_value: walk.depth.unwrap_or(0) as f64,
+                                },
+                            },
+                            append: include = &gtk::Entry {
+                                set_width_chars: 30,
+                                set_placeholder_text: Some("Only: */target, *.log"),
+                                bind: |entry| {
+                                    entry.buffer().set_text(&walk.include.join(", "));
+                                }
+                            },
+                            append: exclude = &gtk::Entry {
+                                set_placeholder_text: Some("Except: .git, node_modules"),
+                                bind: |entry| {
+                                    entry.buffer().set_text(&walk.exclude.join(", "));
+                                }
+                            },
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_spacing: 10,
+                                append: dirs = &gtk::CheckButton {
+                                    set_hexpand: true,
+                                    set_label: Some("Include folders themselves"),
+                                    set_active: walk.dirs,
+                                },
+                                gtk::Button {
+                                    set_icon_name: "emblem-ok-symbolic",
+                                    add_css_class: "circular",
+                                    connect_clicked[sender, depth, include, exclude, dirs, popover] => move |_| {
+                                        popover.hide();
+                                        match parse_walk(&depth, &include, &exclude, &dirs) {
+                                            Ok(walk) => sender.send(EditRuleInput::ChangedWalk(index, walk)),
+                                            Err(e) => SENDER.send(AppMsg::Error("Wrong pattern formatting".to_string(), e.to_string())),
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            button
+        })),
     }
     bin
 }
 
+fn parse_walk(
+    depth: &gtk::SpinButton,
+    include: &gtk::Entry,
+    exclude: &gtk::Entry,
+    dirs: &gtk::CheckButton,
+) -> anyhow::Result<Walk> {
+    let patterns = |entry: &gtk::Entry| {
+        entry
+            .buffer()
+            .text()
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+    let walk = Walk {
+        depth: match depth.value_as_int() {
+            0 => None,
+            depth => Some(depth as usize),
+        },
+        include: patterns(include),
+        exclude: patterns(exclude),
+        dirs: dirs.is_active(),
+    };
+    walk.validate()?;
+    Ok(walk)
+}
+
+fn parse_command(
+    entry: &gtk::Entry,
+    shell: &gtk::CheckButton,
+    timeout: &gtk::Entry,
+) -> anyhow::Result<CommandLine> {
+    let timeout = duration_string::DurationString::try_from(timeout.buffer().text())
+        .map_err(|e| anyhow::anyhow!(e))?;
+    CommandLine::parse(&entry.buffer().text(), shell.is_active(), timeout.into())
+}
+
 fn parse_path(s: &str) -> Option<PathBuf> {
-    if s.is_empty() {
+    // Paths may contain placeholders which are expanded for every file
+    if s.is_empty() || Template::new(s).validate().is_err() {
         return None;
     }
 
     let path = PathBuf::from(s);
-    if path.is_absolute() || s.starts_with('~') {
+    // Placeholders such as `{dir}` expand into absolute paths
+    if path.is_absolute() || s.starts_with('~') || s.starts_with('{') {
         Some(path)
     } else {
         None
     }
 }
 
+/// A column of the tag library with a button for each tag.
+fn column_view(
+    index: usize,
+    expr: &TagExpr,
+    column: &TagColumn,
+    sender: &Sender<EditRuleInput>,
+    popover: &gtk::Popover,
+) -> impl IsA<gtk::Widget> {
+    view! {
+        container = gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_spacing: 10,
+            set_width_request: 300,
+            gtk::Label {
+                set_markup: &format!("<b>{}</b>", gtk::glib::markup_escape_text(&column.name)),
+            },
+            gtk::FlowBox {
+                set_selection_mode: gtk::SelectionMode::None,
+                #[iterate]
+                insert[-1]: column
+                    .tags
+                    .iter()
+                    .map(|tag| tag_view(index, expr, tag, sender, popover))
+                    .collect::<Vec<_>>()
+                    .iter(),
+            },
+        }
+    }
+    container
+}
+
 fn tag_view(
     index: usize,
     expr: &TagExpr,
@@ -2,6 +2,7 @@ use std::{
     collections::HashMap,
     path::PathBuf,
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{channel, Sender},
         Arc, Mutex,
     },
@@ -9,7 +10,8 @@ use std::{
     time::Duration,
 };
 
-use crate::lib::SkippableResult;
+use crate::components::run_progress::RunProgressInput;
+use crate::lib::{Progress, SkippableResult};
 use crate::{lib::Rule, log::Log};
 
 struct StopMessage;
@@ -17,13 +19,19 @@ struct StopMessage;
 pub struct Executor {
     log: Arc<Mutex<Log>>,
     sender: Option<Sender<StopMessage>>,
+    /// Shared with the running thread to cancel long copies.
+    progress: Progress,
+    /// Receives the progress of long copies.
+    watcher: relm4::Sender<RunProgressInput>,
 }
 
 impl Executor {
-    pub fn new(log: &Arc<Mutex<Log>>) -> Self {
+    pub fn new(log: &Arc<Mutex<Log>>, watcher: &relm4::Sender<RunProgressInput>) -> Self {
         Executor {
             log: log.clone(),
             sender: None,
+            progress: Progress::default(),
+            watcher: watcher.clone(),
         }
     }
     pub fn restart(&mut self, rule_map: &HashMap<PathBuf, Vec<Rule>>) {
@@ -34,22 +42,38 @@ impl Executor {
         }
         let (sender, receiver) = channel();
         self.sender = Some(sender);
+        // The rules have changed, so the cancelled copies might succeed now
+        self.progress.forget_cancelled();
 
         let rule_map = rule_map.clone();
         let log = self.log.clone();
+        let progress = self.progress.clone();
+        let watcher = self.watcher.clone();
         thread::spawn(move || loop {
             if receiver.try_recv().is_ok() {
                 return;
             }
-            for (dir, rules) in rule_map.iter() {
+            progress.reset();
+            let reported = Arc::new(AtomicBool::new(false));
+            let run = log.lock().expect("unable to aquire mutex").next_run();
+            'run: for (dir, rules) in rule_map.iter() {
                 for rule in rules {
+                    let progress = {
+                        let (title, watcher, reported) =
+                            (rule.title().to_owned(), watcher.clone(), reported.clone());
+                        progress.with_handler(move |status| {
+                            reported.store(true, Ordering::SeqCst);
+                            watcher.send(RunProgressInput::Update(title.clone(), status.clone()));
+                        })
+                    };
                     for event in rule.events() {
-                        match event.execute(dir) {
+                        let history = log.lock().expect("unable to aquire mutex").history(event);
+                        match event.execute(dir, &history, &progress) {
                             Ok(results) => {
                                 let mut log = log.lock().expect("unable to aquire mutex");
                                 for result in results {
                                     match result {
-                                        SkippableResult::Ok(entry) => log.push(entry),
+                                        SkippableResult::Ok(entry) => log.push(entry.with_run(run)),
                                         SkippableResult::Err(e) => eprintln!("An error has occured while trying to execute the event on one of the items: {e}"),
                                         SkippableResult::Skipped => {}
                                     }
@@ -61,13 +85,24 @@ impl Executor {
                                 );
                             }
                         }
+                        if progress.is_cancelled() {
+                            break 'run;
+                        }
                     }
                 }
             }
+            if reported.load(Ordering::SeqCst) {
+                watcher.send(RunProgressInput::Finish);
+            }
             std::thread::sleep(Duration::from_secs(15));
         });
     }
 
+    /// Stop the copy that is running and skip the rest of the current run.
+    pub fn cancel(&self) {
+        self.progress.cancel();
+    }
+
     pub fn stop(&mut self) {
         if let Some(sender) = self.sender.take() {
             sender
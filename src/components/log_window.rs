@@ -5,13 +5,14 @@ use std::{
 };
 
 use adw::prelude::BinExt;
-use gtk::prelude::{BoxExt, ButtonExt, GtkWindowExt, IsA, OrientableExt, WidgetExt};
+use gtk::prelude::{BoxExt, ButtonExt, GtkWindowExt, IsA, OrientableExt, PopoverExt, WidgetExt};
 use relm4::{
     adw, gtk, view, ComponentParts, ComponentSender, RelmRemoveAllExt, SimpleComponent, WidgetPlus,
 };
 
 use crate::{lib::Event, log::Log};
 use crate::{lib::Var, log::LogEntry};
+use crate::{AppMsg, SENDER};
 
 #[derive(Debug)]
 pub struct LogWindow {
@@ -20,6 +21,8 @@ pub struct LogWindow {
 
 pub enum LogWindowInput {
     Refresh,
+    Undo(usize),
+    UndoRun(u64),
 }
 
 #[derive(Debug)]
@@ -67,8 +70,9 @@ impl SimpleComponent for LogWindow {
                                 .expect("unable to aquire mutex")
                                 .entries()
                                 .iter()
+                                .enumerate()
                                 .rev()
-                                .map(entry_view)
+                                .map(|(index, entry)| entry_view(index, entry, &sender))
                                 .collect::<Vec<_>>()
                                 .iter(),
                         }
@@ -89,20 +93,81 @@ impl SimpleComponent for LogWindow {
         widgets.root.present();
         ComponentParts { model, widgets }
     }
+
+    fn update(&mut self, message: Self::Input, _sender: &ComponentSender<Self>) {
+        let mut log = self.log.lock().expect("unable to aquire mutex");
+        let result = match message {
+            LogWindowInput::Refresh => Ok(()),
+            LogWindowInput::Undo(index) => log.undo(index),
+            LogWindowInput::UndoRun(run) => log.undo_run(run),
+        };
+        if let Err(e) = result {
+            SENDER.send(AppMsg::Error(
+                "Unable to undo the action".to_string(),
+                e.to_string(),
+            ));
+        }
+    }
 }
 
-fn entry_view(entry: &LogEntry) -> impl IsA<gtk::Widget> {
+fn entry_view(
+    index: usize,
+    entry: &LogEntry,
+    sender: &ComponentSender<LogWindow>,
+) -> impl IsA<gtk::Widget> {
     let time = entry.time();
+    let run = entry.run();
     view! {
         row = gtk::ListBoxRow {
             gtk::CenterBox {
                 set_margin_all: 10,
-                set_start_widget: Some(&event_view(entry.event(), entry.file())),
+                set_start_widget: Some(&event_view(entry.event(), entry.file(), entry.result())),
                 set_end_widget = Some(&gtk::Box) {
                     set_margin_end: 15,
                     set_orientation: gtk::Orientation::Horizontal,
                     set_spacing: 10,
+                    append?: &details_view(entry.details()),
                     append?: &source_view(entry.source()),
+                    gtk::Label {
+                        set_visible: entry.is_undone(),
+                        set_label: "Undone",
+                        add_css_class: "opaque",
+                    },
+                    gtk::Label {
+                        set_visible: entry.is_failed(),
+                        set_label: "Failed",
+                        add_css_class: "error",
+                    },
+                    gtk::MenuButton {
+                        set_visible: entry.can_undo(),
+                        set_valign: gtk::Align::Center,
+                        set_icon_name: "edit-undo-symbolic",
+                        set_css_classes: &["flat", "circular"],
+                        set_popover: popover = Some(&gtk::Popover) {
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                gtk::Button {
+                                    set_label: "Undo this action",
+                                    add_css_class: "flat",
+                                    connect_clicked[sender, popover] => move |_| {
+                                        popover.hide();
+                                        sender.input(LogWindowInput::Undo(index));
+                                    }
+                                },
+                                gtk::Button {
+                                    set_visible: run.is_some(),
+                                    set_label: "Undo the whole run",
+                                    add_css_class: "flat",
+                                    connect_clicked[sender, popover] => move |_| {
+                                        popover.hide();
+                                        if let Some(run) = run {
+                                            sender.input(LogWindowInput::UndoRun(run));
+                                        }
+                                    }
+                                },
+                            }
+                        }
+                    },
                     gtk::Box {
                         set_orientation: gtk::Orientation::Vertical,
                         set_spacing: 2,
@@ -121,6 +186,20 @@ fn entry_view(entry: &LogEntry) -> impl IsA<gtk::Widget> {
     row
 }
 
+fn details_view(details: Option<&str>) -> Option<impl IsA<gtk::Widget>> {
+    details
+        .filter(|details| !details.is_empty())
+        .map(|details| {
+            view! {
+                image = gtk::Image {
+                    set_icon_name: Some("dialog-information-symbolic"),
+                    set_tooltip_text: Some(details),
+                }
+            }
+            image
+        })
+}
+
 fn source_view(source: Option<&Path>) -> Option<impl IsA<gtk::Widget>> {
     if let Some(source) = source {
         let source_str = source.to_string_lossy();
@@ -147,7 +226,7 @@ fn source_view(source: Option<&Path>) -> Option<impl IsA<gtk::Widget>> {
     }
 }
 
-fn var_view(var: &Var, path: &Path) -> impl IsA<gtk::Widget> {
+fn var_view(var: &Var, path: &Path, result: Option<&Path>) -> impl IsA<gtk::Widget> {
     let bin = adw::Bin::new();
     match var {
         Var::String { label, css_class } => bin.set_child(Some(
@@ -171,15 +250,46 @@ fn var_view(var: &Var, path: &Path) -> impl IsA<gtk::Widget> {
                 .css_classes(vec!["link".into()])
                 .build(),
         )),
+        // Show the name that the template has produced rather than the template itself
+        Var::Template(template) => bin.set_child(Some(
+            &gtk::Label::builder()
+                .label(
+                    &result
+                        .and_then(|result| result.file_name())
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| template.to_string()),
+                )
+                .max_width_chars(15)
+                .lines(1)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build(),
+        )),
+        Var::Command(command) => bin.set_child(Some(
+            &gtk::Label::builder()
+                .label(command.program())
+                .tooltip_text(&command.line())
+                .css_classes(vec!["monospace".into()])
+                .build(),
+        )),
+        Var::Choice {
+            options, selected, ..
+        } => bin.set_child(Some(
+            &gtk::Label::builder()
+                .label(options[*selected])
+                .css_classes(vec!["opaque".into()])
+                .build(),
+        )),
+        // The file is already shown, so it does not matter how it was found
+        Var::Walk(_) => {}
     }
     bin
 }
 
-fn event_view(event: &Event, path: &Path) -> impl IsA<gtk::Widget> {
+fn event_view(event: &Event, path: &Path, result: Option<&Path>) -> impl IsA<gtk::Widget> {
     let vars = event
         .vars()
         .iter()
-        .map(|event| var_view(event, path))
+        .map(|event| var_view(event, path, result))
         .collect::<Vec<_>>();
     view! {
         container = gtk::Box {
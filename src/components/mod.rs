@@ -2,4 +2,7 @@ pub mod edit_rule_window;
 pub mod error_dialog;
 pub mod executor;
 pub mod log_window;
+pub mod plan_window;
 pub mod property_window;
+pub mod run_progress;
+pub mod tag_library_window;
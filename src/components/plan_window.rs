@@ -0,0 +1,122 @@
+//! A window that shows what a rule would do without doing it.
+use relm4::{
+    adw,
+    gtk::{
+        self,
+        prelude::{BoxExt, GtkWindowExt, IsA, OrientableExt, WidgetExt},
+    },
+    view, ComponentParts, ComponentSender, SimpleComponent, WidgetPlus,
+};
+
+use crate::lib::{Action, Planned};
+
+pub struct PlanWindow;
+
+#[relm4::component(pub)]
+impl SimpleComponent for PlanWindow {
+    type Widgets = PlanWindowWidgets;
+
+    type InitParams = (String, Vec<Action>);
+
+    type Input = ();
+    type Output = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 780,
+            set_default_height: 500,
+            set_title: Some(&format!("Simulation of \"{title}\"")),
+            gtk::ScrolledWindow {
+                set_hscrollbar_policy: gtk::PolicyType::Never,
+                adw::Clamp {
+                    set_maximum_size: 800,
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_margin_all: 15,
+                        set_spacing: 5,
+                        gtk::Label {
+                            set_visible: actions.is_empty(),
+                            add_css_class: "opaque",
+                            set_label: "The rule does not apply to any of the files right now.",
+                        },
+                        gtk::ListBox {
+                            set_visible: !actions.is_empty(),
+                            add_css_class: "boxed-list",
+                            set_hexpand: true,
+                            #[iterate]
+                            append: actions.iter().map(action_view).collect::<Vec<_>>().iter(),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(
+        (title, actions): Self::InitParams,
+        root: &Self::Root,
+        _sender: &ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = PlanWindow;
+        let widgets = view_output!();
+        root.present();
+        ComponentParts { model, widgets }
+    }
+}
+
+fn action_view(action: &Action) -> impl IsA<gtk::Widget> {
+    let file = action.file.to_string_lossy();
+    let dest = action.dest.as_ref().map(|dest| dest.to_string_lossy());
+    view! {
+        row = gtk::ListBoxRow {
+            gtk::CenterBox {
+                set_margin_all: 10,
+                set_start_widget = Some(&gtk::Box) {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 10,
+                    gtk::Image { set_icon_name: Some(&action.icon_name) },
+                    gtk::Label { set_label: &action.event, add_css_class: "bold" },
+                    gtk::Label {
+                        set_label: &file,
+                        set_tooltip_text: Some(&file),
+                        set_max_width_chars: 25,
+                        set_ellipsize: gtk::pango::EllipsizeMode::Start,
+                    },
+                    gtk::Label {
+                        set_visible: dest.is_some(),
+                        set_label: "→",
+                        add_css_class: "opaque",
+                    },
+                    gtk::Label {
+                        set_visible: dest.is_some(),
+                        set_label: dest.as_deref().unwrap_or_default(),
+                        set_tooltip_text: dest.as_deref(),
+                        set_max_width_chars: 25,
+                        set_ellipsize: gtk::pango::EllipsizeMode::Start,
+                    },
+                },
+                set_end_widget = Some(&gtk::Box) {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 10,
+                    gtk::Image {
+                        set_visible: action.details.is_some(),
+                        set_icon_name: Some("dialog-information-symbolic"),
+                        set_tooltip_text: action.details.as_deref(),
+                    },
+                    gtk::Label {
+                        set_label: action.outcome.label(),
+                        set_tooltip_text: action.outcome.reason(),
+                        add_css_class: match action.outcome {
+                            Planned::Apply => "success",
+                            Planned::Overwrite => "warning",
+                            Planned::Skip(_) => "opaque",
+                            Planned::Fail(_) => "error",
+                        },
+                    },
+                }
+            }
+        }
+    }
+    row
+}
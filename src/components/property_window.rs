@@ -7,8 +7,9 @@ use relm4::{
     view, ComponentParts, ComponentSender, SimpleComponent, WidgetPlus,
 };
 
-use crate::all_tags;
-use crate::lib::Item;
+use std::sync::{Arc, Mutex};
+
+use crate::lib::{Item, TagLibrary};
 
 pub struct PropertyWindow;
 
@@ -16,7 +17,7 @@ pub struct PropertyWindow;
 impl SimpleComponent for PropertyWindow {
     type Widgets = PropertyWindowWidgets;
 
-    type InitParams = Item;
+    type InitParams = (Item, Arc<Mutex<TagLibrary>>);
 
     type Input = ();
     type Output = ();
@@ -59,6 +60,15 @@ impl SimpleComponent for PropertyWindow {
                             .unwrap_or_else(|_| String::from("(unknown)"))
                     }
                 },
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+                    gtk::Label { set_label: "Type:" },
+                    gtk::Label {
+                        set_selectable: true,
+                        set_label: &item.mime().unwrap_or_else(|_| String::from("(unknown)")),
+                    }
+                },
                 gtk::Separator {},
                 gtk::Label { set_label: "Tags" },
                 gtk::Box {
@@ -70,7 +80,7 @@ impl SimpleComponent for PropertyWindow {
                         set_selection_mode: gtk::SelectionMode::None,
                         #[iterate]
                         insert[-1]:
-                            all_tags()
+                            tags
                             .into_iter()
                             .filter(|tag| if let Ok(b) = tag.is(&mut item) { b } else { false }).map(|tag| {
                                 view! {
@@ -94,10 +104,16 @@ impl SimpleComponent for PropertyWindow {
     }
 
     fn init(
-        mut item: Self::InitParams,
+        (mut item, library): Self::InitParams,
         root: &Self::Root,
         _sender: &ComponentSender<Self>,
     ) -> ComponentParts<Self> {
+        let tags = library
+            .lock()
+            .expect("unable to aquire mutex")
+            .tags()
+            .cloned()
+            .collect::<Vec<_>>();
         let model = PropertyWindow;
         let widgets = view_output!();
         root.present();
@@ -0,0 +1,143 @@
+//! A bar at the bottom of the main window that shows the copy the rules are busy with.
+use std::time::Duration;
+
+use byte_unit::Byte;
+use gtk::prelude::{BoxExt, ButtonExt, OrientableExt, WidgetExt};
+use relm4::{gtk, ComponentParts, ComponentSender, SimpleComponent, WidgetPlus};
+
+use crate::lib::Status;
+
+pub struct RunProgress {
+    /// Title of the rule and the state of its copy.
+    status: Option<(String, Status)>,
+}
+
+pub enum RunProgressInput {
+    Update(String, Status),
+    Finish,
+}
+
+#[derive(Debug)]
+pub enum RunProgressOutput {
+    Cancel,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for RunProgress {
+    type Widgets = RunProgressWidgets;
+
+    type InitParams = ();
+
+    type Input = RunProgressInput;
+    type Output = RunProgressOutput;
+
+    view! {
+        gtk::Revealer {
+            #[watch]
+            set_reveal_child: model.status.is_some(),
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_margin_all: 10,
+                set_spacing: 10,
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_hexpand: true,
+                    set_spacing: 5,
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_spacing: 10,
+                        gtk::Label {
+                            add_css_class: "bold",
+                            #[watch]
+                            set_label: model.rule(),
+                        },
+                        gtk::Label {
+                            set_hexpand: true,
+                            set_xalign: 0.0,
+                            set_ellipsize: gtk::pango::EllipsizeMode::Start,
+                            #[watch]
+                            set_label: &model.file(),
+                            #[watch]
+                            set_tooltip_text: Some(&model.file()),
+                        },
+                        gtk::Label {
+                            add_css_class: "opaque",
+                            #[watch]
+                            set_label: &model.details(),
+                        },
+                    },
+                    gtk::ProgressBar {
+                        #[watch]
+                        set_fraction: model.status.as_ref().map_or(0.0, |(_, status)| status.fraction()),
+                    },
+                },
+                gtk::Button {
+                    set_valign: gtk::Align::Center,
+                    set_icon_name: "process-stop-symbolic",
+                    set_tooltip_text: Some("Cancel the copy and the rest of the run"),
+                    set_css_classes: &["flat", "circular"],
+                    connect_clicked[sender] => move |_| {
+                        sender.output(RunProgressOutput::Cancel);
+                    }
+                },
+            }
+        }
+    }
+
+    fn init(
+        _params: Self::InitParams,
+        root: &Self::Root,
+        sender: &ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = RunProgress { status: None };
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, _sender: &ComponentSender<Self>) {
+        self.status = match message {
+            RunProgressInput::Update(rule, status) => Some((rule, status)),
+            RunProgressInput::Finish => None,
+        };
+    }
+}
+
+impl RunProgress {
+    fn rule(&self) -> &str {
+        self.status.as_ref().map_or("", |(rule, _)| rule)
+    }
+    fn file(&self) -> String {
+        self.status
+            .as_ref()
+            .map(|(_, status)| status.file.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+    fn details(&self) -> String {
+        let status = match &self.status {
+            Some((_, status)) => status,
+            None => return String::new(),
+        };
+        let size = |bytes: u64| Byte::from_bytes(bytes.into()).get_appropriate_unit(true);
+        let mut details = format!(
+            "{} of {}",
+            size(status.copied_bytes),
+            size(status.total_bytes)
+        );
+        if status.total_files > 1 {
+            details += &format!(", {} of {} files", status.copied_files, status.total_files);
+        }
+        if let Some(eta) = status.eta() {
+            details += &format!(", {} left", format_duration(eta));
+        }
+        details
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{secs} s"),
+        60..=3599 => format!("{} min", secs / 60),
+        _ => format!("{} h {} min", secs / 3600, secs % 3600 / 60),
+    }
+}
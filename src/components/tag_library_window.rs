@@ -0,0 +1,323 @@
+//! A window for creating, editing and deleting the tags of the library.
+use std::sync::{Arc, Mutex};
+
+use gtk::prelude::{
+    BoxExt, ButtonExt, EditableExt, EntryBufferExtManual, EntryExt, GtkWindowExt, OrientableExt,
+    WidgetExt,
+};
+use relm4::{
+    adw, gtk, gtk::prelude::IsA, view, ComponentParts, ComponentSender, RelmRemoveAllExt,
+    SimpleComponent, WidgetPlus,
+};
+
+use crate::lib::{library_tag, parse_condition, Tag, TagColumn, TagLibrary};
+use crate::{AppMsg, SENDER};
+
+#[derive(Debug)]
+pub struct TagLibraryWindow {
+    tags: Arc<Mutex<TagLibrary>>,
+}
+
+/// The fields of the form of a tag.
+#[derive(Debug)]
+pub struct TagForm {
+    name: String,
+    desc: String,
+    condition: String,
+    column: usize,
+}
+
+pub enum TagLibraryInput {
+    AddColumn(String),
+    RemoveColumn(usize),
+    /// Add a tag to the column of the form.
+    Add(TagForm),
+    /// Replace the tag at the index of the column.
+    Edit(usize, usize, TagForm),
+    Remove(usize, usize),
+}
+
+#[derive(Debug)]
+pub enum TagLibraryOutput {
+    /// The tags have changed, so the views that show them are out of date.
+    Changed,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for TagLibraryWindow {
+    type Widgets = TagLibraryWindowWidgets;
+
+    type InitParams = Arc<Mutex<TagLibrary>>;
+
+    type Input = TagLibraryInput;
+    type Output = TagLibraryOutput;
+
+    view! {
+        root = gtk::Window {
+            set_default_width: 780,
+            set_default_height: 500,
+            set_title: Some("Tags"),
+            set_modal: true,
+            set_titlebar = Some(&gtk::HeaderBar) {
+                pack_end = &gtk::MenuButton {
+                    set_icon_name: "list-add-symbolic",
+                    set_tooltip_text: Some("Add a column"),
+                    set_popover: column_popover = Some(&gtk::Popover) {
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 10,
+                            append: column_name = &gtk::Entry {
+                                set_placeholder_text: Some("Name of the column"),
+                                connect_activate[sender, column_popover] => move |entry| {
+                                    column_popover.hide();
+                                    sender.input(TagLibraryInput::AddColumn(entry.buffer().text()));
+                                    entry.buffer().set_text("");
+                                }
+                            },
+                            gtk::Button {
+                                set_icon_name: "emblem-ok-symbolic",
+                                set_css_classes: &["flat", "circular"],
+                                connect_clicked[sender, column_popover, column_name] => move |_| {
+                                    column_popover.hide();
+                                    sender.input(TagLibraryInput::AddColumn(column_name.buffer().text()));
+                                    column_name.buffer().set_text("");
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            gtk::ScrolledWindow {
+                set_hscrollbar_policy: gtk::PolicyType::Never,
+                adw::Clamp {
+                    set_maximum_size: 700,
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_margin_all: 15,
+                        set_spacing: 10,
+                        #[watch]
+                        remove_all: (),
+                        #[watch]
+                        #[iterate]
+                        append: {
+                            let tags = model.tags.lock().expect("unable to aquire mutex");
+                            let names = tags
+                                .columns()
+                                .iter()
+                                .map(|column| column.name.as_str())
+                                .collect::<Vec<_>>();
+                            tags.columns()
+                                .iter()
+                                .enumerate()
+                                .map(|(index, column)| column_view(index, column, &names, &sender))
+                                .collect::<Vec<_>>()
+                        }
+                        .iter(),
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(
+        tags: Self::InitParams,
+        root: &Self::Root,
+        sender: &ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = TagLibraryWindow { tags };
+        let widgets = view_output!();
+        widgets.root.present();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, sender: &ComponentSender<Self>) {
+        let mut tags = self.tags.lock().expect("unable to aquire mutex");
+        let result = match message {
+            TagLibraryInput::AddColumn(name) => tags.add_column(&name),
+            TagLibraryInput::RemoveColumn(column) => {
+                tags.remove_column(column);
+                Ok(())
+            }
+            TagLibraryInput::Add(form) => library_tag(&form.name, &form.desc, &form.condition)
+                .and_then(|tag| tags.add(form.column, tag)),
+            TagLibraryInput::Edit(column, index, form) => {
+                library_tag(&form.name, &form.desc, &form.condition)
+                    .and_then(|tag| tags.replace(column, index, form.column, tag))
+            }
+            TagLibraryInput::Remove(column, index) => {
+                tags.remove(column, index);
+                Ok(())
+            }
+        };
+        match result {
+            Ok(()) => sender.output(TagLibraryOutput::Changed),
+            Err(e) => SENDER.send(AppMsg::Error(
+                "Unable to change the tags".to_string(),
+                e.to_string(),
+            )),
+        }
+    }
+}
+
+fn column_view(
+    index: usize,
+    column: &TagColumn,
+    names: &[&str],
+    sender: &ComponentSender<TagLibraryWindow>,
+) -> impl IsA<gtk::Widget> {
+    let rows = column
+        .tags
+        .iter()
+        .enumerate()
+        .map(|(tag_index, tag)| tag_row(index, tag_index, tag, names, sender))
+        .collect::<Vec<_>>();
+    let add_form = tag_form(None, index, names, sender, move |form| {
+        TagLibraryInput::Add(form)
+    });
+    view! {
+        container = gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_spacing: 5,
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 10,
+                gtk::Label {
+                    set_hexpand: true,
+                    set_xalign: 0.,
+                    set_markup: &format!("<b>{}</b>", gtk::glib::markup_escape_text(&column.name)),
+                },
+                gtk::MenuButton {
+                    set_icon_name: "list-add-symbolic",
+                    set_css_classes: &["flat", "circular"],
+                    set_tooltip_text: Some("Add a tag"),
+                    set_popover: Some(&add_form),
+                },
+                gtk::Button {
+                    set_icon_name: "user-trash-symbolic",
+                    set_css_classes: &["flat", "circular"],
+                    set_tooltip_text: Some("Delete the column with its tags"),
+                    connect_clicked[sender] => move |_| {
+                        sender.input(TagLibraryInput::RemoveColumn(index));
+                    }
+                },
+            },
+            gtk::ListBox {
+                add_css_class: "boxed-list",
+                set_hexpand: true,
+                #[iterate]
+                append: rows.iter(),
+            }
+        }
+    }
+    container
+}
+
+fn tag_row(
+    column: usize,
+    index: usize,
+    tag: &Tag,
+    names: &[&str],
+    sender: &ComponentSender<TagLibraryWindow>,
+) -> impl IsA<gtk::Widget> {
+    let edit_form = tag_form(Some(tag), column, names, sender, move |form| {
+        TagLibraryInput::Edit(column, index, form)
+    });
+    view! {
+        row = gtk::ListBoxRow {
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_margin_all: 10,
+                set_spacing: 10,
+                gtk::Label {
+                    set_label: tag.name(),
+                    set_tooltip_text: Some(tag.desc()),
+                    add_css_class: "tag",
+                },
+                gtk::Label {
+                    set_hexpand: true,
+                    set_xalign: 0.,
+                    set_ellipsize: gtk::pango::EllipsizeMode::End,
+                    set_label: &tag.basis.to_query(),
+                    set_css_classes: &["monospace", "opaque"],
+                },
+                gtk::MenuButton {
+                    set_icon_name: "document-edit-symbolic",
+                    set_css_classes: &["flat", "circular"],
+                    set_popover: Some(&edit_form),
+                },
+                gtk::Button {
+                    set_icon_name: "list-remove-symbolic",
+                    set_css_classes: &["flat", "circular"],
+                    connect_clicked[sender] => move |_| {
+                        sender.input(TagLibraryInput::Remove(column, index));
+                    }
+                },
+            }
+        }
+    }
+    row
+}
+
+/// A popover with the fields of a tag, filled with `tag` if there is one.
+///
+/// The condition is a query of a single condition, such as `ext:pdf`.
+fn tag_form(
+    tag: Option<&Tag>,
+    column: usize,
+    names: &[&str],
+    sender: &ComponentSender<TagLibraryWindow>,
+    message: impl Fn(TagForm) -> TagLibraryInput + 'static,
+) -> gtk::Popover {
+    view! {
+        popover = gtk::Popover {
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_width_request: 400,
+                set_spacing: 10,
+                append: name = &gtk::Entry {
+                    set_placeholder_text: Some("Name, such as 🖥️ Wallpaper"),
+                    set_text: tag.map_or("", |tag| tag.name()),
+                },
+                append: desc = &gtk::Entry {
+                    set_placeholder_text: Some("Description"),
+                    set_text: tag.map_or("", |tag| tag.desc()),
+                },
+                append: condition = &gtk::Entry {
+                    add_css_class: "monospace",
+                    set_placeholder_text: Some("ratio:16/9"),
+                    set_text: &tag.map(|tag| tag.basis.to_query()).unwrap_or_default(),
+                    connect_changed => move |entry| {
+                        if parse_condition(&entry.buffer().text()).is_ok() {
+                            entry.remove_css_class("error");
+                        } else {
+                            entry.add_css_class("error");
+                        }
+                    },
+                },
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 10,
+                    gtk::Label { set_label: "Column" },
+                    append: column_choice = &gtk::DropDown::from_strings(names) {
+                        set_hexpand: true,
+                        set_selected: column as u32,
+                    },
+                    gtk::Button {
+                        set_icon_name: "emblem-ok-symbolic",
+                        set_css_classes: &["flat", "circular"],
+                        connect_clicked[sender, popover, name, desc, condition, column_choice] => move |_| {
+                            popover.hide();
+                            sender.input(message(TagForm {
+                                name: name.buffer().text(),
+                                desc: desc.buffer().text(),
+                                condition: condition.buffer().text(),
+                                column: column_choice.selected() as usize,
+                            }));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    popover
+}
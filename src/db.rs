@@ -1,6 +1,9 @@
 use anyhow::Context;
 
-use crate::{lib::Rule, log::Log};
+use crate::{
+    lib::{Rule, TagLibrary},
+    log::Log,
+};
 use std::{
     collections::HashMap,
     path::PathBuf,
@@ -11,11 +14,13 @@ use std::{
 pub struct Database {
     rules: HashMap<PathBuf, Vec<Rule>>,
     log: Arc<Mutex<Log>>,
+    tags: Arc<Mutex<TagLibrary>>,
 }
 
 const BASE_DIR_FILENAME: &str = "course_oop";
 const RULES_FILENAME: &str = "rules.json";
 const LOG_FILENAME: &str = "log.json";
+const TAGS_FILENAME: &str = "tags.json";
 
 impl Database {
     pub fn rules(&self) -> &HashMap<PathBuf, Vec<Rule>> {
@@ -27,6 +32,9 @@ impl Database {
     pub fn log(&self) -> &Arc<Mutex<Log>> {
         &self.log
     }
+    pub fn tags(&self) -> &Arc<Mutex<TagLibrary>> {
+        &self.tags
+    }
     pub fn load() -> anyhow::Result<Self> {
         let base_dir = dirs::config_dir()
             .with_context(|| "Unable to find application config directory")?
@@ -38,6 +46,7 @@ impl Database {
 
         let rules_path = base_dir.join(RULES_FILENAME);
         let log_path = base_dir.join(LOG_FILENAME);
+        let tags_path = base_dir.join(TAGS_FILENAME);
 
         let rules = if rules_path.exists() {
             let rule_bytes = std::fs::read(&rules_path)?;
@@ -51,8 +60,14 @@ impl Database {
         } else {
             Arc::new(Mutex::new(Log::new()))
         };
+        let tags = if tags_path.exists() {
+            let tags_bytes = std::fs::read(&tags_path)?;
+            serde_json::from_slice(&tags_bytes)?
+        } else {
+            Arc::new(Mutex::new(TagLibrary::default()))
+        };
 
-        Ok(Database { rules, log })
+        Ok(Database { rules, log, tags })
     }
 
     pub fn save(&self) -> anyhow::Result<()> {
@@ -74,10 +89,17 @@ impl Database {
             std::fs::File::create(&log_path)?;
         }
 
+        let tags_path = base_dir.join(TAGS_FILENAME);
+        if !tags_path.exists() {
+            std::fs::File::create(&tags_path)?;
+        }
+
         let rules_bits = serde_json::to_vec(&self.rules)?;
         let log_bits = serde_json::to_vec(&self.log)?;
+        let tags_bits = serde_json::to_vec(&self.tags)?;
         std::fs::write(rules_path, rules_bits)?;
         std::fs::write(log_path, log_bits)?;
+        std::fs::write(tags_path, tags_bits)?;
 
         Ok(())
     }
@@ -87,6 +109,7 @@ impl Default for Database {
     fn default() -> Self {
         let rules = HashMap::new();
         let log = Arc::new(Mutex::new(Log::default()));
-        Database { rules, log }
+        let tags = Arc::new(Mutex::new(TagLibrary::default()));
+        Database { rules, log, tags }
     }
 }
@@ -1,4 +1,4 @@
-use crate::lib::{FileType, Item};
+use crate::lib::{FileType, Item, Walk};
 use std::{
     cmp::Ordering,
     path::{Path, PathBuf},
@@ -118,9 +118,11 @@ impl NavigationHistory {
 }
 
 pub fn read_path(path: impl AsRef<Path>) -> anyhow::Result<Vec<Item>> {
+    let path = path.as_ref();
     let mut items = std::fs::read_dir(path)?
         .filter_map(|res| res.ok())
         .filter_map(|entry| Item::new(entry.path()).ok())
+        .map(|item| item.with_root(path))
         .collect::<Vec<_>>();
 
     // Order items by name, folders first
@@ -133,3 +135,40 @@ pub fn read_path(path: impl AsRef<Path>) -> anyhow::Result<Vec<Item>> {
 
     Ok(items)
 }
+
+/// Read the folder and its subfolders according to the walk settings.
+///
+/// Items are listed in the same order as `read_path` lists them,
+/// with the content of every folder following the folder itself.
+pub fn walk_path(path: impl AsRef<Path>, walk: &Walk) -> anyhow::Result<Vec<Item>> {
+    let root = path.as_ref();
+    let mut items = Vec::new();
+    walk_dir(root, root, 1, walk, &mut items)?;
+    Ok(items)
+}
+
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    level: usize,
+    walk: &Walk,
+    items: &mut Vec<Item>,
+) -> anyhow::Result<()> {
+    for item in read_path(dir)? {
+        let item = item.with_root(root);
+        let relative = item.path().strip_prefix(root)?.to_owned();
+        if walk.excludes(&relative) {
+            continue;
+        }
+        let is_dir = item.file_type() == &FileType::Dir;
+        let path = item.path().to_owned();
+        if walk.accepts(&relative, is_dir) {
+            items.push(item);
+        }
+        if is_dir && walk.enters(level + 1) {
+            // Unreadable subfolders are skipped, just like unreadable files
+            let _ = walk_dir(root, &path, level + 1, walk, items);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,444 @@
+//! Creating and unpacking archives.
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+use xz2::{read::XzDecoder, write::XzEncoder};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Compression level used for `.tar.xz` archives.
+const XZ_LEVEL: u32 = 6;
+
+/// Bits of a Unix file mode that describe the type of the file.
+const S_IFMT: u32 = 0o170000;
+/// File type of symbolic links.
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+}
+
+/// Recognized extensions, longer ones first.
+const EXTENSIONS: [(&str, ArchiveFormat); 6] = [
+    ("tar.gz", ArchiveFormat::TarGz),
+    ("tar.xz", ArchiveFormat::TarXz),
+    ("tgz", ArchiveFormat::TarGz),
+    ("txz", ArchiveFormat::TarXz),
+    ("tar", ArchiveFormat::Tar),
+    ("zip", ArchiveFormat::Zip),
+];
+
+impl ArchiveFormat {
+    pub const ALL: [ArchiveFormat; 4] = [
+        ArchiveFormat::Zip,
+        ArchiveFormat::Tar,
+        ArchiveFormat::TarGz,
+        ArchiveFormat::TarXz,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarXz => "tar.xz",
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => ".zip",
+            ArchiveFormat::Tar => ".tar",
+            ArchiveFormat::TarGz => ".tar.gz",
+            ArchiveFormat::TarXz => ".tar.xz",
+        }
+    }
+    /// Recognize the format by the extension of the file.
+    ///
+    /// Returns the format and the file name without the extension.
+    pub fn from_path(path: &Path) -> Option<(ArchiveFormat, String)> {
+        let name = path.file_name()?.to_string_lossy().into_owned();
+        EXTENSIONS.iter().find_map(|(ext, format)| {
+            // Compared without lowercasing the name, which can change its length in bytes
+            let stem_len = name.len().checked_sub(ext.len() + 1)?;
+            if !name.is_char_boundary(stem_len) {
+                return None;
+            }
+            let (stem, suffix) = name.split_at(stem_len);
+            (!stem.is_empty() && suffix.starts_with('.') && suffix[1..].eq_ignore_ascii_case(ext))
+                .then(|| (*format, stem.to_owned()))
+        })
+    }
+}
+
+/// What to do with the original file after it was archived.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Original {
+    Keep,
+    Delete,
+    Trash,
+}
+
+impl Original {
+    pub const ALL: [Original; 3] = [Original::Keep, Original::Delete, Original::Trash];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Original::Keep => "and keep the original",
+            Original::Delete => "and delete the original",
+            Original::Trash => "and trash the original",
+        }
+    }
+}
+
+/// Information about a newly created archive.
+#[derive(Debug)]
+pub struct Compressed {
+    pub archive: PathBuf,
+    /// Total size of the archived files.
+    pub original_size: u64,
+    /// Size of the archive itself.
+    pub archive_size: u64,
+}
+
+impl Compressed {
+    /// Size of the archive relative to the size of the archived files.
+    pub fn ratio(&self) -> f64 {
+        if self.original_size == 0 {
+            1.
+        } else {
+            self.archive_size as f64 / self.original_size as f64
+        }
+    }
+}
+
+/// Pack a file or a folder into `archive`.
+///
+/// The archive is read back once written, and is removed
+/// if it does not contain exactly the files that were packed.
+pub fn compress(path: &Path, archive: &Path, format: ArchiveFormat) -> anyhow::Result<Compressed> {
+    let result = write_archive(path, archive, format).and_then(|written| {
+        let mut read = read_archive(archive, format)?;
+        let mut written = written;
+        read.sort();
+        written.sort();
+        if read != written {
+            anyhow::bail!("{archive:?} does not match the archived files");
+        }
+        Ok(written)
+    });
+    match result {
+        Ok(files) => Ok(Compressed {
+            archive: archive.to_owned(),
+            original_size: files.iter().map(|(_, size)| size).sum(),
+            archive_size: std::fs::metadata(archive)?.len(),
+        }),
+        Err(e) => {
+            // Do not leave broken archives behind
+            let _ = std::fs::remove_file(archive);
+            Err(e)
+        }
+    }
+}
+
+/// Name and size of each file inside an archive.
+type Entries = Vec<(String, u64)>;
+
+fn write_archive(path: &Path, archive: &Path, format: ArchiveFormat) -> anyhow::Result<Entries> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{path:?} has no file name"))?
+        .to_string_lossy()
+        .into_owned();
+    let mut files = Vec::new();
+    collect_files(path, &name, &mut files)?;
+
+    let file = File::create(archive)?;
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = ZipWriter::new(file);
+            let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+            for (source, name) in &files {
+                if source.is_dir() {
+                    zip.add_directory(name, options)?;
+                } else {
+                    zip.start_file(name, options)?;
+                    io::copy(&mut File::open(source)?, &mut zip)?;
+                }
+            }
+            zip.finish()?.sync_all()?;
+        }
+        ArchiveFormat::Tar => {
+            write_tar(file, &files)?.sync_all()?;
+        }
+        ArchiveFormat::TarGz => {
+            let encoder = GzEncoder::new(file, Compression::default());
+            write_tar(encoder, &files)?.finish()?.sync_all()?;
+        }
+        ArchiveFormat::TarXz => {
+            let encoder = XzEncoder::new(file, XZ_LEVEL);
+            write_tar(encoder, &files)?.finish()?.sync_all()?;
+        }
+    }
+
+    files
+        .into_iter()
+        .filter(|(source, _)| !source.is_dir())
+        .map(|(source, name)| Ok((name, std::fs::metadata(source)?.len())))
+        .collect()
+}
+
+fn write_tar<W: Write>(writer: W, files: &[(PathBuf, String)]) -> anyhow::Result<W> {
+    let mut tar = tar::Builder::new(writer);
+    for (source, name) in files {
+        if source.is_dir() {
+            tar.append_dir(name, source)?;
+        } else {
+            tar.append_path_with_name(source, name)?;
+        }
+    }
+    Ok(tar.into_inner()?)
+}
+
+/// Collect the files and folders under `path`
+/// along with the names they get inside the archive.
+fn collect_files(
+    path: &Path,
+    name: &str,
+    files: &mut Vec<(PathBuf, String)>,
+) -> anyhow::Result<()> {
+    if path.is_dir() {
+        files.push((path.to_owned(), format!("{name}/")));
+        let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let entry_name = format!("{name}/{}", entry.file_name().to_string_lossy());
+            // Symlinks to folders are not followed to avoid cycles
+            if entry.file_type()?.is_symlink() && entry.path().is_dir() {
+                continue;
+            }
+            collect_files(&entry.path(), &entry_name, files)?;
+        }
+    } else {
+        files.push((path.to_owned(), name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Read every file of the archive to the end, which also checks their checksums.
+fn read_archive(archive: &Path, format: ArchiveFormat) -> anyhow::Result<Entries> {
+    let mut entries = Vec::new();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = ZipArchive::new(File::open(archive)?)?;
+            for i in 0..zip.len() {
+                let mut file = zip.by_index(i)?;
+                if !file.is_dir() {
+                    let size = io::copy(&mut file, &mut io::sink())?;
+                    entries.push((file.name().to_owned(), size));
+                }
+            }
+        }
+        ArchiveFormat::Tar | ArchiveFormat::TarGz | ArchiveFormat::TarXz => {
+            let mut tar = tar::Archive::new(tar_reader(File::open(archive)?, format));
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    let name = entry.path()?.to_string_lossy().into_owned();
+                    let size = io::copy(&mut entry, &mut io::sink())?;
+                    entries.push((name, size));
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Whether the file name ends with the extension of the format.
+pub fn is_archive_name(path: &Path, format: ArchiveFormat) -> bool {
+    matches!(ArchiveFormat::from_path(path), Some((f, _)) if f == format)
+}
+
+fn tar_reader(file: File, format: ArchiveFormat) -> Box<dyn Read> {
+    match format {
+        ArchiveFormat::TarGz => Box::new(GzDecoder::new(file)),
+        ArchiveFormat::TarXz => Box::new(XzDecoder::new(file)),
+        ArchiveFormat::Tar | ArchiveFormat::Zip => Box::new(file),
+    }
+}
+
+/// Unpack the archive into the `dest` folder, which must not exist yet.
+///
+/// Entries that point outside of `dest` (absolute paths, `..`)
+/// make the whole archive fail. Links are not extracted.
+/// Returns the number of extracted files.
+pub fn extract(archive: &Path, dest: &Path, format: ArchiveFormat) -> anyhow::Result<usize> {
+    if dest.exists() {
+        anyhow::bail!("{dest:?} already exists");
+    }
+    std::fs::create_dir_all(dest)?;
+    let result = match format {
+        ArchiveFormat::Zip => extract_zip(archive, dest),
+        ArchiveFormat::Tar | ArchiveFormat::TarGz | ArchiveFormat::TarXz => {
+            extract_tar(archive, dest, format)
+        }
+    };
+    if result.is_err() {
+        // Do not leave half-extracted archives behind
+        let _ = std::fs::remove_dir_all(dest);
+    }
+    result
+}
+
+fn extract_zip(archive: &Path, dest: &Path) -> anyhow::Result<usize> {
+    let mut zip = ZipArchive::new(File::open(archive)?)?;
+    let mut count = 0;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let path = file
+            .enclosed_name()
+            .map(|path| path.to_owned())
+            .ok_or_else(|| {
+                anyhow::anyhow!("{archive:?} contains an unsafe path {:?}", file.name())
+            })?;
+        let out = dest.join(safe_path(&path)?);
+        if matches!(file.unix_mode(), Some(mode) if mode & S_IFMT == S_IFLNK) {
+            continue;
+        } else if file.is_dir() {
+            std::fs::create_dir_all(&out)?;
+        } else {
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&out)?)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn extract_tar(archive: &Path, dest: &Path, format: ArchiveFormat) -> anyhow::Result<usize> {
+    let mut tar = tar::Archive::new(tar_reader(File::open(archive)?, format));
+    let mut count = 0;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let out = dest.join(safe_path(&path)?);
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            std::fs::create_dir_all(&out)?;
+        } else if entry_type.is_file() {
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&out)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Make sure that the path stays inside of the folder it is joined to.
+fn safe_path(path: &Path) -> anyhow::Result<&Path> {
+    if path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path)
+    } else {
+        Err(anyhow::anyhow!(
+            "Unsafe path {path:?} inside of the archive"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, extract, safe_path, ArchiveFormat};
+    use crate::lib::testing::test_dir;
+    use std::path::{Path, PathBuf};
+
+    fn test_folder(name: &str) -> PathBuf {
+        let folder = test_dir("archive").join(name);
+        if !folder.exists() {
+            std::fs::create_dir_all(folder.join("nested")).unwrap();
+            std::fs::write(folder.join("a.txt"), "a".repeat(1000)).unwrap();
+            std::fs::write(folder.join("nested").join("b.txt"), "b").unwrap();
+        }
+        folder
+    }
+
+    #[test]
+    fn compress_zip() {
+        let folder = test_folder("test1");
+        let archive = test_dir("archive").join("test1.zip");
+        let compressed = compress(&folder, &archive, ArchiveFormat::Zip).unwrap();
+        assert!(archive.exists());
+        assert_eq!(compressed.original_size, 1001);
+        assert!(compressed.ratio() < 1.);
+    }
+
+    #[test]
+    fn compress_tar_gz() {
+        let folder = test_folder("test2");
+        let archive = test_dir("archive").join("test2.tar.gz");
+        let compressed = compress(&folder, &archive, ArchiveFormat::TarGz).unwrap();
+        assert!(archive.exists());
+        assert_eq!(compressed.original_size, 1001);
+    }
+
+    #[test]
+    fn extract_all_formats() {
+        let folder = test_folder("test3");
+        for format in ArchiveFormat::ALL {
+            let archive = test_dir("archive").join(format!("test3.{}", format.extension()));
+            if archive.exists() {
+                std::fs::remove_file(&archive).unwrap();
+            }
+            compress(&folder, &archive, format).unwrap();
+            let dest = test_dir("archive").join(format!("test3-{}", format.extension()));
+            if dest.exists() {
+                std::fs::remove_dir_all(&dest).unwrap();
+            }
+            assert_eq!(extract(&archive, &dest, format).unwrap(), 2);
+            assert!(dest.join("test3").join("nested").join("b.txt").exists());
+        }
+    }
+
+    #[test]
+    fn from_path() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("/tmp/Photos.TAR.GZ")),
+            Some((ArchiveFormat::TarGz, "Photos".to_owned()))
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("a.b.zip")),
+            Some((ArchiveFormat::Zip, "a.b".to_owned()))
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("\u{212A}.zip")),
+            Some((ArchiveFormat::Zip, "\u{212A}".into()))
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("ẞ.TAR.GZ")),
+            Some((ArchiveFormat::TarGz, "ẞ".into()))
+        );
+        assert_eq!(ArchiveFormat::from_path(Path::new("ézip")), None);
+        assert_eq!(ArchiveFormat::from_path(Path::new(".zip")), None);
+        assert_eq!(ArchiveFormat::from_path(Path::new("notes.txt")), None);
+    }
+
+    #[test]
+    fn unsafe_paths() {
+        assert!(safe_path(Path::new("a/./b.txt")).is_ok());
+        assert!(safe_path(Path::new("../b.txt")).is_err());
+        assert!(safe_path(Path::new("a/../../b.txt")).is_err());
+        assert!(safe_path(Path::new("/etc/passwd")).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+//! Tags that music players show, read from ID3v2, FLAC and Ogg Vorbis comments.
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use lazy_static::lazy_static;
+use symphonia::core::{
+    errors::Error,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataOptions, StandardTagKey},
+    probe::{Hint, ProbeResult},
+};
+
+use super::{cache::make_room, Base, Tag};
+
+lazy_static! {
+    /// Tags of the audio files read so far, with the modification time of the file when it was read.
+    static ref AUDIO: Mutex<HashMap<PathBuf, (SystemTime, AudioInfo)>> = Mutex::new(HashMap::new());
+}
+
+/// The tags the conditions and the templates look at.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioInfo {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    /// The year of the release date.
+    pub year: Option<i32>,
+    /// How long the audio plays.
+    pub duration: Option<Duration>,
+}
+
+/// Whether the tag contains `text`, ignoring letter case.
+pub fn is_tag_match(tag: &Option<String>, text: &str) -> bool {
+    matches!(tag, Some(tag) if tag.to_lowercase().contains(&text.to_lowercase()))
+}
+
+/// Read the tags of an MP3, FLAC or Ogg Vorbis file.
+///
+/// Tags that are missing are `None`, and so are all of the tags of folders
+/// and the files that are not audio. Only the files that cannot be read are an error.
+pub fn audio_info(path: &Path) -> anyhow::Result<AudioInfo> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Ok(AudioInfo::default());
+    }
+    let modified = metadata.modified()?;
+    if let Ok(audio) = AUDIO.lock() {
+        if let Some((time, info)) = audio.get(path) {
+            if *time == modified {
+                return Ok(info.clone());
+            }
+        }
+    }
+    let stream = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    );
+    let info = match probed {
+        Ok(mut probed) => read_info(&mut probed),
+        Err(Error::IoError(e)) if e.kind() != std::io::ErrorKind::UnexpectedEof => {
+            return Err(e.into())
+        }
+        Err(_) => AudioInfo::default(),
+    };
+    if let Ok(mut audio) = AUDIO.lock() {
+        make_room(&mut audio);
+        audio.insert(path.to_owned(), (modified, info.clone()));
+    }
+    Ok(info)
+}
+
+fn read_info(probed: &mut ProbeResult) -> AudioInfo {
+    // Tags inside the container, such as Vorbis comments, win over tags in front of it, such as ID3v2
+    let mut tags = Vec::new();
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend(revision.tags().iter().cloned());
+    }
+    if let Some(mut metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.skip_to_latest() {
+            tags.extend(revision.tags().iter().cloned());
+        }
+    }
+    let text = |keys: &[StandardTagKey]| {
+        keys.iter().find_map(|key| {
+            tags.iter()
+                .find(|tag| tag.std_key == Some(*key))
+                .map(|tag| tag.value.to_string().trim().to_owned())
+                .filter(|value| !value.is_empty())
+        })
+    };
+    let duration = probed.format.default_track().and_then(|track| {
+        let params = &track.codec_params;
+        let time = params.time_base?.calc_time(params.n_frames?);
+        Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+    });
+    AudioInfo {
+        artist: text(&[StandardTagKey::Artist, StandardTagKey::AlbumArtist]),
+        album: text(&[StandardTagKey::Album]),
+        genre: text(&[StandardTagKey::Genre]),
+        year: text(&[
+            StandardTagKey::Date,
+            StandardTagKey::ReleaseDate,
+            StandardTagKey::OriginalDate,
+        ])
+        .and_then(|date| date.get(..4)?.parse().ok()),
+        duration,
+    }
+}
+
+/// Text tags offered for custom tags.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioMatch {
+    Artist,
+    Album,
+    Genre,
+}
+
+impl AudioMatch {
+    pub const ALL: [AudioMatch; 3] = [AudioMatch::Artist, AudioMatch::Album, AudioMatch::Genre];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AudioMatch::Artist => "Artist",
+            AudioMatch::Album => "Album",
+            AudioMatch::Genre => "Genre",
+        }
+    }
+}
+
+/// A custom tag for the audio files whose tag, one of [`AudioMatch`], contains the text.
+pub fn custom_audio_tag(kind: AudioMatch, text: &str) -> anyhow::Result<Tag> {
+    let text = text.trim();
+    if text.is_empty() {
+        anyhow::bail!("The text is empty");
+    }
+    let (name, basis) = match kind {
+        AudioMatch::Artist => ("🎤", Base::Artist(text.to_owned())),
+        AudioMatch::Album => ("💿", Base::Album(text.to_owned())),
+        AudioMatch::Genre => ("🎸", Base::Genre(text.to_owned())),
+    };
+    let what = kind.label().to_lowercase();
+    Ok(Tag {
+        name: format!("{name} {text}"),
+        desc: format!("A custom tag which includes audio files with the {what} like {text:?}."),
+        basis,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{audio_info, custom_audio_tag, AudioInfo, AudioMatch};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Base, Item, Tag, TagExpr, Template};
+    use std::{path::Path, time::Duration};
+
+    /// Write an MP3 file of silent frames with an ID3v2.3 tag in front of them.
+    fn write_mp3(path: &Path, frames: &[(&[u8; 4], &str)]) {
+        let mut tag = Vec::new();
+        for (id, text) in frames {
+            tag.extend_from_slice(*id);
+            tag.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+            tag.extend_from_slice(&[0, 0, 0]);
+            tag.extend_from_slice(text.as_bytes());
+        }
+        let size = tag.len() as u32;
+        let mut mp3 = b"ID3\x03\x00\x00".to_vec();
+        // The size of the tag is stored in 7 bits per byte
+        mp3.extend((0..4).rev().map(|i| (size >> (7 * i) & 0x7F) as u8));
+        mp3.extend_from_slice(&tag);
+        // MPEG-1 layer III frames at 128 kbit/s and 44.1 kHz, 417 bytes and 1152 samples each
+        for _ in 0..100 {
+            mp3.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x44]);
+            mp3.extend_from_slice(&[0; 413]);
+        }
+        std::fs::write(path, mp3).unwrap();
+    }
+
+    #[test]
+    fn read() {
+        let path = test_dir("audio").join("song.mp3");
+        write_mp3(
+            &path,
+            &[
+                (b"TPE1", "Daft Punk"),
+                (b"TALB", "Discovery"),
+                (b"TCON", "House"),
+                (b"TYER", "2001"),
+            ],
+        );
+        let info = audio_info(&path).unwrap();
+        assert_eq!(info.artist.as_deref(), Some("Daft Punk"));
+        assert_eq!(info.album.as_deref(), Some("Discovery"));
+        assert_eq!(info.genre.as_deref(), Some("House"));
+        assert_eq!(info.year, Some(2001));
+        let duration = info.duration.unwrap();
+        assert!(duration > Duration::from_secs(2) && duration < Duration::from_secs(3));
+        let text = test_dir("audio").join("text.txt");
+        std::fs::write(&text, "text").unwrap();
+        assert_eq!(audio_info(&text).unwrap(), AudioInfo::default());
+        assert_eq!(
+            audio_info(&test_dir("audio")).unwrap(),
+            AudioInfo::default()
+        );
+    }
+
+    #[test]
+    fn not_audio() {
+        let text = test_dir("audio").join("notes.txt");
+        std::fs::write(&text, "notes").unwrap();
+        for path in [text, test_dir("audio")] {
+            let mut item = Item::new(&path).unwrap();
+            assert!(!Base::Artist("x".into()).is(&mut item).unwrap());
+            assert!(!Base::YearLT(2000).is(&mut item).unwrap());
+            let artist = Tag {
+                name: String::new(),
+                desc: String::new(),
+                basis: Base::Artist("x".into()),
+            };
+            assert!(TagExpr::new(artist, false).is(&mut item).unwrap());
+        }
+    }
+
+    #[test]
+    fn conditions() {
+        let path = test_dir("audio").join("untitled.mp3");
+        write_mp3(&path, &[(b"TPE1", "AC/DC"), (b"TYER", "1980")]);
+        let mut item = Item::new(&path).unwrap();
+        let mut is = |basis: Base| basis.is(&mut item).unwrap();
+        assert!(is(Base::Artist("ac/dc".into())));
+        assert!(!is(Base::Album("ac/dc".into())));
+        assert!(is(Base::YearLT(1990)));
+        assert!(is(Base::YearET(1980)));
+        assert!(!is(Base::YearGT(1980)));
+        assert!(is(Base::DurationLT(Duration::from_secs(60))));
+        assert!(is(custom_audio_tag(AudioMatch::Artist, "AC")
+            .unwrap()
+            .basis));
+        assert!(custom_audio_tag(AudioMatch::Genre, " ").is_err());
+        let template = Template::new("Music/{artist}/{album}/{year}");
+        assert_eq!(
+            template.expand(&item, 0).unwrap(),
+            "Music/AC-DC/Unknown/1980"
+        );
+        let template = Template::new("{album:Singles}");
+        assert_eq!(template.expand(&item, 0).unwrap(), "Singles");
+    }
+
+    #[test]
+    fn hostile_tags() {
+        let path = test_dir("audio").join("hostile.mp3");
+        write_mp3(
+            &path,
+            &[(b"TPE1", ".."), (b"TALB", "../../etc"), (b"TCON", "\x07\t")],
+        );
+        let item = Item::new(&path).unwrap();
+        let template = Template::new("Music/{artist}/{album}/{genre}");
+        assert_eq!(
+            template.expand(&item, 0).unwrap(),
+            "Music/Unknown/..-..-etc/Unknown"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! Limits for the caches of file metadata that are kept for the whole session.
+use std::{collections::HashMap, path::PathBuf};
+
+/// Number of files a cache holds before it is cleaned up.
+const CACHE_LIMIT: usize = 10_000;
+
+/// Make room in a cache keyed by path before adding to it.
+///
+/// Files that no longer exist are dropped first, and the rest only if that is not enough.
+pub(crate) fn make_room<V>(cache: &mut HashMap<PathBuf, V>) {
+    if cache.len() < CACHE_LIMIT {
+        return;
+    }
+    cache.retain(|path, _| path.symlink_metadata().is_ok());
+    if cache.len() >= CACHE_LIMIT {
+        cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_room, CACHE_LIMIT};
+    use std::{collections::HashMap, path::PathBuf};
+
+    #[test]
+    fn drop_missing_files() {
+        let existing = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut cache = (1..CACHE_LIMIT)
+            .map(|n| (existing.join(format!("missing-{n}")), ()))
+            .collect::<HashMap<_, _>>();
+        make_room(&mut cache);
+        assert_eq!(cache.len(), CACHE_LIMIT - 1);
+        cache.insert(existing.clone(), ());
+        make_room(&mut cache);
+        assert_eq!(cache.into_keys().collect::<Vec<_>>(), vec![existing]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Conditions on the calendar date of the times of a file, in the local time zone.
+use std::time::SystemTime;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+use super::{Base, Tag, Timestamp};
+
+/// A part of the calendar that contains today.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Period {
+    Day,
+    /// Weeks start on Monday.
+    Week,
+    Month,
+    Year,
+}
+
+impl Period {
+    pub const ALL: [Period; 4] = [Period::Day, Period::Week, Period::Month, Period::Year];
+
+    /// The first day of the period that contains `date`.
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week => {
+                date - chrono::Duration::days(date.weekday().num_days_from_monday().into())
+            }
+            Period::Month => date.with_day(1).expect("every month has a first day"),
+            Period::Year => date.with_ordinal(1).expect("every year has a first day"),
+        }
+    }
+    /// Such as `today` or `this month`.
+    pub fn current(self) -> &'static str {
+        match self {
+            Period::Day => "today",
+            Period::Week => "this week",
+            Period::Month => "this month",
+            Period::Year => "this year",
+        }
+    }
+}
+
+/// The local date of the time.
+pub fn local_date(time: SystemTime) -> NaiveDate {
+    DateTime::<Local>::from(time).date_naive()
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+pub fn is_weekend(date: NaiveDate) -> bool {
+    matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// Ways to compare a date offered for custom tags.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateMatch {
+    Before,
+    After,
+    InCurrent(Period),
+    BeforeCurrent(Period),
+    OnWeekend,
+}
+
+impl DateMatch {
+    pub const ALL: [DateMatch; 11] = [
+        DateMatch::Before,
+        DateMatch::After,
+        DateMatch::InCurrent(Period::Day),
+        DateMatch::InCurrent(Period::Week),
+        DateMatch::InCurrent(Period::Month),
+        DateMatch::InCurrent(Period::Year),
+        DateMatch::BeforeCurrent(Period::Day),
+        DateMatch::BeforeCurrent(Period::Week),
+        DateMatch::BeforeCurrent(Period::Month),
+        DateMatch::BeforeCurrent(Period::Year),
+        DateMatch::OnWeekend,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DateMatch::Before => "Before the day",
+            DateMatch::After => "After the day",
+            DateMatch::InCurrent(Period::Day) => "Today",
+            DateMatch::InCurrent(Period::Week) => "This week",
+            DateMatch::InCurrent(Period::Month) => "This month",
+            DateMatch::InCurrent(Period::Year) => "This year",
+            DateMatch::BeforeCurrent(Period::Day) => "Before today",
+            DateMatch::BeforeCurrent(Period::Week) => "Before this week",
+            DateMatch::BeforeCurrent(Period::Month) => "Before this month",
+            DateMatch::BeforeCurrent(Period::Year) => "Before this year",
+            DateMatch::OnWeekend => "On a weekend",
+        }
+    }
+    /// Whether the picked date is compared with.
+    pub fn uses_date(&self) -> bool {
+        matches!(self, DateMatch::Before | DateMatch::After)
+    }
+}
+
+/// A custom tag that compares the `timestamp` of a file with the calendar
+/// in one of the ways of [`DateMatch`].
+pub fn custom_date_tag(timestamp: Timestamp, kind: DateMatch, date: NaiveDate) -> Tag {
+    let (name, verb) = match timestamp {
+        Timestamp::Created => ("Created", "created"),
+        Timestamp::Modified => ("Modified", "last modified"),
+        Timestamp::Accessed => ("Opened", "last opened"),
+        Timestamp::Taken => ("Taken", "taken"),
+    };
+    let (basis, when) = match kind {
+        DateMatch::Before => (
+            Base::DateBefore(timestamp, date),
+            format!("before {}", date.format("%Y-%m-%d")),
+        ),
+        DateMatch::After => (
+            Base::DateAfter(timestamp, date),
+            format!("after {}", date.format("%Y-%m-%d")),
+        ),
+        DateMatch::InCurrent(period) => (
+            Base::InCurrent(timestamp, period),
+            period.current().to_owned(),
+        ),
+        DateMatch::BeforeCurrent(period) => (
+            Base::BeforeCurrent(timestamp, period),
+            format!("before {}", period.current()),
+        ),
+        DateMatch::OnWeekend => (Base::OnWeekend(timestamp), "on a weekend".to_owned()),
+    };
+    Tag {
+        name: format!("📅 {name} {when}"),
+        desc: format!("A custom tag which includes files that were {verb} {when}."),
+        basis,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{custom_date_tag, is_weekend, today, DateMatch, Period};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Item, Timestamp};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn periods() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(Period::Day.start(date), date);
+        assert_eq!(
+            Period::Week.start(date),
+            NaiveDate::from_ymd_opt(2024, 2, 26).unwrap()
+        );
+        assert_eq!(
+            Period::Month.start(date),
+            NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()
+        );
+        assert_eq!(
+            Period::Year.start(date),
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+        );
+        assert!(is_weekend(NaiveDate::from_ymd_opt(2024, 3, 2).unwrap()));
+        assert!(!is_weekend(date));
+    }
+
+    #[test]
+    fn new_file() {
+        let path = test_dir("calendar").join("new.txt");
+        std::fs::write(&path, "").unwrap();
+        let mut item = Item::new(&path).unwrap();
+        let mut is = |kind, date| {
+            custom_date_tag(Timestamp::Modified, kind, date)
+                .basis
+                .is(&mut item)
+                .unwrap()
+        };
+        let past = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        assert!(is(DateMatch::After, past));
+        assert!(!is(DateMatch::Before, past));
+        assert!(!is(DateMatch::After, today()));
+        for period in Period::ALL {
+            assert!(is(DateMatch::InCurrent(period), past));
+            assert!(!is(DateMatch::BeforeCurrent(period), past));
+            // Every period is offered both ways
+            assert!(DateMatch::ALL.contains(&DateMatch::InCurrent(period)));
+            assert!(DateMatch::ALL.contains(&DateMatch::BeforeCurrent(period)));
+        }
+        assert_eq!(is(DateMatch::OnWeekend, past), is_weekend(today()));
+    }
+}
@@ -0,0 +1,352 @@
+//! External programs that are run by the command event.
+use super::{Item, Template};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    process::{self, ExitStatus, Stdio},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often a running program is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long the output is waited for after the program has finished,
+/// even if the timeout has passed.
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// A program with its arguments.
+///
+/// Arguments are templates, so `{path}`, `{name}`, `{dir}` and
+/// the rest of the placeholders are replaced for every file.
+/// The arguments are passed to the program directly, unless `shell` is set.
+/// In that case the whole line is a shell script, and every placeholder
+/// in it is passed to the script as a positional argument (`"$1"`, `"$2"`...),
+/// so file names are never interpreted by the shell.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandLine {
+    program: String,
+    args: Vec<Template>,
+    shell: bool,
+    timeout: Duration,
+}
+
+/// Exit status and captured output of a finished program.
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Both output streams joined together.
+    pub fn text(&self) -> String {
+        match (self.stdout.trim(), self.stderr.trim()) {
+            (stdout, "") => stdout.to_owned(),
+            ("", stderr) => stderr.to_owned(),
+            (stdout, stderr) => format!("{stdout}\n{stderr}"),
+        }
+    }
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        CommandLine {
+            program: "echo".into(),
+            args: vec![Template::new("{path}")],
+            shell: false,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl CommandLine {
+    /// Split the line into a program and its arguments.
+    ///
+    /// Words are separated by whitespace, quotes and `\` work as in the shell.
+    /// If `shell` is set, the line is kept as a single script.
+    pub fn parse(line: &str, shell: bool, timeout: Duration) -> anyhow::Result<Self> {
+        if shell {
+            if line.trim().is_empty() {
+                anyhow::bail!("The command is empty");
+            }
+            Template::new(line).validate()?;
+            return Ok(CommandLine {
+                program: line.to_owned(),
+                args: Vec::new(),
+                shell,
+                timeout,
+            });
+        }
+        let mut words = split(line)?.into_iter();
+        let program = words.next().with_context(|| "The command is empty")?;
+        let args = words.map(Template::new).collect::<Vec<_>>();
+        for arg in &args {
+            arg.validate()?;
+        }
+        Ok(CommandLine {
+            program,
+            args,
+            shell,
+            timeout,
+        })
+    }
+    /// The program and its arguments joined into a single line.
+    pub fn line(&self) -> String {
+        if self.shell {
+            return self.program.clone();
+        }
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(|arg| arg.as_str()))
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+    pub fn shell(&self) -> bool {
+        self.shell
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    /// Run the program for the item and wait until it finishes.
+    ///
+    /// The program is killed if it is still running after the timeout.
+    pub fn run(&self, item: &Item) -> anyhow::Result<CommandOutput> {
+        let (mut command, args) = if self.shell {
+            let (script, args) = Template::new(&self.program).to_shell_script()?;
+            (shell_command(&script)?, args)
+        } else {
+            (process::Command::new(&self.program), self.args.clone())
+        };
+        let args = args
+            .iter()
+            .map(|arg| arg.expand(item, 0))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        command
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = item.path().parent() {
+            command.current_dir(dir);
+        }
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Unable to start {:?}", self.program))?;
+        let stdout = Capture::start(child.stdout.take());
+        let stderr = Capture::start(child.stderr.take());
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                anyhow::bail!(
+                    "{:?} has not finished in {}",
+                    self.program,
+                    duration_string::DurationString::from(self.timeout)
+                );
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        // Programs started in the background by the program can keep the output open,
+        // so it is not waited for past the timeout
+        let deadline = deadline.max(Instant::now() + OUTPUT_GRACE);
+        Ok(CommandOutput {
+            status,
+            stdout: stdout.finish(deadline),
+            stderr: stderr.finish(deadline),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(script: &str) -> anyhow::Result<process::Command> {
+    let mut command = process::Command::new("sh");
+    // The first argument after the script becomes `$0`
+    command.arg("-c").arg(script).arg("sh");
+    Ok(command)
+}
+
+#[cfg(not(unix))]
+fn shell_command(_script: &str) -> anyhow::Result<process::Command> {
+    anyhow::bail!("Running commands through the shell is only supported on Unix")
+}
+
+/// Output of a program, read in the background.
+struct Capture {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    /// Disconnected once the output is closed.
+    done: mpsc::Receiver<()>,
+}
+
+impl Capture {
+    fn start(mut pipe: Option<impl Read + Send + 'static>) -> Self {
+        let bytes = Arc::new(Mutex::new(Vec::new()));
+        let (sender, done) = mpsc::channel::<()>();
+        let shared = bytes.clone();
+        thread::spawn(move || {
+            let _sender = sender;
+            let mut buf = [0; 8192];
+            while let Some(pipe) = pipe.as_mut() {
+                match pipe.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => shared
+                        .lock()
+                        .expect("unable to aquire mutex")
+                        .extend_from_slice(&buf[..read]),
+                }
+            }
+        });
+        Capture { bytes, done }
+    }
+    /// The output read until it was closed, or until `deadline`.
+    fn finish(self, deadline: Instant) -> String {
+        let _ = self
+            .done
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        String::from_utf8_lossy(&self.bytes.lock().expect("unable to aquire mutex")).into_owned()
+    }
+}
+
+fn split(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                word.push(chars.next().with_context(|| "Unfinished escape sequence")?);
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        anyhow::bail!("Unclosed quote");
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'))
+    {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quote, split, CommandLine};
+    use crate::lib::{testing::test_dir, Item};
+    use std::time::Duration;
+
+    fn test_item() -> Item {
+        let file = test_dir("command").join("test1.txt");
+        if !file.exists() {
+            std::fs::File::create(&file).unwrap();
+        }
+        Item::new(file).unwrap()
+    }
+
+    #[test]
+    fn split_words() {
+        assert_eq!(
+            split(r#"convert "{path}" 'out file.png' a\ b"#).unwrap(),
+            vec!["convert", "{path}", "out file.png", "a b"]
+        );
+        assert_eq!(split(r#"echo "" x"#).unwrap(), vec!["echo", "", "x"]);
+        assert!(split("echo 'unclosed").is_err());
+    }
+
+    #[test]
+    fn quote_roundtrip() {
+        for word in ["plain", "with space", "it's", "", r#"a"b\c"#] {
+            assert_eq!(split(&quote(word)).unwrap(), vec![word]);
+        }
+    }
+
+    #[test]
+    fn parse_line() {
+        let command = CommandLine::parse("gzip -k {path}", false, Duration::from_secs(1)).unwrap();
+        assert_eq!(command.program(), "gzip");
+        assert_eq!(command.line(), "gzip -k {path}");
+        assert!(CommandLine::parse("", false, Duration::from_secs(1)).is_err());
+        assert!(CommandLine::parse("echo {unknown}", false, Duration::from_secs(1)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_output() {
+        let command = CommandLine::parse("echo {name}", false, Duration::from_secs(5)).unwrap();
+        let output = command.run(&test_item()).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.text(), "test1.txt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_shell() {
+        let command =
+            CommandLine::parse("echo {stem} >&2; exit 3", true, Duration::from_secs(5)).unwrap();
+        assert_eq!(command.line(), "echo {stem} >&2; exit 3");
+        let output = command.run(&test_item()).unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stderr.trim(), "test1");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_timeout() {
+        let command = CommandLine::parse("sleep 5", false, Duration::from_millis(100)).unwrap();
+        assert!(command.run(&test_item()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_background() {
+        // The program in the background keeps the output open after the script has finished
+        let command =
+            CommandLine::parse("sleep 5 & echo done", true, Duration::from_millis(100)).unwrap();
+        let started = std::time::Instant::now();
+        let output = command.run(&test_item()).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert_eq!(output.stdout.trim(), "done");
+    }
+}
@@ -0,0 +1,236 @@
+//! Strategies for files that already exist at the destination.
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::util::PathExt;
+
+/// What to do when the destination of a file is already taken.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Conflict {
+    /// Leave both files as they are.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep both files, adding a ` (1)` suffix to the new one.
+    Rename,
+    /// Replace the existing file if it is older.
+    KeepNewer,
+    /// Replace the existing file if it is smaller.
+    KeepLarger,
+    /// Skip the file if both have the same content, otherwise keep both.
+    SkipIdentical,
+}
+
+impl Conflict {
+    pub const ALL: [Conflict; 6] = [
+        Conflict::Skip,
+        Conflict::Overwrite,
+        Conflict::Rename,
+        Conflict::KeepNewer,
+        Conflict::KeepLarger,
+        Conflict::SkipIdentical,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Conflict::Skip => "(skip existing)",
+            Conflict::Overwrite => "(overwrite)",
+            Conflict::Rename => "(keep both)",
+            Conflict::KeepNewer => "(keep newer)",
+            Conflict::KeepLarger => "(keep larger)",
+            Conflict::SkipIdentical => "(skip identical)",
+        }
+    }
+    /// Decide where the file should go if it is meant to end up at `dest`.
+    ///
+    /// Returns the final destination and whether it should be overwritten,
+    /// or `None` if the file should be skipped.
+    pub fn resolve(&self, from: &Path, dest: PathBuf) -> anyhow::Result<Option<(PathBuf, bool)>> {
+        if dest.symlink_metadata().is_err() {
+            return Ok(Some((dest, false)));
+        }
+        Ok(match self {
+            Conflict::Skip => None,
+            Conflict::Overwrite => Some((dest, true)),
+            Conflict::Rename => Some((free_name(&dest, from.is_dir())?, false)),
+            Conflict::KeepNewer => (std::fs::metadata(from)?.modified()?
+                > std::fs::metadata(&dest)?.modified()?)
+            .then_some((dest, true)),
+            Conflict::KeepLarger => (fs_extra::dir::get_size(from)?
+                > fs_extra::dir::get_size(&dest)?)
+            .then_some((dest, true)),
+            Conflict::SkipIdentical => {
+                if identical(from, &dest)? {
+                    None
+                } else {
+                    Some((free_name(&dest, from.is_dir())?, false))
+                }
+            }
+        })
+    }
+}
+
+/// Accepts both the strategy and the `overwrite` flag of older rule files.
+pub fn deserialize_conflict<'de, D: Deserializer<'de>>(d: D) -> Result<Conflict, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Overwrite(bool),
+        Strategy(Conflict),
+    }
+    Ok(match Repr::deserialize(d)? {
+        Repr::Overwrite(true) => Conflict::Overwrite,
+        Repr::Overwrite(false) => Conflict::Skip,
+        Repr::Strategy(conflict) => conflict,
+    })
+}
+
+/// Find a name like `file (1).txt` that is not taken yet.
+///
+/// Folders have no extension, so `v1.2` becomes `v1.2 (1)`.
+fn free_name(path: &Path, is_dir: bool) -> anyhow::Result<PathBuf> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("{path:?} has no parent"))?;
+    let (stem, ext) = if is_dir {
+        (path.name().unwrap_or_default(), String::new())
+    } else {
+        (
+            path.stem().unwrap_or_default(),
+            path.ext().map(|ext| format!(".{ext}")).unwrap_or_default(),
+        )
+    };
+    (1..)
+        .map(|n| parent.join(format!("{stem} ({n}){ext}")))
+        .find(|path| path.symlink_metadata().is_err())
+        .ok_or_else(|| anyhow::anyhow!("Unable to find a free name for {path:?}"))
+}
+
+/// Compare two files or folders by their content.
+pub fn identical(a: &Path, b: &Path) -> anyhow::Result<bool> {
+    match (a.is_dir(), b.is_dir()) {
+        (true, true) => {
+            let mut a_names = std::fs::read_dir(a)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut b_names = std::fs::read_dir(b)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<Result<Vec<_>, _>>()?;
+            a_names.sort();
+            b_names.sort();
+            if a_names != b_names {
+                return Ok(false);
+            }
+            for name in a_names {
+                if !identical(&a.join(&name), &b.join(&name))? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (false, false) => {
+            if std::fs::metadata(a)?.len() != std::fs::metadata(b)?.len() {
+                return Ok(false);
+            }
+            let mut a = BufReader::new(File::open(a)?);
+            let mut b = BufReader::new(File::open(b)?);
+            let mut a_buf = [0; 8192];
+            let mut b_buf = [0; 8192];
+            loop {
+                let read = a.read(&mut a_buf)?;
+                if read == 0 {
+                    return Ok(true);
+                }
+                b.read_exact(&mut b_buf[..read])?;
+                if a_buf[..read] != b_buf[..read] {
+                    return Ok(false);
+                }
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The size and modification time of a file or folder,
+/// to tell later whether it has changed since.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileState {
+    size: u64,
+    modified: SystemTime,
+}
+
+impl FileState {
+    pub fn of(path: &Path) -> anyhow::Result<Self> {
+        let metadata = path.symlink_metadata()?;
+        Ok(FileState {
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{free_name, identical, Conflict};
+    use crate::lib::testing::test_dir;
+
+    #[test]
+    fn migrate_overwrite() {
+        #[derive(serde::Deserialize)]
+        struct Event {
+            #[serde(alias = "overwrite", deserialize_with = "super::deserialize_conflict")]
+            conflict: Conflict,
+        }
+        let parse = |s| serde_json::from_str::<Event>(s).unwrap().conflict;
+        assert_eq!(parse(r#"{"overwrite":true}"#), Conflict::Overwrite);
+        assert_eq!(parse(r#"{"overwrite":false}"#), Conflict::Skip);
+        assert_eq!(parse(r#"{"conflict":"KeepNewer"}"#), Conflict::KeepNewer);
+    }
+
+    #[test]
+    fn free_names() {
+        let taken = test_dir("conflict").join("test1.txt");
+        std::fs::write(&taken, "").unwrap();
+        let renamed = test_dir("conflict").join("test1 (1).txt");
+        if renamed.exists() {
+            std::fs::remove_file(&renamed).unwrap();
+        }
+        assert_eq!(free_name(&taken, false).unwrap(), renamed);
+        let taken = test_dir("conflict").join("v1.2");
+        std::fs::create_dir_all(&taken).unwrap();
+        assert_eq!(
+            free_name(&taken, true).unwrap(),
+            test_dir("conflict").join("v1.2 (1)")
+        );
+    }
+
+    #[test]
+    fn identical_files() {
+        let a = test_dir("conflict").join("test2-a.txt");
+        let b = test_dir("conflict").join("test2-b.txt");
+        let c = test_dir("conflict").join("test2-c.txt");
+        std::fs::write(&a, "same").unwrap();
+        std::fs::write(&b, "same").unwrap();
+        std::fs::write(&c, "diff").unwrap();
+        assert!(identical(&a, &b).unwrap());
+        assert!(!identical(&a, &c).unwrap());
+    }
+
+    #[test]
+    fn resolve() {
+        let from = test_dir("conflict").join("test3-from.txt");
+        let dest = test_dir("conflict").join("test3-dest.txt");
+        std::fs::write(&dest, "small").unwrap();
+        std::fs::write(&from, "larger").unwrap();
+        let resolve = |conflict: Conflict| conflict.resolve(&from, dest.clone()).unwrap();
+        assert_eq!(resolve(Conflict::Skip), None);
+        assert_eq!(resolve(Conflict::Overwrite), Some((dest.clone(), true)));
+        assert_eq!(resolve(Conflict::KeepLarger), Some((dest.clone(), true)));
+        assert!(matches!(resolve(Conflict::SkipIdentical), Some((_, false))));
+    }
+}
@@ -0,0 +1,212 @@
+//! Searching the content of files for text.
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    sync::Mutex,
+};
+
+use byte_unit::Byte;
+use lazy_static::lazy_static;
+use regex::bytes::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{Base, Tag};
+
+/// Files that have a zero byte among their first bytes are considered binary.
+const BINARY_CHECK_LEN: usize = 8192;
+/// Longer lines are searched in pieces, so that a file without line breaks is not read whole.
+const MAX_LINE_LEN: usize = 1 << 20;
+/// How much of the previous piece of a long line is searched again with the next one,
+/// unless the text is longer.
+const LINE_OVERLAP: usize = 4096;
+
+lazy_static! {
+    /// Compiled searches, as they are checked for every file.
+    static ref SEARCHES: Mutex<HashMap<(String, bool), Regex>> = Mutex::new(HashMap::new());
+}
+
+/// Text to look for inside files.
+///
+/// Files are read line by line, so a match cannot span several lines.
+/// Lines over a mebibyte are searched in overlapping pieces,
+/// where a regular expression can miss matches that are longer than the overlap.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContentSearch {
+    pub text: String,
+    /// Whether `text` is a regular expression instead of literal text.
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub ignore_case: bool,
+    /// Larger files are not searched at all.
+    #[serde(default = "ContentSearch::default_max_size")]
+    pub max_size: Byte,
+    /// Whether files that do not look like text are searched too.
+    #[serde(default)]
+    pub binary: bool,
+}
+
+impl ContentSearch {
+    /// Size limit of new searches.
+    pub fn default_max_size() -> Byte {
+        Byte::from_bytes(64 << 20)
+    }
+    pub fn new(text: impl Into<String>, regex: bool, ignore_case: bool) -> Self {
+        ContentSearch {
+            text: text.into(),
+            regex,
+            ignore_case,
+            max_size: ContentSearch::default_max_size(),
+            binary: false,
+        }
+    }
+    /// Check that the regular expression is valid.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.compile().map(|_| ())
+    }
+    fn compile(&self) -> anyhow::Result<Regex> {
+        let pattern = if self.regex {
+            self.text.clone()
+        } else {
+            regex::escape(&self.text)
+        };
+        let mut searches = SEARCHES.lock().expect("unable to aquire mutex");
+        let key = (pattern, self.ignore_case);
+        if let Some(regex) = searches.get(&key) {
+            return Ok(regex.clone());
+        }
+        let regex = RegexBuilder::new(&key.0)
+            .case_insensitive(self.ignore_case)
+            .build()?;
+        searches.insert(key, regex.clone());
+        Ok(regex)
+    }
+    /// Whether the file contains the text.
+    ///
+    /// Folders, files over the size limit and binary files (unless allowed) never match.
+    pub fn is(&self, path: &Path) -> anyhow::Result<bool> {
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_file() || u128::from(metadata.len()) > self.max_size.get_bytes() {
+            return Ok(false);
+        }
+        let regex = self.compile()?;
+        let mut reader = BufReader::with_capacity(BINARY_CHECK_LEN, File::open(path)?);
+        if !self.binary && reader.fill_buf()?.contains(&0) {
+            return Ok(false);
+        }
+        let overlap = self.text.len().clamp(LINE_OVERLAP, MAX_LINE_LEN / 2);
+        let mut line = Vec::new();
+        loop {
+            let limit = (MAX_LINE_LEN - line.len()) as u64;
+            if reader.by_ref().take(limit).read_until(b'\n', &mut line)? == 0 {
+                return Ok(false);
+            }
+            // Leave the line terminator out, so that `$` matches at the end of the line
+            let text = line.strip_suffix(b"\n").unwrap_or(&line);
+            let text = text.strip_suffix(b"\r").unwrap_or(text);
+            if regex.is_match(text) {
+                return Ok(true);
+            }
+            if line.ends_with(b"\n") || line.len() < MAX_LINE_LEN {
+                line.clear();
+            } else {
+                // Keep the end of the piece, so that text across the pieces is found
+                line.drain(..line.len() - overlap);
+            }
+        }
+    }
+    /// A custom tag for the files that contain the text.
+    pub fn into_tag(self) -> Tag {
+        let what = if self.regex {
+            "match the regular expression"
+        } else {
+            "contain"
+        };
+        let mut desc = format!(
+            "A custom tag which includes files whose contents {what} {:?}.",
+            self.text
+        );
+        if self.ignore_case {
+            desc += " Letter case is ignored.";
+        }
+        desc += &format!(
+            " Files over {} are skipped",
+            self.max_size.get_appropriate_unit(true)
+        );
+        desc += if self.binary {
+            "."
+        } else {
+            ", and so are binary files."
+        };
+        Tag {
+            name: format!("🔍 {}", self.text),
+            desc,
+            basis: Base::Content(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentSearch;
+    use crate::lib::testing::test_dir;
+    use byte_unit::Byte;
+
+    #[test]
+    fn search_text() {
+        let path = test_dir("content").join("app.log");
+        std::fs::write(&path, "INFO started\r\nFATAL out of memory\n").unwrap();
+        assert!(ContentSearch::new("FATAL", false, false).is(&path).unwrap());
+        assert!(!ContentSearch::new("fatal", false, false).is(&path).unwrap());
+        assert!(ContentSearch::new("fatal", false, true).is(&path).unwrap());
+        assert!(!ContentSearch::new("started.FATAL", true, false)
+            .is(&path)
+            .unwrap());
+        assert!(ContentSearch::new(r"^FATAL\b", true, false)
+            .is(&path)
+            .unwrap());
+        assert!(ContentSearch::new(r"started$", true, false)
+            .is(&path)
+            .unwrap());
+        assert!(ContentSearch::new(r"memory$", true, false)
+            .is(&path)
+            .unwrap());
+        // Literal text is not a regular expression
+        assert!(!ContentSearch::new("^FATAL", false, false)
+            .is(&path)
+            .unwrap());
+        assert!(ContentSearch::new("(", true, false).validate().is_err());
+        assert!(!ContentSearch::new("FATAL", false, false)
+            .is(&test_dir("content"))
+            .unwrap());
+    }
+
+    #[test]
+    fn search_long_lines() {
+        let path = test_dir("content").join("minified.js");
+        let mut text = "x".repeat(super::MAX_LINE_LEN * 2);
+        text.replace_range(super::MAX_LINE_LEN - 3..super::MAX_LINE_LEN + 3, "FATAL!");
+        std::fs::write(&path, &text).unwrap();
+        assert!(ContentSearch::new("FATAL!", false, false)
+            .is(&path)
+            .unwrap());
+        assert!(ContentSearch::new("x$", true, false).is(&path).unwrap());
+        assert!(!ContentSearch::new("FATAL?", false, false)
+            .is(&path)
+            .unwrap());
+    }
+
+    #[test]
+    fn skip_files() {
+        let path = test_dir("content").join("data.bin");
+        std::fs::write(&path, b"\x00\x01FATAL\x02").unwrap();
+        let mut search = ContentSearch::new("FATAL", false, false);
+        assert!(!search.is(&path).unwrap());
+        search.binary = true;
+        assert!(search.is(&path).unwrap());
+        search.max_size = Byte::from_bytes(4);
+        assert!(!search.is(&path).unwrap());
+    }
+}
@@ -0,0 +1,293 @@
+//! Conditions on the dimensions of images, read from their headers without decoding the pixels.
+use std::path::Path;
+
+use imagesize::ImageError;
+
+use super::{photo_info, Base, Tag};
+
+/// How far the aspect ratio of an image may be from the wanted one, relatively.
+///
+/// Screens such as 1366×768 are only close to 16/9.
+const RATIO_TOLERANCE: f64 = 0.01;
+
+/// The size of an image as it is shown, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Dimensions {
+    pub fn megapixels(&self) -> f64 {
+        f64::from(self.width) * f64::from(self.height) / 1_000_000.
+    }
+    /// Whether the width divided by the height is close to `width / height`.
+    pub fn is_ratio(&self, width: u32, height: u32) -> bool {
+        if self.height == 0 || height == 0 {
+            return false;
+        }
+        let ratio = f64::from(self.width) / f64::from(self.height);
+        let wanted = f64::from(width) / f64::from(height);
+        (ratio / wanted - 1.).abs() <= RATIO_TOLERANCE
+    }
+    pub fn is_portrait(&self) -> bool {
+        self.height > self.width
+    }
+    pub fn is_landscape(&self) -> bool {
+        self.width > self.height
+    }
+}
+
+/// Read the dimensions of an image from its header.
+///
+/// Photos that the camera stored on their side are turned as their EXIF orientation tells,
+/// so a photo taken holding the camera upright is a portrait.
+/// Folders and the files that are not images have no dimensions.
+pub fn image_dimensions(path: &Path) -> anyhow::Result<Option<Dimensions>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let size = match imagesize::size(path) {
+        Ok(size) => size,
+        Err(ImageError::IoError(e)) if e.kind() != std::io::ErrorKind::UnexpectedEof => {
+            return Err(e.into())
+        }
+        Err(_) => return Ok(None),
+    };
+    let (width, height) = (u32::try_from(size.width)?, u32::try_from(size.height)?);
+    let turned = matches!(photo_info(path), Ok(info) if matches!(info.orientation, Some(5..=8)));
+    Ok(Some(if turned {
+        Dimensions {
+            width: height,
+            height: width,
+        }
+    } else {
+        Dimensions { width, height }
+    }))
+}
+
+/// Ways to compare the dimensions offered for custom tags.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DimensionMatch {
+    WiderThan,
+    NarrowerThan,
+    TallerThan,
+    ShorterThan,
+    MoreMegapixels,
+    FewerMegapixels,
+    AspectRatio,
+    Portrait,
+    Landscape,
+}
+
+impl DimensionMatch {
+    pub const ALL: [DimensionMatch; 9] = [
+        DimensionMatch::WiderThan,
+        DimensionMatch::NarrowerThan,
+        DimensionMatch::TallerThan,
+        DimensionMatch::ShorterThan,
+        DimensionMatch::MoreMegapixels,
+        DimensionMatch::FewerMegapixels,
+        DimensionMatch::AspectRatio,
+        DimensionMatch::Portrait,
+        DimensionMatch::Landscape,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DimensionMatch::WiderThan => "Wider than",
+            DimensionMatch::NarrowerThan => "Narrower than",
+            DimensionMatch::TallerThan => "Taller than",
+            DimensionMatch::ShorterThan => "Shorter than",
+            DimensionMatch::MoreMegapixels => "More megapixels than",
+            DimensionMatch::FewerMegapixels => "Fewer megapixels than",
+            DimensionMatch::AspectRatio => "Aspect ratio",
+            DimensionMatch::Portrait => "Portrait",
+            DimensionMatch::Landscape => "Landscape",
+        }
+    }
+}
+
+/// Parse an aspect ratio such as `16/9`.
+pub fn parse_ratio(s: &str) -> anyhow::Result<(u32, u32)> {
+    s.split_once('/')
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .ok_or_else(|| anyhow::anyhow!("{s:?} is not an aspect ratio such as 16/9"))
+}
+
+/// A custom tag that compares the dimensions of images with the value
+/// in one of the ways of [`DimensionMatch`]. Portrait and landscape do not use the value.
+pub fn custom_dimension_tag(kind: DimensionMatch, value: &str) -> anyhow::Result<Tag> {
+    let value = value.trim();
+    let pixels = || -> anyhow::Result<u32> {
+        value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{value:?} is not a number of pixels"))
+    };
+    let megapixels = || -> anyhow::Result<f64> {
+        value
+            .parse()
+            .ok()
+            .filter(|megapixels: &f64| megapixels.is_finite() && *megapixels >= 0.)
+            .ok_or_else(|| anyhow::anyhow!("{value:?} is not a number of megapixels"))
+    };
+    let (name, basis, what) = match kind {
+        DimensionMatch::WiderThan => {
+            let width = pixels()?;
+            (
+                format!("↔️ > {width}px"),
+                Base::WidthGT(width),
+                format!("wider than {width} pixels"),
+            )
+        }
+        DimensionMatch::NarrowerThan => {
+            let width = pixels()?;
+            (
+                format!("↔️ < {width}px"),
+                Base::WidthLT(width),
+                format!("narrower than {width} pixels"),
+            )
+        }
+        DimensionMatch::TallerThan => {
+            let height = pixels()?;
+            (
+                format!("↕️ > {height}px"),
+                Base::HeightGT(height),
+                format!("taller than {height} pixels"),
+            )
+        }
+        DimensionMatch::ShorterThan => {
+            let height = pixels()?;
+            (
+                format!("↕️ < {height}px"),
+                Base::HeightLT(height),
+                format!("shorter than {height} pixels"),
+            )
+        }
+        DimensionMatch::MoreMegapixels => {
+            let megapixels = megapixels()?;
+            (
+                format!("🔍 > {megapixels}MP"),
+                Base::MegapixelsGT(megapixels),
+                format!("with more than {megapixels} megapixels"),
+            )
+        }
+        DimensionMatch::FewerMegapixels => {
+            let megapixels = megapixels()?;
+            (
+                format!("🔍 < {megapixels}MP"),
+                Base::MegapixelsLT(megapixels),
+                format!("with fewer than {megapixels} megapixels"),
+            )
+        }
+        DimensionMatch::AspectRatio => {
+            let (width, height) = parse_ratio(value)?;
+            (
+                format!("📐 {width}/{height}"),
+                Base::AspectRatio(width, height),
+                format!("with the aspect ratio {width}/{height}"),
+            )
+        }
+        DimensionMatch::Portrait => (
+            "📱 Portrait".to_owned(),
+            Base::IsPortrait,
+            "taller than wide".to_owned(),
+        ),
+        DimensionMatch::Landscape => (
+            "🖥️ Landscape".to_owned(),
+            Base::IsLandscape,
+            "wider than tall".to_owned(),
+        ),
+    };
+    Ok(Tag {
+        name,
+        desc: format!("A custom tag which includes images {what}."),
+        basis,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{custom_dimension_tag, image_dimensions, parse_ratio, DimensionMatch, Dimensions};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Base, Item, Tag, TagExpr};
+    use std::path::Path;
+
+    /// Write the header of a PNG file, which is all that is read.
+    fn write_png(path: &Path, width: u32, height: u32) {
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        png.extend_from_slice(&width.to_be_bytes());
+        png.extend_from_slice(&height.to_be_bytes());
+        png.extend_from_slice(&[8, 6, 0, 0, 0]);
+        std::fs::write(path, png).unwrap();
+    }
+
+    #[test]
+    fn read() {
+        let path = test_dir("dimensions").join("wallpaper.png");
+        write_png(&path, 2560, 1440);
+        let dimensions = image_dimensions(&path).unwrap().unwrap();
+        assert_eq!(
+            dimensions,
+            Dimensions {
+                width: 2560,
+                height: 1440
+            }
+        );
+        assert!(dimensions.is_ratio(16, 9));
+        assert!(!dimensions.is_ratio(9, 16));
+        assert!(Dimensions {
+            width: 1366,
+            height: 768
+        }
+        .is_ratio(16, 9));
+        assert!(dimensions.is_landscape());
+        assert!(!dimensions.is_portrait());
+        let text = test_dir("dimensions").join("text.txt");
+        std::fs::write(&text, "text").unwrap();
+        assert_eq!(image_dimensions(&text).unwrap(), None);
+        assert_eq!(image_dimensions(&test_dir("dimensions")).unwrap(), None);
+    }
+
+    #[test]
+    fn conditions() {
+        let path = test_dir("dimensions").join("screenshot.png");
+        write_png(&path, 1080, 2400);
+        let mut item = Item::new(&path).unwrap();
+        let mut is = |basis: Base| basis.is(&mut item).unwrap();
+        assert!(is(Base::WidthLT(1920)));
+        assert!(!is(Base::WidthGT(1920)));
+        assert!(is(Base::HeightGT(1920)));
+        assert!(is(Base::MegapixelsGT(2.5)));
+        assert!(!is(Base::MegapixelsLT(2.5)));
+        assert!(is(Base::AspectRatio(9, 20)));
+        assert!(is(Base::IsPortrait));
+        assert!(is(custom_dimension_tag(DimensionMatch::Portrait, "")
+            .unwrap()
+            .basis));
+        assert!(!is(custom_dimension_tag(DimensionMatch::Landscape, "")
+            .unwrap()
+            .basis));
+        assert_eq!(parse_ratio("16/9").unwrap(), (16, 9));
+        assert!(parse_ratio("16:9").is_err());
+        assert!(custom_dimension_tag(DimensionMatch::WiderThan, "wide").is_err());
+    }
+
+    #[test]
+    fn not_images() {
+        let text = test_dir("dimensions").join("notes.txt");
+        std::fs::write(&text, "notes").unwrap();
+        for path in [text, test_dir("dimensions")] {
+            let mut item = Item::new(&path).unwrap();
+            assert!(!Base::IsPortrait.is(&mut item).unwrap());
+            assert!(!Base::WidthLT(100).is(&mut item).unwrap());
+            let portrait = Tag {
+                name: String::new(),
+                desc: String::new(),
+                basis: Base::IsPortrait,
+            };
+            assert!(TagExpr::new(portrait, false).is(&mut item).unwrap());
+        }
+    }
+}
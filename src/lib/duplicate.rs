@@ -0,0 +1,235 @@
+//! Detection of files with the same content.
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::cache::make_room;
+
+/// How long the sizes of the files in a folder are trusted when no pass over it starts.
+const SIZES_LIFETIME: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// Hashes of the files that have already been read,
+    /// together with the size and modification time they were computed for.
+    static ref HASHES: Mutex<HashMap<PathBuf, (u64, SystemTime, blake3::Hash)>> =
+        Mutex::new(HashMap::new());
+    /// Files of the folder searched for duplicates last, grouped by their size.
+    static ref SIZES: Mutex<Option<SizeIndex>> = Mutex::new(None);
+}
+
+struct SizeIndex {
+    root: PathBuf,
+    built: Instant,
+    sizes: HashMap<u64, Vec<PathBuf>>,
+}
+
+/// What to do with a file that has the same content as an older one.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Duplicate {
+    /// Move the duplicate to the trash.
+    Trash,
+    /// Replace the duplicate with a hard link to the original,
+    /// so both names stay but the content is only stored once.
+    Hardlink,
+}
+
+impl Duplicate {
+    pub const ALL: [Duplicate; 2] = [Duplicate::Trash, Duplicate::Hardlink];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Duplicate::Trash => "(move to trash)",
+            Duplicate::Hardlink => "(replace with hard link)",
+        }
+    }
+}
+
+/// Find the original of `path` among the files inside `root` and its subfolders.
+///
+/// Files are first compared by size and only then by a BLAKE3 hash of their content.
+/// The oldest of the identical files is considered the original,
+/// so `None` is returned if `path` is the original itself or has no copies.
+/// Folders, empty files and hard links never count as duplicates.
+pub fn original_of(path: &Path, root: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(None);
+    }
+    let candidates = same_size(root, path, metadata.len())?;
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    let expected = hash(path)?;
+    let mut original = (age(&metadata), path.to_owned());
+    for candidate in candidates {
+        // Hard links to the same data do not take extra space
+        if matches!(is_same_file(path, &candidate), Ok(true) | Err(_)) {
+            continue;
+        }
+        // Files that disappear or become unreadable while scanning are ignored
+        if let (Ok(metadata), Ok(other)) = (std::fs::metadata(&candidate), hash(&candidate)) {
+            let key = (age(&metadata), candidate);
+            if other == expected && key < original {
+                original = key;
+            }
+        }
+    }
+    Ok((original.1 != path).then_some(original.1))
+}
+
+/// Whether both paths point to the same data on the disk.
+pub fn is_same_file(a: &Path, b: &Path) -> anyhow::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let (a, b) = (std::fs::metadata(a)?, std::fs::metadata(b)?);
+        Ok(a.dev() == b.dev() && a.ino() == b.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(a.canonicalize()? == b.canonicalize()?)
+    }
+}
+
+/// Replace `path` with a hard link to `original`.
+///
+/// The link is created under a temporary name first,
+/// so the file is never lost if linking fails.
+pub fn replace_with_link(path: &Path, original: &Path) -> anyhow::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{path:?} has no file name"))?;
+    let temp = path.with_file_name(format!(".{}.link", file_name.to_string_lossy()));
+    std::fs::hard_link(original, &temp)?;
+    if let Err(e) = std::fs::rename(&temp, path) {
+        std::fs::remove_file(&temp)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Forget the sizes of the files found so far, so the next search for duplicates
+/// looks at the disk again. Called at the start of every pass over a folder.
+pub fn forget_sizes() {
+    *SIZES.lock().expect("unable to aquire mutex") = None;
+}
+
+/// Other files inside `root` with the given size.
+///
+/// The sizes of all files of the folder are collected once and then reused,
+/// instead of walking the folder again for every file.
+fn same_size(root: &Path, path: &Path, size: u64) -> anyhow::Result<Vec<PathBuf>> {
+    let mut index = SIZES.lock().expect("unable to aquire mutex");
+    if !matches!(&*index, Some(index) if index.root == root && index.built.elapsed() < SIZES_LIFETIME)
+    {
+        let mut sizes = HashMap::new();
+        collect_sizes(root, &mut sizes)?;
+        *index = Some(SizeIndex {
+            root: root.to_owned(),
+            built: Instant::now(),
+            sizes,
+        });
+    }
+    Ok(index
+        .as_ref()
+        .and_then(|index| index.sizes.get(&size))
+        .into_iter()
+        .flatten()
+        .filter(|candidate| *candidate != path)
+        .cloned()
+        .collect())
+}
+
+fn collect_sizes(dir: &Path, sizes: &mut HashMap<u64, Vec<PathBuf>>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)?.filter_map(|res| res.ok()) {
+        let entry_path = entry.path();
+        match entry.file_type() {
+            Ok(tp) if tp.is_dir() => {
+                // Unreadable subfolders should not hide duplicates in the rest of the tree
+                let _ = collect_sizes(&entry_path, sizes);
+            }
+            Ok(tp) if tp.is_file() => {
+                if let Ok(metadata) = entry.metadata() {
+                    sizes.entry(metadata.len()).or_default().push(entry_path);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn age(metadata: &std::fs::Metadata) -> SystemTime {
+    metadata
+        .created()
+        .or_else(|_| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Hash the content of the file, reusing the previous result if the file has not changed.
+fn hash(path: &Path) -> anyhow::Result<blake3::Hash> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?;
+    if let Ok(hashes) = HASHES.lock() {
+        if let Some((size, time, hash)) = hashes.get(path) {
+            if *size == metadata.len() && *time == modified {
+                return Ok(*hash);
+            }
+        }
+    }
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    let hash = hasher.finalize();
+    if let Ok(mut hashes) = HASHES.lock() {
+        make_room(&mut hashes);
+        hashes.insert(path.to_owned(), (metadata.len(), modified, hash));
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_same_file, original_of, replace_with_link};
+    use crate::lib::testing::test_dir;
+
+    #[test]
+    fn find_original() {
+        let dir = test_dir("duplicate").join("test1");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        let original = dir.join("file.pdf");
+        let copy = dir.join("nested").join("file (1).pdf");
+        let other = dir.join("other.pdf");
+        std::fs::write(&original, "content").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&copy, "content").unwrap();
+        std::fs::write(&other, "CONTENT").unwrap();
+        assert_eq!(original_of(&copy, &dir).unwrap(), Some(original.clone()));
+        assert_eq!(original_of(&original, &dir).unwrap(), None);
+        assert_eq!(original_of(&other, &dir).unwrap(), None);
+    }
+
+    #[test]
+    fn hard_link() {
+        let original = test_dir("duplicate").join("test2.txt");
+        let copy = test_dir("duplicate").join("test2 (1).txt");
+        if copy.exists() {
+            std::fs::remove_file(&copy).unwrap();
+        }
+        std::fs::write(&original, "content").unwrap();
+        std::fs::write(&copy, "content").unwrap();
+        assert!(!is_same_file(&original, &copy).unwrap());
+        replace_with_link(&copy, &original).unwrap();
+        assert!(is_same_file(&original, &copy).unwrap());
+    }
+}
@@ -1,19 +1,60 @@
-use super::TagExpr;
-use crate::{fs::read_path, log::LogEntry};
-use fs_extra::dir::CopyOptions;
+use super::{
+    compress, copy_tracked, deserialize_conflict, deserialize_tag_expr, extract, forget_accounts,
+    forget_sizes, identical, is_archive_name, original_of, remove_path, replace_with_link,
+    temp_path, verified_move, ArchiveFormat, Base, CommandLine, Conflict, Duplicate, FileState,
+    Item, Original, Progress, TagExpr, TagLibrary, Template, Walk,
+};
+use crate::{
+    fs::walk_path,
+    log::{History, LogEntry},
+};
+use anyhow::Context;
+use byte_unit::Byte;
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Event {
+    #[serde(deserialize_with = "deserialize_tag_expr")]
     expr: TagExpr,
     tp: EventType,
+    #[serde(default)]
+    walk: Walk,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum EventType {
-    Copy { target: PathBuf, overwrite: bool },
-    Move { target: PathBuf, overwrite: bool },
+    Copy {
+        target: PathBuf,
+        #[serde(alias = "overwrite", deserialize_with = "deserialize_conflict")]
+        conflict: Conflict,
+    },
+    Move {
+        target: PathBuf,
+        #[serde(alias = "overwrite", deserialize_with = "deserialize_conflict")]
+        conflict: Conflict,
+        /// Check the copy before removing the original when moving to another file system.
+        #[serde(default)]
+        verify: bool,
+    },
+    Rename {
+        template: Template,
+    },
+    Command {
+        command: CommandLine,
+    },
+    Compress {
+        target: PathBuf,
+        format: ArchiveFormat,
+        original: Original,
+    },
+    Extract {
+        target: PathBuf,
+    },
+    Dedup {
+        action: Duplicate,
+    },
     Trash,
 }
 
@@ -22,6 +63,11 @@ impl Event {
         match &self.tp {
             EventType::Copy { .. } => "Copy",
             EventType::Move { .. } => "Move",
+            EventType::Rename { .. } => "Rename",
+            EventType::Command { .. } => "Run",
+            EventType::Compress { .. } => "Compress",
+            EventType::Extract { .. } => "Extract",
+            EventType::Dedup { .. } => "Deduplicate",
             EventType::Trash => "Trash",
         }
     }
@@ -29,53 +75,137 @@ impl Event {
         match &self.tp {
             EventType::Copy { .. } => "edit-copy-symbolic",
             EventType::Move { .. } => "go-jump-symbolic",
+            EventType::Rename { .. } => "insert-text-symbolic",
+            EventType::Command { .. } => "utilities-terminal-symbolic",
+            EventType::Compress { .. } => "package-x-generic-symbolic",
+            EventType::Extract { .. } => "folder-open-symbolic",
+            EventType::Dedup { .. } => "edit-select-all-symbolic",
             EventType::Trash => "user-trash-symbolic",
         }
     }
     pub fn vars(&self) -> Vec<Var> {
-        match &self.tp {
-            EventType::Copy { target, overwrite } => {
-                let mut vars = vec![
-                    Var::String {
-                        label: "Copy".into(),
-                        css_class: Some("bold"),
-                    },
-                    Var::TagExpr(self.expr.clone()),
-                    Var::String {
-                        label: "to".into(),
-                        css_class: Some("opaque"),
-                    },
-                    Var::Path(target.into()),
-                ];
-                if *overwrite {
-                    vars.push(Var::String {
-                        label: "(overwrite)".into(),
-                        css_class: Some("opaque"),
-                    });
-                }
-                vars
-            }
-            EventType::Move { target, overwrite } => {
-                let mut vars = vec![
-                    Var::String {
-                        label: "Move".into(),
-                        css_class: Some("bold"),
-                    },
-                    Var::TagExpr(self.expr.clone()),
-                    Var::String {
-                        label: "to".into(),
-                        css_class: Some("opaque"),
-                    },
-                    Var::Path(target.into()),
-                ];
-                if *overwrite {
-                    vars.push(Var::String {
-                        label: "(overwrite)".into(),
-                        css_class: Some("opaque"),
-                    });
-                }
-                vars
-            }
+        let mut vars = match &self.tp {
+            EventType::Copy { target, conflict } => vec![
+                Var::String {
+                    label: "Copy".into(),
+                    css_class: Some("bold"),
+                },
+                Var::TagExpr(self.expr.clone()),
+                Var::String {
+                    label: "to".into(),
+                    css_class: Some("opaque"),
+                },
+                Var::Path(target.into()),
+                Var::Choice {
+                    key: "conflict",
+                    options: Conflict::ALL.iter().map(|c| c.label()).collect(),
+                    selected: Conflict::ALL.iter().position(|c| c == conflict).unwrap(),
+                },
+            ],
+            EventType::Move {
+                target,
+                conflict,
+                verify,
+            } => vec![
+                Var::String {
+                    label: "Move".into(),
+                    css_class: Some("bold"),
+                },
+                Var::TagExpr(self.expr.clone()),
+                Var::String {
+                    label: "to".into(),
+                    css_class: Some("opaque"),
+                },
+                Var::Path(target.into()),
+                Var::Choice {
+                    key: "conflict",
+                    options: Conflict::ALL.iter().map(|c| c.label()).collect(),
+                    selected: Conflict::ALL.iter().position(|c| c == conflict).unwrap(),
+                },
+                Var::Choice {
+                    key: "verify",
+                    options: vec!["(without verification)", "(verify copies)"],
+                    selected: *verify as usize,
+                },
+            ],
+            EventType::Rename { template } => vec![
+                Var::String {
+                    label: "Rename".into(),
+                    css_class: Some("bold"),
+                },
+                Var::TagExpr(self.expr.clone()),
+                Var::String {
+                    label: "to".into(),
+                    css_class: Some("opaque"),
+                },
+                Var::Template(template.clone()),
+            ],
+            EventType::Command { command } => vec![
+                Var::String {
+                    label: "Run".into(),
+                    css_class: Some("bold"),
+                },
+                Var::Command(command.clone()),
+                Var::String {
+                    label: "on".into(),
+                    css_class: Some("opaque"),
+                },
+                Var::TagExpr(self.expr.clone()),
+            ],
+            EventType::Compress {
+                target,
+                format,
+                original,
+            } => vec![
+                Var::String {
+                    label: "Compress".into(),
+                    css_class: Some("bold"),
+                },
+                Var::TagExpr(self.expr.clone()),
+                Var::String {
+                    label: "to".into(),
+                    css_class: Some("opaque"),
+                },
+                Var::Choice {
+                    key: "format",
+                    options: ArchiveFormat::ALL.iter().map(|f| f.label()).collect(),
+                    selected: ArchiveFormat::ALL.iter().position(|f| f == format).unwrap(),
+                },
+                Var::String {
+                    label: "in".into(),
+                    css_class: Some("opaque"),
+                },
+                Var::Path(target.into()),
+                Var::Choice {
+                    key: "original",
+                    options: Original::ALL.iter().map(|o| o.label()).collect(),
+                    selected: Original::ALL.iter().position(|o| o == original).unwrap(),
+                },
+            ],
+            EventType::Extract { target } => vec![
+                Var::String {
+                    label: "Extract".into(),
+                    css_class: Some("bold"),
+                },
+                Var::TagExpr(self.expr.clone()),
+                Var::String {
+                    label: "into".into(),
+                    css_class: Some("opaque"),
+                },
+                Var::Path(target.into()),
+            ],
+            EventType::Dedup { action } => vec![
+                Var::String {
+                    label: "Deduplicate".into(),
+                    css_class: Some("bold"),
+                },
+                Var::TagExpr(self.expr.clone()),
+                Var::Choice {
+                    key: "duplicate",
+                    options: Duplicate::ALL.iter().map(|d| d.label()).collect(),
+                    selected: Duplicate::ALL.iter().position(|d| d == action).unwrap(),
+                },
+            ],
             EventType::Trash => vec![
                 Var::String {
                     label: "Trash".into(),
@@ -83,29 +213,89 @@ impl Event {
                 },
                 Var::TagExpr(self.expr.clone()),
             ],
-        }
+        };
+        vars.push(Var::Walk(self.walk.clone()));
+        vars
     }
     pub fn copy() -> Self {
         Event {
             expr: TagExpr::default(),
+            walk: Walk::default(),
             tp: EventType::Copy {
                 target: dirs::home_dir().unwrap(),
-                overwrite: false,
+                conflict: Conflict::Skip,
             },
         }
     }
     pub fn mv() -> Self {
         Event {
             expr: TagExpr::default(),
+            walk: Walk::default(),
             tp: EventType::Move {
                 target: dirs::home_dir().unwrap(),
-                overwrite: false,
+                conflict: Conflict::Skip,
+                verify: true,
+            },
+        }
+    }
+    pub fn rename() -> Self {
+        Event {
+            expr: TagExpr::default(),
+            walk: Walk::default(),
+            tp: EventType::Rename {
+                template: Template::new("{stem}-{created:%Y-%m-%d}{counter}.{ext}"),
+            },
+        }
+    }
+    pub fn command() -> Self {
+        Event {
+            expr: TagExpr::default(),
+            walk: Walk::default(),
+            tp: EventType::Command {
+                command: CommandLine::default(),
+            },
+        }
+    }
+    pub fn compress() -> Self {
+        Event {
+            expr: TagExpr::default(),
+            walk: Walk::default(),
+            tp: EventType::Compress {
+                target: PathBuf::from("{dir}"),
+                format: ArchiveFormat::Zip,
+                original: Original::Keep,
+            },
+        }
+    }
+    pub fn extract() -> Self {
+        Event {
+            expr: TagExpr::default(),
+            walk: Walk::default(),
+            tp: EventType::Extract {
+                target: PathBuf::from("{dir}"),
+            },
+        }
+    }
+    pub fn dedup(library: &TagLibrary) -> Self {
+        Event {
+            expr: TagExpr::new(
+                library
+                    .tags()
+                    .find(|tag| tag.basis == Base::IsDuplicate)
+                    .cloned()
+                    .unwrap_or_default(),
+                true,
+            ),
+            walk: Walk::default(),
+            tp: EventType::Dedup {
+                action: Duplicate::Trash,
             },
         }
     }
     pub fn trash() -> Self {
         Event {
             expr: TagExpr::default(),
+            walk: Walk::default(),
             tp: EventType::Trash,
         }
     }
@@ -113,43 +303,346 @@ impl Event {
         match &mut self.tp {
             EventType::Copy { target, .. } => *target = p,
             EventType::Move { target, .. } => *target = p,
-            EventType::Trash => unreachable!(),
+            EventType::Compress { target, .. } => *target = p,
+            EventType::Extract { target } => *target = p,
+            EventType::Rename { .. }
+            | EventType::Command { .. }
+            | EventType::Dedup { .. }
+            | EventType::Trash => unreachable!(),
+        }
+    }
+    pub fn set_template(&mut self, t: Template) {
+        match &mut self.tp {
+            EventType::Rename { template } => *template = t,
+            EventType::Copy { .. }
+            | EventType::Move { .. }
+            | EventType::Command { .. }
+            | EventType::Compress { .. }
+            | EventType::Extract { .. }
+            | EventType::Dedup { .. }
+            | EventType::Trash => unreachable!(),
         }
     }
+    pub fn set_command(&mut self, c: CommandLine) {
+        match &mut self.tp {
+            EventType::Command { command } => *command = c,
+            EventType::Copy { .. }
+            | EventType::Move { .. }
+            | EventType::Rename { .. }
+            | EventType::Compress { .. }
+            | EventType::Extract { .. }
+            | EventType::Dedup { .. }
+            | EventType::Trash => unreachable!(),
+        }
+    }
+    /// Select one of the options of a `Var::Choice` with the given key.
+    pub fn set_choice(&mut self, key: &str, selected: usize) {
+        match (&mut self.tp, key) {
+            (EventType::Copy { conflict, .. } | EventType::Move { conflict, .. }, "conflict") => {
+                if let Some(c) = Conflict::ALL.get(selected) {
+                    *conflict = *c;
+                }
+            }
+            (EventType::Move { verify, .. }, "verify") => *verify = selected == 1,
+            (EventType::Compress { format, .. }, "format") => {
+                if let Some(f) = ArchiveFormat::ALL.get(selected) {
+                    *format = *f;
+                }
+            }
+            (EventType::Compress { original, .. }, "original") => {
+                if let Some(o) = Original::ALL.get(selected) {
+                    *original = *o;
+                }
+            }
+            (EventType::Dedup { action }, "duplicate") => {
+                if let Some(d) = Duplicate::ALL.get(selected) {
+                    *action = *d;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    pub fn set_walk(&mut self, w: Walk) {
+        self.walk = w;
+    }
     pub fn tag_expr(&self) -> &TagExpr {
         &self.expr
     }
     pub fn tag_expr_mut(&mut self) -> &mut TagExpr {
         &mut self.expr
     }
+    /// Whether the actions of the event can be reverted.
+    pub fn can_undo(&self) -> bool {
+        matches!(
+            self.tp,
+            EventType::Copy { .. }
+                | EventType::Move { .. }
+                | EventType::Rename { .. }
+                | EventType::Dedup {
+                    action: Duplicate::Trash
+                }
+                | EventType::Trash
+        )
+    }
+    /// Revert the action that the event has performed on `file` at `time`,
+    /// where `result` is the path the action has produced.
+    ///
+    /// Nothing is changed if the files are no longer in the state the action has left them in.
+    /// Returns the path the file is restored to.
+    pub fn undo(
+        &self,
+        file: &Path,
+        result: Option<&Path>,
+        state: Option<&FileState>,
+        time: DateTime<Local>,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        match &self.tp {
+            EventType::Copy { .. } => {
+                let copy = result.with_context(|| "The path of the copy is unknown")?;
+                if copy.symlink_metadata().is_err() {
+                    anyhow::bail!("{copy:?} no longer exists");
+                }
+                if !file.exists() {
+                    anyhow::bail!("{file:?} no longer exists, so the copy is the only one left");
+                }
+                if !identical(file, copy)? {
+                    anyhow::bail!("{copy:?} has changed since it was copied");
+                }
+                if copy.is_dir() {
+                    std::fs::remove_dir_all(copy)?;
+                } else {
+                    std::fs::remove_file(copy)?;
+                }
+                Ok(None)
+            }
+            EventType::Move { .. } | EventType::Rename { .. } => {
+                let moved = result.with_context(|| "The new path of the file is unknown")?;
+                if moved.symlink_metadata().is_err() {
+                    anyhow::bail!("{moved:?} no longer exists");
+                }
+                if state.is_some_and(|state| FileState::of(moved).ok().as_ref() != Some(state)) {
+                    anyhow::bail!("{moved:?} has changed since it was moved");
+                }
+                if file.symlink_metadata().is_ok() {
+                    anyhow::bail!("{file:?} is taken by another file");
+                }
+                if let Some(parent) = file.parent() {
+                    create_target_dir(parent)?;
+                }
+                transfer(moved, file, false, true, true, &Progress::default())?;
+                Ok(Some(file.to_owned()))
+            }
+            EventType::Dedup {
+                action: Duplicate::Trash,
+            }
+            | EventType::Trash => {
+                if file.symlink_metadata().is_ok() {
+                    anyhow::bail!("{file:?} is taken by another file");
+                }
+                restore_from_trash(file, time)?;
+                Ok(Some(file.to_owned()))
+            }
+            _ => anyhow::bail!("{} events cannot be undone", self.name()),
+        }
+    }
+    /// Find the items inside the folder that the event applies to.
+    ///
+    /// Items inside a folder that is a candidate itself are left out,
+    /// as they are handled together with the folder.
+    pub fn candidates(&self, path: impl AsRef<Path>) -> anyhow::Result<Vec<Item>> {
+        forget_sizes();
+        forget_accounts();
+        let mut items: Vec<Item> = Vec::new();
+        for mut item in walk_path(path, &self.walk)? {
+            if items
+                .iter()
+                .any(|parent| item.path().starts_with(parent.path()))
+            {
+                continue;
+            }
+            if let Ok(true) = self.tag_expr().is(&mut item) {
+                items.push(item);
+            }
+        }
+        Ok(items)
+    }
+    /// Find out what the event would do to the items inside the folder
+    /// without changing anything on the disk.
+    ///
+    /// Every action is planned against the current state of the disk,
+    /// so collisions between the results of the event itself are not predicted.
+    pub fn plan(&self, path: impl AsRef<Path>) -> anyhow::Result<Vec<Action>> {
+        Ok(self
+            .candidates(path)?
+            .iter()
+            .map(|item| {
+                let (dest, outcome, details) = match self.plan_item(item) {
+                    Ok(planned) => planned,
+                    Err(e) => (None, Planned::Fail(e.to_string()), None),
+                };
+                Action {
+                    event: self.name().to_owned(),
+                    icon_name: self.icon_name().to_owned(),
+                    file: item.path().to_owned(),
+                    dest,
+                    outcome,
+                    details,
+                }
+            })
+            .collect())
+    }
+    fn plan_item(&self, item: &Item) -> anyhow::Result<(Option<PathBuf>, Planned, Option<String>)> {
+        let path = item.path();
+        let expand = |target: &Path| Template::new(target.to_string_lossy()).expand_path(item);
+        let skipped = |reason: &str| Planned::Skip(reason.to_owned());
+        Ok(match &self.tp {
+            EventType::Copy { target, conflict }
+            | EventType::Move {
+                target, conflict, ..
+            } => {
+                let to = expand(target)?;
+                if to.exists() && !to.is_dir() {
+                    anyhow::bail!("{to:?} is not a directory");
+                }
+                let name = path
+                    .file_name()
+                    .with_context(|| format!("{path:?} has no file name"))?;
+                match conflict.resolve(path, to.join(name))? {
+                    Some((dest, false)) => (Some(dest), Planned::Apply, None),
+                    Some((dest, true)) => (Some(dest), Planned::Overwrite, None),
+                    None => (
+                        Some(to.join(name)),
+                        skipped("The destination already exists"),
+                        None,
+                    ),
+                }
+            }
+            EventType::Rename { template } => match rename_target(item, template) {
+                SkippableResult::Ok(dest) => (Some(dest), Planned::Apply, None),
+                SkippableResult::Skipped => (
+                    None,
+                    skipped("The file already has this name or the name is taken"),
+                    None,
+                ),
+                SkippableResult::Err(e) => return Err(e),
+            },
+            EventType::Command { command } => (None, Planned::Apply, Some(command.line())),
+            EventType::Compress { target, format, .. } => {
+                match archive_target(path, &expand(target)?, *format) {
+                    SkippableResult::Ok(archive) => (Some(archive), Planned::Apply, None),
+                    SkippableResult::Skipped => (
+                        None,
+                        skipped("The file is an archive already or the archive exists"),
+                        None,
+                    ),
+                    SkippableResult::Err(e) => return Err(e),
+                }
+            }
+            EventType::Extract { target } => match extract_target(path, &expand(target)?) {
+                Some((_, dest)) => (Some(dest), Planned::Apply, None),
+                None => (
+                    None,
+                    skipped("The file is not an archive or the folder exists"),
+                    None,
+                ),
+            },
+            EventType::Dedup { action } => {
+                let root = item
+                    .root()
+                    .with_context(|| format!("{path:?} has no parent"))?;
+                match original_of(path, root)? {
+                    Some(original) => {
+                        let details = format!("Duplicate of {}", original.display());
+                        let dest = (*action == Duplicate::Hardlink).then_some(original);
+                        (dest, Planned::Apply, Some(details))
+                    }
+                    None => (None, skipped("The file is not a duplicate"), None),
+                }
+            }
+            EventType::Trash => (None, Planned::Apply, None),
+        })
+    }
+    /// Apply the event to the items inside the folder.
+    ///
+    /// Copies and moves report to `progress` and stop once it is cancelled.
+    /// Items whose copy was cancelled before are left alone, and so are
+    /// the files the event has produced according to its `history`,
+    /// as the rules are applied over and over again.
+    /// Commands are not run again for the files they were run for,
+    /// even if they failed, until the files are modified.
     pub fn execute(
         &self,
         path: impl AsRef<Path>,
+        history: &History,
+        progress: &Progress,
     ) -> anyhow::Result<Vec<SkippableResult<LogEntry>>> {
-        let items = read_path(path)?;
-        let files = items
+        let items = self
+            .candidates(path)?
             .into_iter()
-            .filter_map(|mut item| {
-                if let Ok(is) = self.tag_expr().is(&mut item) {
-                    is
-                } else {
-                    false
-                }
-                .then(|| item)
+            .filter(|item| !progress.was_cancelled(item.path()))
+            .filter(|item| !history.has_produced(item.path()))
+            .filter(|item| {
+                !(matches!(self.tp, EventType::Command { .. }) && history.has_processed(item))
             })
+            .collect::<Vec<_>>();
+        let files = items
+            .iter()
             .map(|item| item.path().to_owned())
             .collect::<Vec<_>>();
-        let (target, results) = match &self.tp {
-            EventType::Copy { target, overwrite } => {
-                (Some(target), copy(&files, target, *overwrite))
-            }
-            EventType::Move { target, overwrite } => (Some(target), mv(&files, target, *overwrite)),
-            EventType::Trash => (None, trash(&files)),
+        let results = match &self.tp {
+            EventType::Copy { target, conflict } => per_target(&items, target, |file, to| {
+                copy(&[file], to, *conflict, progress)
+            }),
+            EventType::Move {
+                target,
+                conflict,
+                verify,
+            } => per_target(&items, target, |file, to| {
+                mv(&[file], to, *conflict, *verify, progress)
+            }),
+            EventType::Rename { template } => rename(&items, template),
+            EventType::Command { command } => run(&items, command),
+            EventType::Compress {
+                target,
+                format,
+                original,
+            } => per_target(&items, target, |file, to| {
+                compress_file(file, to, *format, *original)
+            }),
+            EventType::Extract { target } => per_target(&items, target, extract_file),
+            EventType::Dedup { action } => dedup(&items, *action),
+            EventType::Trash => trash(&files),
         };
         let results = results
             .into_iter()
             .map(|result| match result {
-                SkippableResult::Ok(file) => SkippableResult::Ok(LogEntry::new(self, target, file)),
+                SkippableResult::Ok(outcome) => {
+                    // Copy and move events log the folder the file has ended up in
+                    let target = match &self.tp {
+                        EventType::Copy { .. }
+                        | EventType::Move { .. }
+                        | EventType::Compress { .. }
+                        | EventType::Extract { .. } => {
+                            outcome.result.as_deref().and_then(Path::parent)
+                        }
+                        EventType::Rename { .. }
+                        | EventType::Command { .. }
+                        | EventType::Dedup { .. }
+                        | EventType::Trash => None,
+                    };
+                    SkippableResult::Ok(
+                        LogEntry::new(self, target, &outcome.file)
+                            .with_state(
+                                outcome
+                                    .result
+                                    .as_deref()
+                                    .and_then(|result| FileState::of(result).ok()),
+                            )
+                            .with_result(outcome.result)
+                            .with_details(outcome.details)
+                            .with_failed(outcome.failed),
+                    )
+                }
                 SkippableResult::Skipped => SkippableResult::Skipped,
                 SkippableResult::Err(e) => SkippableResult::Err(e),
             })
@@ -165,6 +658,114 @@ pub enum Var {
     },
     TagExpr(TagExpr),
     Path(PathBuf),
+    Template(Template),
+    Command(CommandLine),
+    /// Which entries of the rule folder the event looks at.
+    Walk(Walk),
+    /// One of a few predefined options.
+    Choice {
+        key: &'static str,
+        options: Vec<&'static str>,
+        selected: usize,
+    },
+}
+
+/// Something an event would do to a single file.
+#[derive(Clone, Debug)]
+pub struct Action {
+    /// Name of the event.
+    pub event: String,
+    pub icon_name: String,
+    /// The file the event would be applied to.
+    pub file: PathBuf,
+    /// Where the file or the result of the event would end up.
+    pub dest: Option<PathBuf>,
+    pub outcome: Planned,
+    /// Additional information about the action.
+    pub details: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Planned {
+    /// The event would be applied.
+    Apply,
+    /// The event would replace an existing file.
+    Overwrite,
+    /// The file would be left as it is, for the given reason.
+    Skip(String),
+    /// The event would fail, for the given reason.
+    Fail(String),
+}
+
+impl Planned {
+    pub fn label(&self) -> &str {
+        match self {
+            Planned::Apply => "Will be applied",
+            Planned::Overwrite => "Will overwrite",
+            Planned::Skip(_) => "Skipped",
+            Planned::Fail(_) => "Will fail",
+        }
+    }
+    /// Why the file would be skipped or the event would fail.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Planned::Apply | Planned::Overwrite => None,
+            Planned::Skip(reason) | Planned::Fail(reason) => Some(reason),
+        }
+    }
+}
+
+/// What has happened to a single file after an event was applied to it.
+#[derive(Debug)]
+pub struct Outcome {
+    /// The file the event was applied to.
+    pub file: PathBuf,
+    /// Path of the file produced by the event, if there is one.
+    pub result: Option<PathBuf>,
+    /// Additional information about the action.
+    pub details: Option<String>,
+    /// Whether the action has not succeeded, with the reason in the details.
+    pub failed: bool,
+}
+
+impl Outcome {
+    fn new(file: impl AsRef<Path>, result: Option<PathBuf>) -> Self {
+        Outcome {
+            file: file.as_ref().to_owned(),
+            result,
+            details: None,
+            failed: false,
+        }
+    }
+    /// An action that has not succeeded, which is still worth a log entry.
+    fn failed(file: impl AsRef<Path>, reason: impl Into<String>) -> Self {
+        Outcome {
+            failed: true,
+            ..Outcome::new(file, None).with_details(reason)
+        }
+    }
+    fn with_details(self, details: impl Into<String>) -> Self {
+        Outcome {
+            details: Some(details.into()),
+            ..self
+        }
+    }
+}
+
+/// Expand `target` for every item and apply `f` to the item and its own target folder.
+fn per_target(
+    items: &[Item],
+    target: &Path,
+    f: impl Fn(&Path, &Path) -> Vec<SkippableResult<Outcome>>,
+) -> Vec<SkippableResult<Outcome>> {
+    let template = Template::new(target.to_string_lossy());
+    items
+        .iter()
+        .flat_map(|item| match template.expand_path(item) {
+            Ok(to) => f(item.path(), &to),
+            Err(e) => vec![SkippableResult::Err(e)],
+        })
+        .collect()
 }
 
 fn copy(
@@ -172,29 +773,56 @@ fn copy(
     files: &[impl AsRef<Path>],
     // Folder to copy files into
     to: impl AsRef<Path>,
-    overwrite: bool,
-) -> Vec<SkippableResult<PathBuf>> {
-    let to = to.as_ref();
+    conflict: Conflict,
+    progress: &Progress,
+) -> Vec<SkippableResult<Outcome>> {
+    transfer_all(files, to.as_ref(), conflict, false, false, progress)
+}
+
+fn mv(
+    // Files to move
+    files: &[impl AsRef<Path>],
+    // Folder to move files into
+    to: impl AsRef<Path>,
+    conflict: Conflict,
+    // Whether to check the copy before removing the original
+    verify: bool,
+    progress: &Progress,
+) -> Vec<SkippableResult<Outcome>> {
+    transfer_all(files, to.as_ref(), conflict, true, verify, progress)
+}
+
+/// Copy or move (if `remove` is set) the files into the folder.
+fn transfer_all(
+    files: &[impl AsRef<Path>],
+    to: &Path,
+    conflict: Conflict,
+    remove: bool,
+    verify: bool,
+    progress: &Progress,
+) -> Vec<SkippableResult<Outcome>> {
     files
         .iter()
         .map(|file| {
             let path = file.as_ref();
-            if let Some(file_name) = path.file_name() {
-                if to.is_dir() {
-                    if !overwrite && to.join(file_name).exists() {
-                        SkippableResult::Skipped
-                    } else {
-                        let options = CopyOptions {
-                            overwrite,
-                            ..CopyOptions::new()
-                        };
-                        match fs_extra::copy_items(&[path], to, &options) {
-                            Ok(_) => SkippableResult::Ok(path.to_owned()),
-                            Err(e) => SkippableResult::Err(e.into()),
+            if progress.is_cancelled() {
+                SkippableResult::Skipped
+            } else if let Some(file_name) = path.file_name() {
+                if let Err(e) = create_target_dir(to) {
+                    SkippableResult::Err(e)
+                } else if to.is_dir() {
+                    match conflict.resolve(path, to.join(file_name)) {
+                        Ok(Some((dest, overwrite))) => {
+                            match transfer(path, &dest, overwrite, remove, verify, progress) {
+                                Ok(()) => SkippableResult::Ok(Outcome::new(path, Some(dest))),
+                                Err(e) => SkippableResult::Err(e),
+                            }
                         }
+                        Ok(None) => SkippableResult::Skipped,
+                        Err(e) => SkippableResult::Err(e),
                     }
                 } else {
-                    SkippableResult::Err(anyhow::anyhow!("{path:?} is not a directory"))
+                    SkippableResult::Err(anyhow::anyhow!("{to:?} is not a directory"))
                 }
             } else {
                 SkippableResult::Err(anyhow::anyhow!("{path:?} has no file name"))
@@ -203,53 +831,305 @@ fn copy(
         .collect()
 }
 
-fn mv(
-    // Files to move
-    files: &[impl AsRef<Path>],
-    // Folder to move files into
-    to: impl AsRef<Path>,
+/// Put the file back from the trash.
+///
+/// If the trash holds several files that were deleted from the same path,
+/// the last one deleted before `time` is restored.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn restore_from_trash(file: &Path, time: DateTime<Local>) -> anyhow::Result<()> {
+    let item = trash::os_limited::list()?
+        .into_iter()
+        .filter(|item| {
+            item.original_parent.join(&item.name) == file
+                // The action is logged right after the file is deleted
+                && item.time_deleted <= time.timestamp() + 1
+        })
+        .max_by_key(|item| item.time_deleted)
+        .with_context(|| format!("{file:?} is no longer in the trash"))?;
+    trash::os_limited::restore_all([item])?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn restore_from_trash(_file: &Path, _time: DateTime<Local>) -> anyhow::Result<()> {
+    anyhow::bail!("Restoring files from the trash is not supported on this platform")
+}
+
+/// Copy or move a single file or folder to exactly `dest`.
+///
+/// With `verify` a move that cannot be done by renaming checks the copy
+/// before the original is removed.
+/// Originals are only removed once everything is copied,
+/// so a cancelled move leaves them untouched.
+fn transfer(
+    from: &Path,
+    dest: &Path,
     overwrite: bool,
-) -> Vec<SkippableResult<PathBuf>> {
-    let to = to.as_ref();
-    files
+    remove: bool,
+    verify: bool,
+    progress: &Progress,
+) -> anyhow::Result<()> {
+    // Renaming is instant, but only works within one file system
+    if remove && !dest.exists() && std::fs::rename(from, dest).is_ok() {
+        return Ok(());
+    }
+    if remove && verify {
+        return verified_move(from, dest, overwrite, progress);
+    }
+    // Copying into an existing folder would merge the two,
+    // so the copy takes the place of the folder once it is complete
+    if overwrite && dest.symlink_metadata().is_ok() && (dest.is_dir() || from.is_dir()) {
+        let temp = temp_path(dest)?;
+        if temp.symlink_metadata().is_ok() {
+            remove_path(&temp)?;
+        }
+        if let Err(e) = copy_tracked(from, &temp, false, false, progress) {
+            let _ = remove_path(&temp);
+            return Err(e);
+        }
+        remove_path(dest)?;
+        std::fs::rename(&temp, dest)?;
+        if remove {
+            remove_path(from)?;
+        }
+        return Ok(());
+    }
+    let existed = dest.exists();
+    if let Err(e) = copy_tracked(from, dest, overwrite, false, progress) {
+        // Files merged into an existing folder cannot be told apart from the old ones
+        if !existed {
+            let _ = remove_path(dest);
+        }
+        return Err(e);
+    }
+    if remove {
+        remove_path(from)?;
+    }
+    Ok(())
+}
+
+fn rename(
+    // Items to rename
+    items: &[Item],
+    // Template for the new file name
+    template: &Template,
+) -> Vec<SkippableResult<Outcome>> {
+    items
         .iter()
-        .map(|file| {
-            let path = file.as_ref();
-            if let Some(file_name) = path.file_name() {
-                if to.is_dir() {
-                    if !overwrite && to.join(file_name).exists() {
-                        SkippableResult::Skipped
-                    } else {
-                        let options = CopyOptions {
-                            overwrite,
-                            ..CopyOptions::new()
-                        };
-                        match fs_extra::move_items(&[path], to, &options) {
-                            Ok(_) => SkippableResult::Ok(path.to_owned()),
-                            Err(e) => SkippableResult::Err(e.into()),
-                        }
-                    }
-                } else {
-                    SkippableResult::Err(anyhow::anyhow!("{path:?} is not a directory"))
+        .map(|item| match rename_target(item, template) {
+            SkippableResult::Ok(new_path) => match std::fs::rename(item.path(), &new_path) {
+                Ok(_) => SkippableResult::Ok(Outcome::new(item.path(), Some(new_path))),
+                Err(e) => SkippableResult::Err(e.into()),
+            },
+            SkippableResult::Skipped => SkippableResult::Skipped,
+            SkippableResult::Err(e) => SkippableResult::Err(e),
+        })
+        .collect()
+}
+
+/// Find the first name produced by the template that is not taken yet.
+///
+/// Files that are already named according to the template are skipped,
+/// so they are not renamed again on every run.
+fn rename_target(item: &Item, template: &Template) -> SkippableResult<PathBuf> {
+    match template.matches_name(item) {
+        Ok(true) => return SkippableResult::Skipped,
+        Ok(false) => {}
+        Err(e) => return SkippableResult::Err(e),
+    }
+    let path = item.path();
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return SkippableResult::Err(anyhow::anyhow!("{path:?} has no parent")),
+    };
+    // Try the names produced by the template until there is no collision
+    for counter in 0..MAX_RENAME_ATTEMPTS {
+        let name = match template.expand(item, counter) {
+            Ok(name) => name,
+            Err(e) => return SkippableResult::Err(e),
+        };
+        if name.is_empty() || name.contains(std::path::MAIN_SEPARATOR) {
+            return SkippableResult::Err(anyhow::anyhow!("{name:?} is not a valid file name"));
+        }
+        let new_path = parent.join(&name);
+        if new_path == path {
+            // The file is already named according to the template
+            return SkippableResult::Skipped;
+        } else if new_path.symlink_metadata().is_err() {
+            return SkippableResult::Ok(new_path);
+        } else if !template.has_counter() {
+            return SkippableResult::Skipped;
+        }
+    }
+    SkippableResult::Err(anyhow::anyhow!("Unable to find a free name for {path:?}"))
+}
+
+/// Failed runs are logged too, so their output is not lost.
+fn run(
+    // Items to run the command for
+    items: &[Item],
+    command: &CommandLine,
+) -> Vec<SkippableResult<Outcome>> {
+    items
+        .iter()
+        .map(|item| {
+            SkippableResult::Ok(match command.run(item) {
+                Ok(output) if output.status.success() => {
+                    Outcome::new(item.path(), None).with_details(output.text())
                 }
-            } else {
-                SkippableResult::Err(anyhow::anyhow!("{path:?} has no file name"))
+                Ok(output) => Outcome::failed(
+                    item.path(),
+                    format!(
+                        "{} ({})\n{}",
+                        command.program(),
+                        output.status,
+                        output.text()
+                    )
+                    .trim(),
+                ),
+                Err(e) => Outcome::failed(item.path(), e.to_string()),
+            })
+        })
+        .collect()
+}
+
+fn compress_file(
+    // File or folder to compress
+    path: &Path,
+    // Folder to put the archive into
+    to: &Path,
+    format: ArchiveFormat,
+    original: Original,
+) -> Vec<SkippableResult<Outcome>> {
+    let archive = match archive_target(path, to, format) {
+        SkippableResult::Ok(archive) => archive,
+        SkippableResult::Skipped => return vec![SkippableResult::Skipped],
+        SkippableResult::Err(e) => return vec![SkippableResult::Err(e)],
+    };
+    let result = create_target_dir(to)
+        .and_then(|_| compress(path, &archive, format))
+        .and_then(|compressed| {
+            match original {
+                Original::Keep => {}
+                Original::Delete if path.is_dir() => std::fs::remove_dir_all(path)?,
+                Original::Delete => std::fs::remove_file(path)?,
+                Original::Trash => trash::delete(path)?,
+            }
+            let details = format!(
+                "{} → {} ({:.0}% of the original size)",
+                Byte::from_bytes(compressed.original_size.into()).get_appropriate_unit(true),
+                Byte::from_bytes(compressed.archive_size.into()).get_appropriate_unit(true),
+                compressed.ratio() * 100.
+            );
+            Ok(Outcome::new(path, Some(compressed.archive)).with_details(details))
+        });
+    vec![match result {
+        Ok(outcome) => SkippableResult::Ok(outcome),
+        Err(e) => SkippableResult::Err(e),
+    }]
+}
+
+/// Path of the archive the file would be packed into.
+fn archive_target(path: &Path, to: &Path, format: ArchiveFormat) -> SkippableResult<PathBuf> {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        None => return SkippableResult::Err(anyhow::anyhow!("{path:?} has no file name")),
+    };
+    let archive = to.join(format!(
+        "{}.{}",
+        file_name.to_string_lossy(),
+        format.extension()
+    ));
+    // Archives are not packed again into the same format
+    if is_archive_name(path, format) || archive.exists() {
+        SkippableResult::Skipped
+    } else {
+        SkippableResult::Ok(archive)
+    }
+}
+
+/// Format of the archive and the folder it would be extracted into.
+fn extract_target(path: &Path, to: &Path) -> Option<(ArchiveFormat, PathBuf)> {
+    // Files that are not archives are skipped
+    let (format, name) = match ArchiveFormat::from_path(path) {
+        Some(recognized) if path.is_file() => recognized,
+        _ => return None,
+    };
+    let dest = to.join(name);
+    (!dest.exists()).then_some((format, dest))
+}
+
+fn extract_file(
+    // Archive to extract
+    path: &Path,
+    // Folder to create the extracted folder in
+    to: &Path,
+) -> Vec<SkippableResult<Outcome>> {
+    let (format, dest) = match extract_target(path, to) {
+        Some(target) => target,
+        None => return vec![SkippableResult::Skipped],
+    };
+    vec![match extract(path, &dest, format) {
+        Ok(count) => SkippableResult::Ok(
+            Outcome::new(path, Some(dest)).with_details(format!("{count} files extracted")),
+        ),
+        Err(e) => SkippableResult::Err(e),
+    }]
+}
+
+fn dedup(
+    // Items to check for duplicates
+    items: &[Item],
+    action: Duplicate,
+) -> Vec<SkippableResult<Outcome>> {
+    items
+        .iter()
+        .map(|item| {
+            let path = item.path();
+            let original = match item.root().map(|root| original_of(path, root)) {
+                Some(Ok(Some(original))) => original,
+                Some(Ok(None)) | None => return SkippableResult::Skipped,
+                Some(Err(e)) => return SkippableResult::Err(e),
+            };
+            let result = match action {
+                Duplicate::Trash => trash::delete(path)
+                    .map(|_| Outcome::new(path, None))
+                    .map_err(anyhow::Error::from),
+                Duplicate::Hardlink => replace_with_link(path, &original)
+                    .map(|_| Outcome::new(path, Some(path.to_owned()))),
+            };
+            match result {
+                Ok(outcome) => SkippableResult::Ok(
+                    outcome.with_details(format!("Duplicate of {}", original.display())),
+                ),
+                Err(e) => SkippableResult::Err(e),
             }
         })
         .collect()
 }
 
+/// How many names the rename event tries before giving up.
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+/// Create the folder (with all of its parents) if it does not exist yet.
+fn create_target_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir.exists() {
+        std::fs::create_dir_all(dir)?;
+    }
+    Ok(())
+}
+
 fn trash(
     // Files to remove
     files: &[impl AsRef<Path>],
-) -> Vec<SkippableResult<PathBuf>> {
+) -> Vec<SkippableResult<Outcome>> {
     files
         .iter()
         .map(|file| {
             let path = file.as_ref();
             if path.exists() {
                 match trash::delete(path) {
-                    Ok(_) => SkippableResult::Ok(path.to_owned()),
+                    Ok(_) => SkippableResult::Ok(Outcome::new(path, None)),
                     Err(e) => SkippableResult::Err(e.into()),
                 }
             } else {
@@ -277,90 +1157,101 @@ impl<T, E: std::error::Error + Send + Sync + 'static> From<Result<T, E>> for Ski
 
 #[cfg(test)]
 mod tests {
-    use crate::lib::SkippableResult;
-
-    use super::{copy, mv, trash};
-    use std::path::PathBuf;
-
-    fn test_dir_a() -> PathBuf {
-        let dir = dirs::desktop_dir().unwrap().join("event-test-a");
-        if !dir.exists() {
-            std::fs::create_dir(&dir).unwrap();
-        }
-        dir
-    }
+    use crate::lib::testing::test_dir;
+    use crate::lib::{
+        Base, Cancelled, Conflict, Duplicate, Event, EventType, FileState, FileType, Item, Planned,
+        Progress, SkippableResult, Tag, TagExpr, Template, Walk,
+    };
 
-    fn test_dir_b() -> PathBuf {
-        let dir = dirs::desktop_dir().unwrap().join("event-test-b");
-        if !dir.exists() {
-            std::fs::create_dir(&dir).unwrap();
-        }
-        dir
-    }
+    use super::{copy, dedup, mv, rename, transfer, trash};
+    use crate::lib::CommandLine;
+    use crate::log::Log;
+    use std::time::{Duration, SystemTime};
 
     #[test]
     fn copy_one() {
-        let from = test_dir_a().join("test1.txt");
+        let from = test_dir("event-a").join("test1.txt");
         if !from.exists() {
             std::fs::File::create(&from).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if to.join("test1.txt").exists() {
             std::fs::remove_file(&to.join("test1.txt")).unwrap();
         }
-        let result = copy(&[&from], to, false);
+        let result = copy(&[&from], to, Conflict::Skip, &Progress::default());
         assert!(from.exists());
         assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
     }
 
     #[test]
     fn copy_skip() {
-        let from = test_dir_a().join("test2.txt");
+        let from = test_dir("event-a").join("test2.txt");
         if !from.exists() {
             std::fs::File::create(&from).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if !to.join("test2.txt").exists() {
             std::fs::File::create(&to.join("test2.txt")).unwrap();
         }
-        let result = copy(&[&from], to, false);
+        let result = copy(&[&from], to, Conflict::Skip, &Progress::default());
         assert!(from.exists());
         assert!(matches!(&result[..], &[SkippableResult::Skipped]));
     }
 
     #[test]
     fn copy_overwrite() {
-        let from = test_dir_a().join("test3.txt");
+        let from = test_dir("event-a").join("test3.txt");
         if !from.exists() {
             std::fs::File::create(&from).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if !to.join("test3.txt").exists() {
             std::fs::File::create(&to.join("test3.txt")).unwrap();
         }
-        let result = copy(&[&from], to, true);
+        let result = copy(&[&from], to, Conflict::Overwrite, &Progress::default());
         assert!(from.exists());
         assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
     }
 
+    #[test]
+    fn copy_overwrite_folder() {
+        let from = test_dir("event-a").join("test28");
+        let to = test_dir("event-b").join("test28");
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        std::fs::write(from.join("new.txt"), "").unwrap();
+        std::fs::write(to.join("old.txt"), "").unwrap();
+        let result = copy(
+            &[&from],
+            test_dir("event-b"),
+            Conflict::Overwrite,
+            &Progress::default(),
+        );
+        assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
+        // The folder is replaced instead of merged
+        assert!(to.join("new.txt").exists());
+        assert!(!to.join("old.txt").exists());
+        assert!(from.join("new.txt").exists());
+    }
+
     #[test]
     fn copy_multiple() {
-        let from1 = test_dir_a().join("test4-1.txt");
+        let from1 = test_dir("event-a").join("test4-1.txt");
         if !from1.exists() {
             std::fs::File::create(&from1).unwrap();
         }
-        let from2 = test_dir_a().join("test4-2.txt");
+        let from2 = test_dir("event-a").join("test4-2.txt");
         if !from2.exists() {
             std::fs::File::create(&from2).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if !to.join("test4-1.txt").exists() {
             std::fs::File::create(&to.join("test4-1.txt")).unwrap();
         }
         if to.join("test4-2.txt").exists() {
             std::fs::remove_file(&to.join("test4-2.txt")).unwrap();
         }
-        let result = copy(&[&from1, &from2], to, false);
+        let result = copy(&[&from1, &from2], to, Conflict::Skip, &Progress::default());
         assert!(from1.exists());
         assert!(from2.exists());
         assert!(matches!(
@@ -371,67 +1262,79 @@ mod tests {
 
     #[test]
     fn mv_one() {
-        let from = test_dir_a().join("test5.txt");
+        let from = test_dir("event-a").join("test5.txt");
         if !from.exists() {
             std::fs::File::create(&from).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if to.join("test5.txt").exists() {
             std::fs::remove_file(&to.join("test5.txt")).unwrap();
         }
-        let result = mv(&[&from], to, false);
+        let result = mv(&[&from], to, Conflict::Skip, false, &Progress::default());
         assert!(!from.exists());
         assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
     }
 
     #[test]
     fn mv_skip() {
-        let from = test_dir_a().join("test6.txt");
+        let from = test_dir("event-a").join("test6.txt");
         if !from.exists() {
             std::fs::File::create(&from).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if !to.join("test6.txt").exists() {
             std::fs::File::create(&to.join("test6.txt")).unwrap();
         }
-        let result = mv(&[&from], to, false);
+        let result = mv(&[&from], to, Conflict::Skip, false, &Progress::default());
         assert!(from.exists());
         assert!(matches!(&result[..], &[SkippableResult::Skipped]));
     }
 
     #[test]
     fn mv_overwrite() {
-        let from = test_dir_a().join("test7.txt");
+        let from = test_dir("event-a").join("test7.txt");
         if !from.exists() {
             std::fs::File::create(&from).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if !to.join("test7.txt").exists() {
             std::fs::File::create(&to.join("test7.txt")).unwrap();
         }
-        let result = mv(&[&from], to, true);
+        let result = mv(
+            &[&from],
+            to,
+            Conflict::Overwrite,
+            true,
+            &Progress::default(),
+        );
         assert!(!from.exists());
         assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
     }
 
     #[test]
     fn mv_multiple() {
-        let from1 = test_dir_a().join("test8-1.txt");
+        let from1 = test_dir("event-a").join("test8-1.txt");
         if !from1.exists() {
             std::fs::File::create(&from1).unwrap();
         }
-        let from2 = test_dir_a().join("test8-2.txt");
+        let from2 = test_dir("event-a").join("test8-2.txt");
         if !from2.exists() {
             std::fs::File::create(&from2).unwrap();
         }
-        let to = test_dir_b();
+        let to = test_dir("event-b");
         if !to.join("test8-1.txt").exists() {
             std::fs::File::create(&to.join("test8-1.txt")).unwrap();
         }
         if to.join("test8-2.txt").exists() {
             std::fs::remove_file(&to.join("test8-2.txt")).unwrap();
         }
-        let result = mv(&[&from1, &from2], to, false);
+        let result = mv(
+            &[&from1, &from2],
+            to,
+            Conflict::Skip,
+            false,
+            &Progress::default(),
+        );
         assert!(from1.exists());
         assert!(!from2.exists());
         assert!(matches!(
@@ -442,7 +1345,7 @@ mod tests {
 
     #[test]
     fn trash_one() {
-        let file = test_dir_a().join("test9.txt");
+        let file = test_dir("event-a").join("test9.txt");
         if !file.exists() {
             std::fs::File::create(&file).unwrap();
         }
@@ -453,11 +1356,11 @@ mod tests {
 
     #[test]
     fn trash_multiple() {
-        let file1 = test_dir_a().join("test10-1.txt");
+        let file1 = test_dir("event-a").join("test10-1.txt");
         if !file1.exists() {
             std::fs::File::create(&file1).unwrap();
         }
-        let file2 = test_dir_a().join("test10-2.txt");
+        let file2 = test_dir("event-a").join("test10-2.txt");
         if !file2.exists() {
             std::fs::File::create(&file2).unwrap();
         }
@@ -469,4 +1372,416 @@ mod tests {
             &[SkippableResult::Ok(_), SkippableResult::Ok(_)]
         ));
     }
+
+    #[test]
+    fn rename_one() {
+        let from = test_dir("event-a").join("test11.txt");
+        if !from.exists() {
+            std::fs::File::create(&from).unwrap();
+        }
+        let to = test_dir("event-a").join("test11-renamed.txt");
+        if to.exists() {
+            std::fs::remove_file(&to).unwrap();
+        }
+        let item = Item::new(&from).unwrap();
+        let result = rename(&[item], &Template::new("{stem}-renamed.{ext}"));
+        assert!(!from.exists());
+        assert!(to.exists());
+        assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
+    }
+
+    #[test]
+    fn rename_counter() {
+        let from = test_dir("event-a").join("test12.txt");
+        if !from.exists() {
+            std::fs::File::create(&from).unwrap();
+        }
+        let taken = test_dir("event-a").join("test12-new.txt");
+        if !taken.exists() {
+            std::fs::File::create(&taken).unwrap();
+        }
+        let to = test_dir("event-a").join("test12-new-1.txt");
+        if to.exists() {
+            std::fs::remove_file(&to).unwrap();
+        }
+        let item = Item::new(&from).unwrap();
+        let result = rename(&[item], &Template::new("{stem}-new{counter}.{ext}"));
+        assert!(!from.exists());
+        assert!(to.exists());
+        assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
+    }
+
+    #[test]
+    fn rename_skip() {
+        let from = test_dir("event-a").join("test13.txt");
+        if !from.exists() {
+            std::fs::File::create(&from).unwrap();
+        }
+        let taken = test_dir("event-a").join("test13-new.txt");
+        if !taken.exists() {
+            std::fs::File::create(&taken).unwrap();
+        }
+        let item = Item::new(&from).unwrap();
+        let result = rename(&[item], &Template::new("{stem}-new.{ext}"));
+        assert!(from.exists());
+        assert!(matches!(&result[..], &[SkippableResult::Skipped]));
+    }
+
+    #[test]
+    fn copy_create_dirs() {
+        let from = test_dir("event-a").join("test14.txt");
+        if !from.exists() {
+            std::fs::File::create(&from).unwrap();
+        }
+        let to = test_dir("event-b").join("test14").join("nested");
+        if to.exists() {
+            std::fs::remove_dir_all(&to).unwrap();
+        }
+        let result = copy(&[&from], &to, Conflict::Skip, &Progress::default());
+        assert!(from.exists());
+        assert!(to.join("test14.txt").exists());
+        assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
+    }
+
+    #[test]
+    fn copy_keep_both() {
+        let from = test_dir("event-a").join("test15.txt");
+        if !from.exists() {
+            std::fs::File::create(&from).unwrap();
+        }
+        let to = test_dir("event-b");
+        if !to.join("test15.txt").exists() {
+            std::fs::File::create(to.join("test15.txt")).unwrap();
+        }
+        if to.join("test15 (1).txt").exists() {
+            std::fs::remove_file(to.join("test15 (1).txt")).unwrap();
+        }
+        let result = copy(&[&from], &to, Conflict::Rename, &Progress::default());
+        assert!(to.join("test15 (1).txt").exists());
+        assert!(matches!(&result[..], &[SkippableResult::Ok(_)]));
+    }
+
+    #[test]
+    fn mv_skip_identical() {
+        let from = test_dir("event-a").join("test16.txt");
+        std::fs::write(&from, "same").unwrap();
+        let to = test_dir("event-b");
+        std::fs::write(to.join("test16.txt"), "same").unwrap();
+        let result = mv(
+            &[&from],
+            &to,
+            Conflict::SkipIdentical,
+            false,
+            &Progress::default(),
+        );
+        assert!(from.exists());
+        assert!(matches!(&result[..], &[SkippableResult::Skipped]));
+    }
+
+    #[test]
+    fn dedup_trash() {
+        let dir = test_dir("event-a").join("test17");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir(&dir).unwrap();
+        let original = dir.join("test17.txt");
+        let copy = dir.join("test17 (1).txt");
+        std::fs::write(&original, "content").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&copy, "content").unwrap();
+        let items = [&original, &copy].map(|path| Item::new(path).unwrap().with_root(&dir));
+        let result = dedup(&items, Duplicate::Trash);
+        assert!(original.exists());
+        assert!(!copy.exists());
+        assert!(matches!(
+            &result[..],
+            &[SkippableResult::Skipped, SkippableResult::Ok(_)]
+        ));
+    }
+
+    #[test]
+    fn candidates_recursive() {
+        let dir = test_dir("event-a").join("test18");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        for project in ["one", "two"] {
+            std::fs::create_dir_all(dir.join(project).join("target").join("debug")).unwrap();
+            std::fs::create_dir_all(dir.join(project).join("src")).unwrap();
+        }
+        let event = Event {
+            expr: TagExpr::new(
+                Tag {
+                    name: "Folder".into(),
+                    desc: String::new(),
+                    basis: Base::Type(FileType::Dir),
+                },
+                true,
+            ),
+            tp: EventType::Trash,
+            walk: Walk {
+                depth: None,
+                include: vec!["*/target".into()],
+                ..Walk::default()
+            },
+        };
+        let candidates = event.candidates(&dir).unwrap();
+        let paths = candidates
+            .iter()
+            .map(|item| item.path())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("one").join("target"),
+                dir.join("two").join("target")
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_move() {
+        let dir = test_dir("event-a").join("test19");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("to")).unwrap();
+        std::fs::write(dir.join("new.txt"), "").unwrap();
+        std::fs::write(dir.join("taken.txt"), "").unwrap();
+        std::fs::write(dir.join("to").join("taken.txt"), "").unwrap();
+        let event = Event {
+            expr: TagExpr::new(
+                Tag {
+                    name: "File".into(),
+                    desc: String::new(),
+                    basis: Base::Type(FileType::File),
+                },
+                true,
+            ),
+            tp: EventType::Move {
+                target: dir.join("to"),
+                conflict: Conflict::Skip,
+                verify: false,
+            },
+            walk: Walk::default(),
+        };
+        let plan = event.plan(&dir).unwrap();
+        assert!(dir.join("new.txt").exists());
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan[0].dest, Some(dir.join("to").join("new.txt")));
+        assert_eq!(plan[0].outcome, Planned::Apply);
+        assert!(matches!(plan[1].outcome, Planned::Skip(_)));
+    }
+
+    #[test]
+    fn undo_move() {
+        let from = test_dir("event-a").join("test20.txt");
+        std::fs::write(&from, "").unwrap();
+        let to = test_dir("event-b").join("test20");
+        if to.exists() {
+            std::fs::remove_dir_all(&to).unwrap();
+        }
+        let result = match &mv(&[&from], &to, Conflict::Skip, false, &Progress::default())[..] {
+            [SkippableResult::Ok(outcome)] => outcome.result.clone(),
+            _ => panic!("the file was not moved"),
+        };
+        let event = Event::mv();
+        let restored = event
+            .undo(&from, result.as_deref(), None, chrono::Local::now())
+            .unwrap();
+        assert_eq!(restored, Some(from.clone()));
+        assert!(from.exists());
+        assert!(!to.join("test20.txt").exists());
+        assert!(event
+            .undo(&from, result.as_deref(), None, chrono::Local::now())
+            .is_err());
+    }
+
+    #[test]
+    fn undo_move_changed() {
+        let from = test_dir("event-a").join("test27.txt");
+        std::fs::write(&from, "original").unwrap();
+        let to = test_dir("event-b").join("test27");
+        if to.exists() {
+            std::fs::remove_dir_all(&to).unwrap();
+        }
+        mv(&[&from], &to, Conflict::Skip, false, &Progress::default());
+        let moved = to.join("test27.txt");
+        let state = FileState::of(&moved).unwrap();
+        std::fs::write(&moved, "changed").unwrap();
+        let event = Event::mv();
+        assert!(event
+            .undo(&from, Some(&moved), Some(&state), chrono::Local::now())
+            .is_err());
+        assert!(moved.exists());
+        assert!(!from.exists());
+        let state = FileState::of(&moved).unwrap();
+        assert!(event
+            .undo(&from, Some(&moved), Some(&state), chrono::Local::now())
+            .is_ok());
+        assert!(from.exists());
+    }
+
+    #[test]
+    fn undo_copy_changed() {
+        let from = test_dir("event-a").join("test21.txt");
+        std::fs::write(&from, "original").unwrap();
+        let to = test_dir("event-b").join("test21");
+        if to.exists() {
+            std::fs::remove_dir_all(&to).unwrap();
+        }
+        copy(&[&from], &to, Conflict::Skip, &Progress::default());
+        let copy = to.join("test21.txt");
+        std::fs::write(&copy, "changed").unwrap();
+        let event = Event::copy();
+        assert!(event
+            .undo(&from, Some(&copy), None, chrono::Local::now())
+            .is_err());
+        assert!(copy.exists());
+        std::fs::write(&copy, "original").unwrap();
+        assert!(event
+            .undo(&from, Some(&copy), None, chrono::Local::now())
+            .is_ok());
+        assert!(!copy.exists());
+    }
+
+    #[test]
+    fn transfer_cancelled() {
+        let from = test_dir("event-a").join("test24");
+        let to = test_dir("event-b").join("test24");
+        for dir in [&from, &to] {
+            if dir.exists() {
+                std::fs::remove_dir_all(dir).unwrap();
+            }
+        }
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::write(from.join("file.txt"), "content").unwrap();
+        let progress = Progress::default();
+        progress.cancel();
+        let e = transfer(&from, &to, false, false, false, &progress).unwrap_err();
+        assert!(e.is::<Cancelled>());
+        assert!(from.join("file.txt").exists());
+        assert!(!to.exists());
+        assert!(progress.was_cancelled(&from));
+        // Everything after the cancelled copy is skipped
+        let result = copy(&[&from], test_dir("event-b"), Conflict::Skip, &progress);
+        assert!(matches!(&result[..], [SkippableResult::Skipped]));
+    }
+
+    #[test]
+    fn rename_twice() {
+        let dir = test_dir("event-a").join("test25");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        let event = Event {
+            expr: TagExpr::new(
+                Tag {
+                    name: "File".into(),
+                    desc: String::new(),
+                    basis: Base::Type(FileType::File),
+                },
+                true,
+            ),
+            ..Event::rename()
+        };
+        let mut log = Log::new();
+        for _ in 0..2 {
+            let results = event
+                .execute(&dir, &log.history(&event), &Progress::default())
+                .unwrap();
+            for result in results {
+                if let SkippableResult::Ok(entry) = result {
+                    log.push(entry);
+                }
+            }
+        }
+        let names = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        let date = Item::new(dir.join(&names[0])).unwrap().created();
+        let expected = format!(
+            "a-{}.txt",
+            chrono::DateTime::<chrono::Local>::from(date).format("%Y-%m-%d")
+        );
+        assert_eq!(names, vec![std::ffi::OsString::from(expected)]);
+        assert_eq!(log.entries().len(), 1);
+        // The renamed file is left alone even without the log
+        let results = event
+            .execute(&dir, &Log::new().history(&event), &Progress::default())
+            .unwrap();
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, SkippableResult::Skipped)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_once() {
+        let dir = test_dir("event-a").join("test26");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        let expr = TagExpr::new(
+            Tag {
+                name: "File".into(),
+                desc: String::new(),
+                basis: Base::Type(FileType::File),
+            },
+            true,
+        );
+        let command = |line| Event {
+            expr: expr.clone(),
+            tp: EventType::Command {
+                command: CommandLine::parse(line, true, Duration::from_secs(5)).unwrap(),
+            },
+            ..Event::command()
+        };
+        let mut log = Log::new();
+        let apply = |event: &Event, log: &mut Log| {
+            let results = event
+                .execute(&dir, &log.history(event), &Progress::default())
+                .unwrap();
+            let entries = results
+                .into_iter()
+                .filter_map(|result| match result {
+                    SkippableResult::Ok(entry) => Some(entry),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for entry in &entries {
+                log.push(entry.clone());
+            }
+            entries
+        };
+        let touch = || {
+            std::fs::File::options()
+                .write(true)
+                .open(dir.join("a.txt"))
+                .unwrap()
+                .set_modified(SystemTime::now())
+                .unwrap();
+        };
+        // Failed runs are logged with their output
+        let failing = command("echo oops >&2; exit 3");
+        let entries = apply(&failing, &mut log);
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].is_failed());
+        assert!(entries[0].details().unwrap().contains("oops"));
+        // Runs are not repeated until the file is modified, even if they failed
+        assert!(apply(&failing, &mut log).is_empty());
+        touch();
+        assert_eq!(apply(&failing, &mut log).len(), 1);
+        let succeeding = command("true");
+        assert_eq!(apply(&succeeding, &mut log).len(), 1);
+        assert!(apply(&succeeding, &mut log).is_empty());
+        touch();
+        assert_eq!(apply(&succeeding, &mut log).len(), 1);
+    }
 }
@@ -36,6 +36,63 @@ impl From<fs::FileType> for FileType {
     }
 }
 
+/// One of the times the file system keeps for every file.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Timestamp {
+    Created,
+    Modified,
+    Accessed,
+    /// When a photo was taken, as recorded by the camera.
+    Taken,
+}
+
+impl Timestamp {
+    pub const ALL: [Timestamp; 4] = [
+        Timestamp::Created,
+        Timestamp::Modified,
+        Timestamp::Accessed,
+        Timestamp::Taken,
+    ];
+
+    /// Read the time from the metadata, which does not hold the time a photo was taken.
+    /// Everything else goes through [`Timestamp::read`].
+    ///
+    /// Many file systems do not keep the creation time,
+    /// and some are mounted without access times,
+    /// so the modification time is used instead of them.
+    fn of(self, metadata: &fs::Metadata) -> std::io::Result<SystemTime> {
+        match self {
+            Timestamp::Created => metadata.created().or_else(|_| metadata.modified()),
+            Timestamp::Modified => metadata.modified(),
+            Timestamp::Accessed => metadata.accessed().or_else(|_| metadata.modified()),
+            Timestamp::Taken => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "The time a photo was taken is not a part of the metadata of the file",
+            )),
+        }
+    }
+    /// Read the time of the file.
+    ///
+    /// Files without the time a photo was taken are an error.
+    pub fn read(self, path: &Path) -> anyhow::Result<SystemTime> {
+        match self {
+            Timestamp::Taken => super::photo_info(path)?
+                .taken_time()
+                .ok_or_else(|| anyhow::anyhow!("{path:?} has no date a photo was taken")),
+            _ => Ok(self.of(&fs::metadata(path)?)?),
+        }
+    }
+    /// Lower case name, as used in queries and descriptions.
+    pub fn name(self) -> &'static str {
+        match self {
+            Timestamp::Created => "created",
+            Timestamp::Modified => "modified",
+            Timestamp::Accessed => "accessed",
+            Timestamp::Taken => "taken",
+        }
+    }
+}
+
 /// Snapshot of information about a certain file.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Item {
@@ -55,6 +112,15 @@ pub struct Item {
     creation_time: SystemTime,
     // Time when the file was modified.
     modified_time: SystemTime,
+    // Time when the file was last read.
+    #[serde(default = "SystemTime::now")]
+    accessed_time: SystemTime,
+    /// Folder that was scanned to find the file, if any.
+    ///
+    /// Conditions that compare the file with its neighbours
+    /// (such as duplicate detection) look for them inside this folder.
+    #[serde(skip)]
+    root: Option<PathBuf>,
 }
 
 impl Item {
@@ -66,8 +132,10 @@ impl Item {
             path: path.to_owned(),
             file_type,
             size: None,
-            creation_time: metadata.created()?,
+            creation_time: Timestamp::Created.of(&metadata)?,
             modified_time: metadata.modified()?,
+            accessed_time: Timestamp::Accessed.of(&metadata)?,
+            root: None,
         })
     }
     pub fn new_with_size(path: impl AsRef<Path>) -> anyhow::Result<Self> {
@@ -77,25 +145,55 @@ impl Item {
             ..Item::new(path)?
         })
     }
+    pub fn with_root(self, root: impl AsRef<Path>) -> Self {
+        Item {
+            root: Some(root.as_ref().to_owned()),
+            ..self
+        }
+    }
     pub fn path(&self) -> &Path {
         &self.path
     }
+    /// Folder that was scanned to find the file, or the parent folder of the file.
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref().or_else(|| self.path.parent())
+    }
     pub fn name(&self) -> Option<String> {
         self.path.name()
     }
+    pub fn stem(&self) -> Option<String> {
+        self.path.stem()
+    }
     pub fn ext(&self) -> Option<String> {
         self.path.ext()
     }
     pub fn file_type(&self) -> &FileType {
         &self.file_type
     }
+    pub fn created(&self) -> SystemTime {
+        self.creation_time
+    }
+    pub fn modified(&self) -> SystemTime {
+        self.modified_time
+    }
+    pub fn accessed(&self) -> SystemTime {
+        self.accessed_time
+    }
+    /// MIME type such as `image/png`.
+    pub fn mime(&self) -> anyhow::Result<String> {
+        super::mime_type(&self.path)
+    }
     pub fn size(&mut self) -> anyhow::Result<Byte> {
         // If the size is cached, return it
         if let Some(size) = self.size {
             Ok(size)
         // Otherwise, update the snapshot
         } else {
-            *self = Item::new_with_size(self.path())?;
+            let item = Item::new_with_size(self.path())?;
+            *self = Item {
+                root: self.root.take(),
+                ..item
+            };
             Ok(self.size.unwrap())
         }
     }
@@ -0,0 +1,220 @@
+//! Named tags that the user can create, change and group into columns.
+use serde::{Deserialize, Serialize};
+
+use super::{all_tags_sorted_by_columns, parse_query, Base, Tag, TagExpr};
+
+/// Names of the columns of the predefined tags, in the order of [`all_tags_sorted_by_columns`].
+const DEFAULT_COLUMNS: [&str; 4] = ["Filetype", "Size", "Date", "Other"];
+/// Name of the column that custom tags are saved to.
+const CUSTOM_COLUMN: &str = "Custom";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TagColumn {
+    pub name: String,
+    pub tags: Vec<Tag>,
+}
+
+/// The tags offered when choosing the files of an event and shown for every file.
+///
+/// Starts with the predefined tags, names of tags are unique across all columns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TagLibrary {
+    columns: Vec<TagColumn>,
+}
+
+impl Default for TagLibrary {
+    fn default() -> Self {
+        let columns = DEFAULT_COLUMNS
+            .into_iter()
+            .zip(all_tags_sorted_by_columns())
+            .map(|(name, tags)| TagColumn {
+                name: name.to_owned(),
+                tags,
+            })
+            .collect();
+        TagLibrary { columns }
+    }
+}
+
+impl TagLibrary {
+    pub fn columns(&self) -> &[TagColumn] {
+        &self.columns
+    }
+    pub fn tags(&self) -> impl Iterator<Item = &Tag> {
+        self.columns.iter().flat_map(|column| column.tags.iter())
+    }
+    pub fn add_column(&mut self, name: &str) -> anyhow::Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!("The name of the column is empty");
+        }
+        if self.columns.iter().any(|column| column.name == name) {
+            anyhow::bail!("There is already a column named {name:?}");
+        }
+        self.columns.push(TagColumn {
+            name: name.to_owned(),
+            tags: Vec::new(),
+        });
+        Ok(())
+    }
+    /// Remove the column together with its tags.
+    pub fn remove_column(&mut self, column: usize) {
+        if column < self.columns.len() {
+            self.columns.remove(column);
+        }
+    }
+    /// Add the tag to the end of the column.
+    pub fn add(&mut self, column: usize, tag: Tag) -> anyhow::Result<()> {
+        self.check_name(&tag.name, None)?;
+        self.column_mut(column)?.tags.push(tag);
+        Ok(())
+    }
+    /// Replace the tag at `index` of `column`, moving it to the end of `new_column`
+    /// if the column changes.
+    pub fn replace(
+        &mut self,
+        column: usize,
+        index: usize,
+        new_column: usize,
+        tag: Tag,
+    ) -> anyhow::Result<()> {
+        if self.column_mut(column)?.tags.get(index).is_none() {
+            anyhow::bail!("There is no such tag");
+        }
+        self.column_mut(new_column)?;
+        self.check_name(&tag.name, Some((column, index)))?;
+        if column == new_column {
+            self.columns[column].tags[index] = tag;
+        } else {
+            self.columns[column].tags.remove(index);
+            self.columns[new_column].tags.push(tag);
+        }
+        Ok(())
+    }
+    /// Add a custom tag of a rule to the end of the custom column, creating the column if needed.
+    ///
+    /// Tags that are in the library already are left alone.
+    pub fn save(&mut self, tag: &Tag) -> anyhow::Result<()> {
+        if self.tags().any(|saved| saved == tag) {
+            return Ok(());
+        }
+        let column = match self
+            .columns
+            .iter()
+            .position(|column| column.name == CUSTOM_COLUMN)
+        {
+            Some(column) => column,
+            None => {
+                self.add_column(CUSTOM_COLUMN)?;
+                self.columns.len() - 1
+            }
+        };
+        self.add(column, tag.clone())
+    }
+    pub fn remove(&mut self, column: usize, index: usize) {
+        if let Some(column) = self.columns.get_mut(column) {
+            if index < column.tags.len() {
+                column.tags.remove(index);
+            }
+        }
+    }
+    fn column_mut(&mut self, column: usize) -> anyhow::Result<&mut TagColumn> {
+        self.columns
+            .get_mut(column)
+            .ok_or_else(|| anyhow::anyhow!("There is no such column"))
+    }
+    /// Check that the name is not empty and not used by another tag than the one at `except`.
+    fn check_name(&self, name: &str, except: Option<(usize, usize)>) -> anyhow::Result<()> {
+        if name.trim().is_empty() {
+            anyhow::bail!("The name of the tag is empty");
+        }
+        let taken = self.columns.iter().enumerate().any(|(column, tags)| {
+            tags.tags
+                .iter()
+                .enumerate()
+                .any(|(index, tag)| tag.name == name && except != Some((column, index)))
+        });
+        if taken {
+            anyhow::bail!("There is already a tag named {name:?}");
+        }
+        Ok(())
+    }
+}
+
+/// A tag for the library, with the condition written as a query of a single condition,
+/// such as `ext:pdf` or `width>1920`.
+pub fn library_tag(name: &str, desc: &str, condition: &str) -> anyhow::Result<Tag> {
+    let basis = parse_condition(condition)?;
+    Ok(Tag {
+        name: name.trim().to_owned(),
+        desc: desc.trim().to_owned(),
+        basis,
+    })
+}
+
+/// Parse a query of a single condition.
+pub fn parse_condition(condition: &str) -> anyhow::Result<Base> {
+    // Only the condition is kept, so there are no tags to name it after
+    let library = TagLibrary {
+        columns: Vec::new(),
+    };
+    match parse_query(condition, &library) {
+        Ok(TagExpr::Tag(tag)) => Ok(tag.basis),
+        Ok(_) => anyhow::bail!("A tag has a single condition, without AND, OR and NOT"),
+        Err(e) => anyhow::bail!("{}", e.message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{library_tag, TagLibrary};
+    use crate::lib::{all_tags, Base};
+
+    #[test]
+    fn defaults() {
+        let library = TagLibrary::default();
+        assert_eq!(library.columns().len(), 4);
+        assert_eq!(library.columns()[0].name, "Filetype");
+        assert_eq!(library.tags().cloned().collect::<Vec<_>>(), all_tags());
+    }
+
+    #[test]
+    fn edit() {
+        let mut library = TagLibrary::default();
+        library.add_column("Screenshots").unwrap();
+        assert!(library.add_column("Screenshots").is_err());
+        let tag = library_tag("🖥️ Wallpaper", "Wide images.", "ratio:16/9").unwrap();
+        assert_eq!(tag.basis, Base::AspectRatio(16, 9));
+        library.add(4, tag.clone()).unwrap();
+        assert!(library.add(4, tag.clone()).is_err());
+        assert!(library.add(5, tag).is_err());
+        // Keeping the name of the tag that is replaced is allowed
+        let tag = library_tag("🖥️ Wallpaper", "", "width>1919").unwrap();
+        library.replace(4, 0, 0, tag.clone()).unwrap();
+        assert!(library.columns()[4].tags.is_empty());
+        assert_eq!(library.columns()[0].tags.last(), Some(&tag));
+        let index = library.columns()[0].tags.len() - 1;
+        library.remove(0, index);
+        assert!(!library.tags().any(|tag| tag.name == "🖥️ Wallpaper"));
+        library.remove_column(4);
+        assert_eq!(library, TagLibrary::default());
+        assert!(library_tag("Both", "", "ext:pdf AND ext:djvu").is_err());
+        assert!(library_tag("Wrong", "", "width:wide").is_err());
+        let unnamed = library_tag(" ", "", "ext:pdf").unwrap();
+        assert!(library.add(0, unnamed).is_err());
+    }
+
+    #[test]
+    fn save() {
+        let mut library = TagLibrary::default();
+        let tag = library_tag("Wide", "", "width>1920").unwrap();
+        library.save(&tag).unwrap();
+        library.save(&tag).unwrap();
+        library.save(&all_tags()[0]).unwrap();
+        assert_eq!(library.columns().len(), 5);
+        assert_eq!(library.columns()[4].name, "Custom");
+        assert_eq!(library.columns()[4].tags, vec![tag]);
+        let other = library_tag("Wide", "", "width>2560").unwrap();
+        assert!(library.save(&other).is_err());
+    }
+}
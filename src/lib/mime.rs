@@ -0,0 +1,104 @@
+//! Exact MIME types of files, such as `image/png`.
+use std::path::Path;
+
+use super::{Base, Tag};
+
+/// The MIME type of the file, detected from its content.
+///
+/// Formats that cannot be told by their content (such as plain text)
+/// are guessed from the extension instead.
+pub fn mime_type(path: &Path) -> anyhow::Result<String> {
+    detect_mime(path)?.ok_or_else(|| anyhow::anyhow!("Unknown file format"))
+}
+
+/// Like [`mime_type`], but a file of an unknown format is not an error.
+pub fn detect_mime(path: &Path) -> anyhow::Result<Option<String>> {
+    if let Some(kind) = infer::get_from_path(path)? {
+        return Ok(Some(kind.mime_type().to_owned()));
+    }
+    Ok(mime_guess::from_path(path)
+        .first()
+        .map(|mime| mime.essence_str().to_owned()))
+}
+
+/// Whether the MIME type matches a pattern such as `image/png` or `image/*`.
+///
+/// Letter case is ignored.
+pub fn is_mime(pattern: &str, mime: &str) -> bool {
+    let matches = |pattern: &str, part: &str| pattern == "*" || pattern.eq_ignore_ascii_case(part);
+    match (pattern.split_once('/'), mime.split_once('/')) {
+        (Some((tp, subtype)), Some((mime_tp, mime_subtype))) => {
+            matches(tp, mime_tp) && matches(subtype, mime_subtype)
+        }
+        _ => false,
+    }
+}
+
+/// Check that the pattern has the form `type/subtype`, where either part may be `*`.
+pub fn validate_mime(pattern: &str) -> anyhow::Result<()> {
+    match pattern.split_once('/') {
+        Some((tp, subtype)) if !tp.is_empty() && !subtype.is_empty() && !subtype.contains('/') => {
+            Ok(())
+        }
+        _ => anyhow::bail!("{pattern:?} is not a MIME type such as image/png or image/*"),
+    }
+}
+
+/// A custom tag for the files of the MIME type.
+pub fn custom_mime_tag(pattern: &str) -> anyhow::Result<Tag> {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    validate_mime(&pattern)?;
+    Ok(Tag {
+        name: format!("🧾 {pattern}"),
+        desc: format!("A custom tag which includes files of the MIME type {pattern}."),
+        basis: Base::Mime(pattern),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_mime, mime_type, validate_mime};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Base, Item, Tag, TagExpr};
+
+    #[test]
+    fn detect() {
+        let png = test_dir("mime").join("image.txt");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR").unwrap();
+        // The content wins over the extension
+        assert_eq!(mime_type(&png).unwrap(), "image/png");
+        let text = test_dir("mime").join("notes.txt");
+        std::fs::write(&text, "notes").unwrap();
+        assert_eq!(mime_type(&text).unwrap(), "text/plain");
+        let unknown = test_dir("mime").join("data.unknown-extension");
+        std::fs::write(&unknown, "data").unwrap();
+        assert!(mime_type(&unknown).is_err());
+    }
+
+    #[test]
+    fn unknown_type() {
+        let unknown = test_dir("mime").join("data.another-unknown-extension");
+        std::fs::write(&unknown, "data").unwrap();
+        let mut item = Item::new(&unknown).unwrap();
+        let image = Tag {
+            name: String::new(),
+            desc: String::new(),
+            basis: Base::Mime("image/*".into()),
+        };
+        assert!(!image.is(&mut item).unwrap());
+        assert!(TagExpr::new(image, false).is(&mut item).unwrap());
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(is_mime("image/png", "image/png"));
+        assert!(is_mime("image/*", "image/png"));
+        assert!(is_mime("*/*", "text/plain"));
+        assert!(is_mime("Image/PNG", "image/png"));
+        assert!(!is_mime("image/*", "video/mp4"));
+        assert!(!is_mime("image", "image/png"));
+        assert!(validate_mime("image/*").is_ok());
+        assert!(validate_mime("image").is_err());
+        assert!(validate_mime("image/").is_err());
+    }
+}
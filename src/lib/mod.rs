@@ -9,3 +9,62 @@ pub use rule::*;
 
 mod event;
 pub use event::*;
+
+mod template;
+pub use template::*;
+
+mod command;
+pub use command::*;
+
+mod archive;
+pub use archive::*;
+
+mod conflict;
+pub use conflict::*;
+
+mod duplicate;
+pub use duplicate::*;
+
+mod photo;
+pub use photo::*;
+
+mod dimensions;
+pub use dimensions::*;
+
+mod audio;
+pub use audio::*;
+
+mod stability;
+pub use stability::*;
+
+mod owner;
+pub use owner::*;
+
+mod mime;
+pub use mime::*;
+
+mod content;
+pub use content::*;
+
+mod calendar;
+pub use calendar::*;
+
+mod walk;
+pub use walk::*;
+
+mod verify;
+pub use verify::*;
+
+mod progress;
+pub use progress::*;
+
+mod cache;
+
+#[cfg(test)]
+mod testing;
+
+mod query;
+pub use query::*;
+
+mod library;
+pub use library::*;
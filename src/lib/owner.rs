@@ -0,0 +1,200 @@
+//! Conditions on the owner and the permissions of files, which only exist on Unix.
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use super::{Base, Tag};
+
+lazy_static! {
+    /// Ids of the users and groups looked up by name during the current pass,
+    /// keyed by whether the name is a group.
+    static ref ACCOUNTS: Mutex<HashMap<(bool, String), Option<u32>>> = Mutex::new(HashMap::new());
+}
+
+/// A user or a group of users.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Account {
+    /// The user that runs the app, or their primary group.
+    Current,
+    Id(u32),
+    Name(String),
+}
+
+impl Account {
+    /// `me`, a number for an id, or a name.
+    pub fn parse(s: &str) -> Self {
+        if s == "me" {
+            Account::Current
+        } else if let Ok(id) = s.parse() {
+            Account::Id(id)
+        } else {
+            Account::Name(s.to_owned())
+        }
+    }
+}
+
+impl std::fmt::Display for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Account::Current => write!(f, "me"),
+            Account::Id(id) => write!(f, "{id}"),
+            Account::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Permission bits, as in `chmod`.
+pub mod mode {
+    pub const EXECUTABLE: u32 = 0o111;
+    pub const WORLD_WRITABLE: u32 = 0o002;
+    pub const SETUID: u32 = 0o4000;
+    pub const SETGID: u32 = 0o2000;
+    pub const STICKY: u32 = 0o1000;
+}
+
+/// Permissions offered for custom tags, with the bits of [`Base::ModeAny`].
+pub const MODE_MATCHES: [(&str, u32); 5] = [
+    ("Executable", mode::EXECUTABLE),
+    ("World-writable", mode::WORLD_WRITABLE),
+    ("Setuid", mode::SETUID),
+    ("Setgid", mode::SETGID),
+    ("Sticky", mode::STICKY),
+];
+
+/// Forget the ids of the users and groups looked up so far,
+/// so accounts that were added or removed are noticed. Called at the start of every pass.
+pub fn forget_accounts() {
+    ACCOUNTS.lock().expect("unable to aquire mutex").clear();
+}
+
+/// Look the name up only once per pass, instead of once for every file.
+#[cfg(unix)]
+fn account_id(name: &str, group: bool) -> anyhow::Result<u32> {
+    let mut accounts = ACCOUNTS.lock().expect("unable to aquire mutex");
+    let id = *accounts.entry((group, name.to_owned())).or_insert_with(|| {
+        if group {
+            uzers::get_group_by_name(name).map(|group| group.gid())
+        } else {
+            uzers::get_user_by_name(name).map(|user| user.uid())
+        }
+    });
+    id.ok_or_else(|| {
+        let kind = if group { "group" } else { "user" };
+        anyhow::anyhow!("There is no {kind} named {name:?}")
+    })
+}
+
+#[cfg(unix)]
+pub fn is_user(path: &Path, account: &Account) -> anyhow::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let uid = std::fs::metadata(path)?.uid();
+    Ok(match account {
+        Account::Current => uid == uzers::get_current_uid(),
+        Account::Id(id) => uid == *id,
+        Account::Name(name) => uid == account_id(name, false)?,
+    })
+}
+
+#[cfg(unix)]
+pub fn is_group(path: &Path, account: &Account) -> anyhow::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let gid = std::fs::metadata(path)?.gid();
+    Ok(match account {
+        Account::Current => gid == uzers::get_current_gid(),
+        Account::Id(id) => gid == *id,
+        Account::Name(name) => gid == account_id(name, true)?,
+    })
+}
+
+/// The permission bits of the file.
+#[cfg(unix)]
+pub fn mode_of(path: &Path) -> anyhow::Result<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(std::fs::metadata(path)?.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub fn is_user(_path: &Path, _account: &Account) -> anyhow::Result<bool> {
+    anyhow::bail!("Owners of files are only supported on Unix")
+}
+
+#[cfg(not(unix))]
+pub fn is_group(_path: &Path, _account: &Account) -> anyhow::Result<bool> {
+    anyhow::bail!("Groups of files are only supported on Unix")
+}
+
+#[cfg(not(unix))]
+pub fn mode_of(_path: &Path) -> anyhow::Result<u32> {
+    anyhow::bail!("Permissions of files are only supported on Unix")
+}
+
+/// A custom tag for the files owned by the user, or by the group with `group`.
+pub fn custom_owner_tag(account: &str, group: bool) -> anyhow::Result<Tag> {
+    let account = account.trim();
+    if account.is_empty() {
+        anyhow::bail!("The owner is empty");
+    }
+    let account = Account::parse(account);
+    Ok(if group {
+        Tag {
+            name: format!("👥 {account}"),
+            desc: format!("A custom tag which includes files that belong to the group {account}."),
+            basis: Base::Group(account),
+        }
+    } else {
+        Tag {
+            name: format!("👤 {account}"),
+            desc: format!("A custom tag which includes files owned by the user {account}."),
+            basis: Base::User(account),
+        }
+    })
+}
+
+/// A custom tag for the files with one of the permissions of [`MODE_MATCHES`].
+pub fn custom_mode_tag(kind: usize) -> Tag {
+    let (name, bits) = MODE_MATCHES[kind];
+    Tag {
+        name: format!("🔐 {name}"),
+        desc: format!("A custom tag which includes files with the permission bits {bits:04o}."),
+        basis: Base::ModeAny(bits),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{mode, Account};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Base, Item};
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn owner_and_mode() {
+        let path = test_dir("owner").join("script.sh");
+        std::fs::write(&path, "").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o744)).unwrap();
+        let mut item = Item::new(&path).unwrap();
+        let mut is = |basis: Base| basis.is(&mut item).unwrap();
+        assert!(is(Base::User(Account::Current)));
+        assert!(is(Base::User(Account::Id(uzers::get_current_uid()))));
+        assert!(!is(Base::User(Account::Id(uzers::get_current_uid() + 1))));
+        if let Some(name) = uzers::get_current_username() {
+            let name = name.to_string_lossy().into_owned();
+            assert!(is(Base::User(Account::Name(name))));
+        }
+        assert!(is(Base::Group(Account::Current)));
+        assert!(is(Base::ModeAny(mode::EXECUTABLE)));
+        assert!(!is(Base::ModeAll(mode::EXECUTABLE)));
+        assert!(is(Base::ModeAll(0o700)));
+        assert!(!is(Base::ModeAny(mode::WORLD_WRITABLE | mode::SETUID)));
+        assert_eq!(Account::parse("me"), Account::Current);
+        assert_eq!(Account::parse("1000"), Account::Id(1000));
+        assert_eq!(Account::parse("alice"), Account::Name("alice".into()));
+        assert!(Base::User(Account::Name("no such user".into()))
+            .is(&mut item)
+            .is_err());
+    }
+}
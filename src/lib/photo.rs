@@ -0,0 +1,281 @@
+//! Metadata that cameras store inside photos (EXIF).
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use exif::{In, Reader};
+use lazy_static::lazy_static;
+
+use super::{cache::make_room, Base, Tag};
+
+lazy_static! {
+    /// Metadata of the photos read so far, with the modification time of the file when it was read.
+    static ref PHOTOS: Mutex<HashMap<PathBuf, (SystemTime, PhotoInfo)>> = Mutex::new(HashMap::new());
+}
+
+/// The EXIF fields the conditions look at.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PhotoInfo {
+    /// When the photo was taken, in the time of the camera.
+    pub taken: Option<NaiveDateTime>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    /// Whether the location of the photo is recorded.
+    pub has_gps: bool,
+    /// From `1` for upright to `8`, as defined by EXIF.
+    pub orientation: Option<u32>,
+}
+
+impl PhotoInfo {
+    /// When the photo was taken, in the local time zone.
+    pub fn taken_time(&self) -> Option<SystemTime> {
+        let taken = self.taken?;
+        Local
+            .from_local_datetime(&taken)
+            .earliest()
+            .map(SystemTime::from)
+    }
+    /// Whether the make or the model of the camera contains `text`, ignoring letter case.
+    pub fn is_camera(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        [&self.make, &self.model]
+            .into_iter()
+            .flatten()
+            .any(|value| value.to_lowercase().contains(&text))
+    }
+}
+
+/// Read the EXIF metadata of a photo, such as a JPEG, TIFF, HEIF, PNG or WebP file.
+///
+/// Files without metadata, folders and the files that are not photos at all
+/// have none of the fields, only the files that cannot be read are an error.
+pub fn photo_info(path: &Path) -> anyhow::Result<PhotoInfo> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Ok(PhotoInfo::default());
+    }
+    let modified = metadata.modified()?;
+    if let Ok(photos) = PHOTOS.lock() {
+        if let Some((time, info)) = photos.get(path) {
+            if *time == modified {
+                return Ok(info.clone());
+            }
+        }
+    }
+    let info = match Reader::new().read_from_container(&mut BufReader::new(File::open(path)?)) {
+        Ok(exif) => read_info(&exif),
+        Err(exif::Error::Io(e)) if e.kind() != std::io::ErrorKind::UnexpectedEof => {
+            return Err(e.into())
+        }
+        Err(_) => PhotoInfo::default(),
+    };
+    if let Ok(mut photos) = PHOTOS.lock() {
+        make_room(&mut photos);
+        photos.insert(path.to_owned(), (modified, info.clone()));
+    }
+    Ok(info)
+}
+
+fn read_info(exif: &exif::Exif) -> PhotoInfo {
+    let text = |tag| {
+        exif.get_field(tag, In::PRIMARY)
+            .and_then(|field| match &field.value {
+                exif::Value::Ascii(values) => values
+                    .first()
+                    .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+                    .filter(|value| !value.is_empty()),
+                _ => None,
+            })
+    };
+    let taken = [
+        exif::Tag::DateTimeOriginal,
+        exif::Tag::DateTimeDigitized,
+        exif::Tag::DateTime,
+    ]
+    .into_iter()
+    .find_map(|tag| parse_date_time(&text(tag)?));
+    PhotoInfo {
+        taken,
+        make: text(exif::Tag::Make),
+        model: text(exif::Tag::Model),
+        has_gps: exif
+            .get_field(exif::Tag::GPSLatitude, In::PRIMARY)
+            .is_some(),
+        orientation: exif
+            .get_field(exif::Tag::Orientation, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0)),
+    }
+}
+
+fn parse_date_time(s: &str) -> Option<NaiveDateTime> {
+    let date_time = exif::DateTime::from_ascii(s.as_bytes()).ok()?;
+    NaiveDate::from_ymd_opt(
+        date_time.year.into(),
+        date_time.month.into(),
+        date_time.day.into(),
+    )?
+    .and_hms_opt(
+        date_time.hour.into(),
+        date_time.minute.into(),
+        date_time.second.into(),
+    )
+}
+
+/// Names of the EXIF orientations, starting from `1`,
+/// which tell how the photo has to be turned to be upright.
+pub const ORIENTATIONS: [&str; 8] = [
+    "Normal",
+    "Mirrored horizontally",
+    "Rotated 180°",
+    "Mirrored vertically",
+    "Mirrored and rotated 270° clockwise",
+    "Rotated 90° clockwise",
+    "Mirrored and rotated 90° clockwise",
+    "Rotated 270° clockwise",
+];
+
+/// A custom tag for the photos taken with a camera whose make or model contains the text.
+pub fn custom_camera_tag(text: &str) -> anyhow::Result<Tag> {
+    let text = text.trim();
+    if text.is_empty() {
+        anyhow::bail!("The camera is empty");
+    }
+    Ok(Tag {
+        name: format!("📷 {text}"),
+        desc: format!(
+            "A custom tag which includes photos taken with a camera named like {text:?}."
+        ),
+        basis: Base::Camera(text.to_owned()),
+    })
+}
+
+/// A custom tag for the photos with one of the [`ORIENTATIONS`], by its index.
+pub fn custom_orientation_tag(kind: usize) -> Tag {
+    let name = ORIENTATIONS[kind];
+    Tag {
+        name: format!("🔄 {name}"),
+        desc: format!("A custom tag which includes photos with the orientation \"{name}\"."),
+        basis: Base::Orientation(kind as u32 + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{custom_orientation_tag, photo_info, PhotoInfo};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Base, Item, Tag as ItemTag, TagExpr, Template, Timestamp};
+    use chrono::NaiveDate;
+    use exif::{experimental::Writer, Field, In, Tag, Value};
+    use std::{io::Cursor, path::Path};
+
+    /// Write a JPEG file that only has the EXIF metadata.
+    fn write_photo(path: &Path, taken: &str, gps: bool) {
+        let ascii = |tag, value: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+        };
+        let fields = [
+            ascii(Tag::Make, "NIKON CORPORATION"),
+            ascii(Tag::Model, "NIKON D750"),
+            ascii(Tag::DateTimeOriginal, taken),
+            Field {
+                tag: Tag::Orientation,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![6]),
+            },
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![(50, 1).into(), (27, 1).into(), (0, 1).into()]),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in fields
+            .iter()
+            .filter(|field| gps || field.tag != Tag::GPSLatitude)
+        {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+        std::fs::write(path, jpeg).unwrap();
+    }
+
+    #[test]
+    fn read() {
+        let path = test_dir("photo").join("photo.jpg");
+        write_photo(&path, "2021:07:04 18:30:00", true);
+        let info = photo_info(&path).unwrap();
+        assert_eq!(
+            info.taken,
+            NaiveDate::from_ymd_opt(2021, 7, 4)
+                .unwrap()
+                .and_hms_opt(18, 30, 0)
+        );
+        assert!(info.is_camera("nikon"));
+        assert!(info.is_camera("D750"));
+        assert!(!info.is_camera("canon"));
+        assert!(info.has_gps);
+        assert_eq!(info.orientation, Some(6));
+        let text = test_dir("photo").join("text.txt");
+        std::fs::write(&text, "text").unwrap();
+        assert_eq!(photo_info(&text).unwrap(), PhotoInfo::default());
+        assert_eq!(
+            photo_info(&test_dir("photo")).unwrap(),
+            PhotoInfo::default()
+        );
+    }
+
+    #[test]
+    fn without_exif() {
+        let path = test_dir("photo").join("plain.png");
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR").unwrap();
+        let mut item = Item::new(&path).unwrap();
+        let tag = |basis| {
+            TagExpr::Tag(ItemTag {
+                name: String::new(),
+                desc: String::new(),
+                basis,
+            })
+        };
+        assert!(!Base::HasGps.is(&mut item).unwrap());
+        assert!(!Base::Camera("nikon".into()).is(&mut item).unwrap());
+        // is:image AND NOT is:geotagged
+        let expr = TagExpr::And(vec![
+            tag(Base::IsImage),
+            TagExpr::Not(Box::new(tag(Base::HasGps))),
+        ]);
+        assert!(expr.is(&mut item).unwrap());
+    }
+
+    #[test]
+    fn conditions() {
+        let path = test_dir("photo").join("no-gps.jpg");
+        write_photo(&path, "2021:07:04 18:30:00", false);
+        let mut item = Item::new(&path).unwrap();
+        let mut is = |basis: Base| basis.is(&mut item).unwrap();
+        assert!(is(Base::Camera("d750".into())));
+        assert!(!is(Base::HasGps));
+        assert!(is(Base::Orientation(6)));
+        assert!(!is(Base::Orientation(1)));
+        assert!(is(custom_orientation_tag(5).basis));
+        let date = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap();
+        assert!(is(Base::DateBefore(Timestamp::Taken, date)));
+        assert!(is(Base::OnWeekend(Timestamp::Taken)));
+        let template = Template::new("{taken:%Y-%m-%d}");
+        assert_eq!(template.expand(&item, 0).unwrap(), "2021-07-04");
+    }
+}
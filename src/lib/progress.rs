@@ -0,0 +1,201 @@
+//! Reporting the progress of long file operations and cancelling them.
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Operations that finish faster than this are not reported at all.
+const REPORT_DELAY: Duration = Duration::from_millis(500);
+/// Minimal time between two reports.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// State of the copy that is running right now.
+#[derive(Clone, Debug)]
+pub struct Status {
+    /// The file or folder the event is applied to.
+    pub item: PathBuf,
+    /// The file that is being copied, which is inside `item` if it is a folder.
+    pub file: PathBuf,
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
+    pub copied_files: usize,
+    pub total_files: usize,
+    pub started: Instant,
+}
+
+impl Status {
+    fn new(item: &Path, total_bytes: u64, total_files: usize) -> Self {
+        Status {
+            item: item.to_owned(),
+            file: item.to_owned(),
+            copied_bytes: 0,
+            total_bytes,
+            copied_files: 0,
+            total_files,
+            started: Instant::now(),
+        }
+    }
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            0.0
+        } else {
+            self.copied_bytes as f64 / self.total_bytes as f64
+        }
+    }
+    /// Time left, estimated from the average speed so far.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.copied_bytes == 0 {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let left = self.total_bytes.saturating_sub(self.copied_bytes) as f64;
+        Some(Duration::from_secs_f64(
+            elapsed * left / self.copied_bytes as f64,
+        ))
+    }
+}
+
+/// The error of an operation that was stopped by [`Progress::cancel`].
+#[derive(Debug)]
+pub struct Cancelled(pub PathBuf);
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Copying {:?} was cancelled", self.0)
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+type Handler = Arc<dyn Fn(&Status) + Send + Sync>;
+
+/// Connects the thread that runs the events with whoever watches them.
+///
+/// Clones share the cancellation state, so any of them can stop the operation.
+#[derive(Clone, Default)]
+pub struct Progress {
+    handler: Option<Handler>,
+    cancel: Arc<AtomicBool>,
+    /// Items whose operation was cancelled, so they are not started over.
+    cancelled: Arc<Mutex<HashSet<PathBuf>>>,
+    last_report: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Progress {
+    /// A clone that passes the reports to `handler` instead.
+    pub fn with_handler(&self, handler: impl Fn(&Status) + Send + Sync + 'static) -> Self {
+        Progress {
+            handler: Some(Arc::new(handler)),
+            ..self.clone()
+        }
+    }
+    /// Ask the running operation and everything after it to stop.
+    ///
+    /// The file that is being copied is stopped too, and its partial copy is removed.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+    /// Allow operations to run again after they were cancelled.
+    pub fn reset(&self) {
+        self.cancel.store(false, Ordering::SeqCst);
+    }
+    /// Try the items that were cancelled again.
+    pub fn forget_cancelled(&self) {
+        if let Ok(mut cancelled) = self.cancelled.lock() {
+            cancelled.clear();
+        }
+    }
+    /// Whether an operation on the item was cancelled while it was running.
+    pub fn was_cancelled(&self, item: &Path) -> bool {
+        self.cancelled
+            .lock()
+            .map(|cancelled| cancelled.contains(item))
+            .unwrap_or(false)
+    }
+    /// Returns [`Cancelled`] if the operation on `item` should stop.
+    pub fn check(&self, item: &Path) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            if let Ok(mut cancelled) = self.cancelled.lock() {
+                cancelled.insert(item.to_owned());
+            }
+            return Err(Cancelled(item.to_owned()).into());
+        }
+        Ok(())
+    }
+    /// Start tracking the copy of `item`.
+    pub fn start(&self, item: &Path, total_bytes: u64, total_files: usize) -> Status {
+        let status = Status::new(item, total_bytes, total_files);
+        if let Ok(mut last_report) = self.last_report.lock() {
+            *last_report = None;
+        }
+        status
+    }
+    /// Pass the status on, unless the operation has only just started
+    /// or the previous report was moments ago.
+    pub fn report(&self, status: &Status) {
+        let handler = match &self.handler {
+            Some(handler) => handler,
+            None => return,
+        };
+        if status.started.elapsed() < REPORT_DELAY {
+            return;
+        }
+        if let Ok(mut last_report) = self.last_report.lock() {
+            if matches!(*last_report, Some(last) if last.elapsed() < REPORT_INTERVAL) {
+                return;
+            }
+            *last_report = Some(Instant::now());
+        }
+        handler(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cancelled, Progress};
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn cancel() {
+        let progress = Progress::default();
+        let item = Path::new("/folder/file");
+        assert!(progress.check(item).is_ok());
+        progress.clone().cancel();
+        let e = progress.check(item).unwrap_err();
+        assert!(e.is::<Cancelled>());
+        assert!(progress.was_cancelled(item));
+        assert!(!progress.was_cancelled(Path::new("/folder")));
+        progress.reset();
+        assert!(progress.check(item).is_ok());
+    }
+
+    #[test]
+    fn throttle() {
+        let reports = Arc::new(Mutex::new(0));
+        let counter = reports.clone();
+        let progress = Progress::default().with_handler(move |_| *counter.lock().unwrap() += 1);
+        let mut status = progress.start(Path::new("/file"), 100, 1);
+        progress.report(&status);
+        assert_eq!(*reports.lock().unwrap(), 0);
+        status.started = Instant::now() - Duration::from_secs(1);
+        status.copied_bytes = 50;
+        progress.report(&status);
+        progress.report(&status);
+        assert_eq!(*reports.lock().unwrap(), 1);
+        assert_eq!(status.fraction(), 0.5);
+        assert!(status.eta().unwrap() <= Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,950 @@
+//! A textual syntax for tag expressions, such as
+//! `type:file AND ext:pdf,djvu AND created>30d AND NOT name~"^invoice"`.
+//!
+//! `NOT` binds tighter than `AND`, which binds tighter than `OR`,
+//! and parentheses group expressions explicitly.
+//! Printing an expression and parsing it back gives the same expression.
+//!
+//! The times of a file are compared either with a duration, such as `modified>90d`,
+//! which means more than 90 days ago, or with a date, such as `modified<2024-01-01`.
+//! They can also be `today`, `this-week`, `this-month`, `this-year` or `weekend`,
+//! and `created<this-year` means before the start of this year.
+//!
+//! Keys that match the name, like `glob`, `prefix` and `ext`, have a variant starting with `i`,
+//! such as `iglob:"img_*"`, that ignores the letter case.
+//!
+//! `taken` is when a photo was taken, such as `taken:weekend`.
+//! `camera:nikon` matches the make or the model of the camera
+//! and `orientation:6` the EXIF orientation of the photo.
+//!
+//! `width`, `height` and `megapixels` compare the dimensions of images, such as `width>1920`,
+//! `ratio:16/9` matches their aspect ratio, and `is:portrait` and `is:landscape` their shape.
+//!
+//! `artist`, `album` and `genre` match the tags of audio files, such as `artist:"daft punk"`,
+//! while `year` compares their release year and `duration` how long they play, such as `duration>10m`.
+//!
+//! `stable:1m` holds for files that have not changed for a minute and are not being downloaded.
+//!
+//! `owner` and `group` take `me`, an id or a name, such as `NOT owner:me`.
+//! `perm:4000` requires all of the octal permission bits and `anyperm:111` any of them.
+//!
+//! `content:"FATAL"` searches the content of files for text and `content~"^FATAL"`
+//! for a regular expression. Options follow the key after dots:
+//! `content.binary` also searches binary files and `content.limit-1GB` raises the size limit.
+//!
+//! A condition is named with `AS`, followed by the name of the tag and optionally
+//! its description, such as `size>1GB AS "🐘 Huge" "Files that are too big to keep"`.
+use std::{cmp::Ordering, fmt, ops::Range, time::Duration};
+
+use byte_unit::Byte;
+use chrono::NaiveDate;
+use regex::Regex;
+
+use super::{
+    parse_ratio, validate_mime, Account, Base, ContentSearch, FileType, Period, Tag, TagExpr,
+    TagLibrary, Timestamp,
+};
+
+/// Keys that can be used in a query, shown when an unknown one is used.
+const KEYS: &str = concat!(
+    "type, name, glob, prefix, suffix, contains, ext, ",
+    "size, children, created, modified, accessed, taken, stable, ",
+    "content, mime, owner, group, perm, anyperm, camera, orientation, ",
+    "width, height, megapixels, ratio, artist, album, genre, year, duration, is"
+);
+
+/// Kinds of files that can follow `is:`.
+const KINDS: &str = concat!(
+    "image, video, audio, document, archive, book, duplicate, ",
+    "geotagged, portrait, landscape"
+);
+
+/// A query that cannot be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    /// Byte range of the query the error refers to.
+    pub span: Range<usize>,
+}
+
+impl QueryError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+        QueryError {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (at {}..{})",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Parse a query into a tag expression.
+///
+/// Conditions without a name get the name of the first tag of the library with the condition.
+pub fn parse_query(query: &str, library: &TagLibrary) -> Result<TagExpr, QueryError> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        pos: 0,
+        end: query.len(),
+        library,
+    };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some((Token::Close, span)) => Err(QueryError::new("Unexpected `)`", span)),
+        Some((_, span)) => Err(QueryError::new("Expected `AND` or `OR`", span)),
+    }
+}
+
+impl TagExpr {
+    /// Print the expression in the syntax understood by [`parse_query`].
+    pub fn to_query(&self, library: &TagLibrary) -> String {
+        match self {
+            TagExpr::Tag(tag) => tag_query(tag, library),
+            TagExpr::Not(expr) => match **expr {
+                TagExpr::And(_) | TagExpr::Or(_) => format!("NOT ({})", expr.to_query(library)),
+                TagExpr::Tag(_) | TagExpr::Not(_) => format!("NOT {}", expr.to_query(library)),
+            },
+            TagExpr::And(exprs) => join_queries(exprs, " AND ", library),
+            TagExpr::Or(exprs) => join_queries(exprs, " OR ", library),
+        }
+    }
+}
+
+/// The condition of the tag, followed by its name and description
+/// unless they are the ones the condition gets when it is parsed.
+fn tag_query(tag: &Tag, library: &TagLibrary) -> String {
+    let query = tag.basis.to_query();
+    if tag_for(tag.basis.clone(), library) == *tag {
+        query
+    } else if named_tag(tag.basis.clone(), tag.name.clone(), None, library) == *tag {
+        format!("{query} AS {}", quote(&tag.name))
+    } else {
+        format!("{query} AS {} {}", quote(&tag.name), quote(&tag.desc))
+    }
+}
+
+/// Nested groups are always put in parentheses,
+/// so they are parsed back into the same tree.
+fn join_queries(exprs: &[TagExpr], separator: &str, library: &TagLibrary) -> String {
+    exprs
+        .iter()
+        .map(|expr| {
+            if matches!(expr, TagExpr::And(_) | TagExpr::Or(_)) {
+                format!("({})", expr.to_query(library))
+            } else {
+                expr.to_query(library)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+impl Base {
+    /// Print the condition as a single term of a query.
+    pub fn to_query(&self) -> String {
+        match self {
+            Base::Type(FileType::File) => "type:file".into(),
+            Base::Type(FileType::Dir) => "type:dir".into(),
+            Base::Type(FileType::Symlink) => "type:symlink".into(),
+            Base::Name(name) => format!("name:{}", quote(name)),
+            Base::NameRegex(regex) => format!("name~{}", quote(regex)),
+            Base::NameGlob {
+                pattern,
+                ignore_case,
+            } => format!("{}glob:{}", case_prefix(*ignore_case), quote(pattern)),
+            Base::NamePrefix {
+                prefix,
+                ignore_case,
+            } => format!("{}prefix:{}", case_prefix(*ignore_case), quote(prefix)),
+            Base::NameSuffix {
+                suffix,
+                ignore_case,
+            } => format!("{}suffix:{}", case_prefix(*ignore_case), quote(suffix)),
+            Base::NameContains { text, ignore_case } => {
+                format!("{}contains:{}", case_prefix(*ignore_case), quote(text))
+            }
+            Base::Mime(pattern) => format!("mime:{}", word_or_quote(pattern)),
+            Base::User(account) => format!("owner:{}", word_or_quote(&account.to_string())),
+            Base::Group(account) => format!("group:{}", word_or_quote(&account.to_string())),
+            Base::ModeAll(bits) => format!("perm:{bits:04o}"),
+            Base::ModeAny(bits) => format!("anyperm:{bits:04o}"),
+            Base::StableFor(duration) => format!("stable:{}", format_duration(duration)),
+            Base::Camera(text) => format!("camera:{}", word_or_quote(text)),
+            Base::HasGps => "is:geotagged".into(),
+            Base::Orientation(orientation) => format!("orientation:{orientation}"),
+            Base::WidthLT(width) => format!("width<{width}"),
+            Base::WidthGT(width) => format!("width>{width}"),
+            Base::HeightLT(height) => format!("height<{height}"),
+            Base::HeightGT(height) => format!("height>{height}"),
+            Base::MegapixelsLT(megapixels) => format!("megapixels<{megapixels}"),
+            Base::MegapixelsGT(megapixels) => format!("megapixels>{megapixels}"),
+            Base::AspectRatio(width, height) => format!("ratio:{width}/{height}"),
+            Base::IsPortrait => "is:portrait".into(),
+            Base::IsLandscape => "is:landscape".into(),
+            Base::Artist(text) => format!("artist:{}", word_or_quote(text)),
+            Base::Album(text) => format!("album:{}", word_or_quote(text)),
+            Base::Genre(text) => format!("genre:{}", word_or_quote(text)),
+            Base::YearLT(year) => format!("year<{year}"),
+            Base::YearET(year) => format!("year={year}"),
+            Base::YearGT(year) => format!("year>{year}"),
+            Base::DurationLT(duration) => format!("duration<{}", format_duration(duration)),
+            Base::DurationGT(duration) => format!("duration>{}", format_duration(duration)),
+            Base::Extension(extensions) => format!("ext:{}", format_list(extensions)),
+            Base::ExtensionIgnoreCase(extensions) => format!("iext:{}", format_list(extensions)),
+            Base::SizeLT(size) => format!("size<{}", format_size(size)),
+            Base::SizeGT(size) => format!("size>{}", format_size(size)),
+            Base::ChildrenCountLT(count) => format!("children<{count}"),
+            Base::ChildrenCountET(count) => format!("children={count}"),
+            Base::ChildrenCountGT(count) => format!("children>{count}"),
+            Base::LifetimeLT(duration) => format!("created<{}", format_duration(duration)),
+            Base::LifetimeGT(duration) => format!("created>{}", format_duration(duration)),
+            Base::AgeLT(timestamp, duration) => {
+                format!("{}<{}", timestamp.name(), format_duration(duration))
+            }
+            Base::AgeGT(timestamp, duration) => {
+                format!("{}>{}", timestamp.name(), format_duration(duration))
+            }
+            Base::DateBefore(timestamp, date) => {
+                format!("{}<{}", timestamp.name(), date.format(DATE_FORMAT))
+            }
+            Base::DateAfter(timestamp, date) => {
+                format!("{}>{}", timestamp.name(), date.format(DATE_FORMAT))
+            }
+            Base::InCurrent(timestamp, period) => {
+                format!("{}:{}", timestamp.name(), period_query(*period))
+            }
+            Base::BeforeCurrent(timestamp, period) => {
+                format!("{}<{}", timestamp.name(), period_query(*period))
+            }
+            Base::OnWeekend(timestamp) => format!("{}:weekend", timestamp.name()),
+            Base::Content(search) => {
+                let mut key = format!("{}content", case_prefix(search.ignore_case));
+                if search.binary {
+                    key += ".binary";
+                }
+                if search.max_size != ContentSearch::default_max_size() {
+                    key += &format!(".limit-{}", format_size(&search.max_size));
+                }
+                let op = if search.regex { '~' } else { ':' };
+                format!("{key}{op}{}", quote(&search.text))
+            }
+            Base::IsImage => "is:image".into(),
+            Base::IsVideo => "is:video".into(),
+            Base::IsAudio => "is:audio".into(),
+            Base::IsDocument => "is:document".into(),
+            Base::IsArchive => "is:archive".into(),
+            Base::IsBook => "is:book".into(),
+            Base::IsDuplicate => "is:duplicate".into(),
+        }
+    }
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+fn period_query(period: Period) -> &'static str {
+    match period {
+        Period::Day => "today",
+        Period::Week => "this-week",
+        Period::Month => "this-month",
+        Period::Year => "this-year",
+    }
+}
+
+fn case_prefix(ignore_case: bool) -> &'static str {
+    if ignore_case {
+        "i"
+    } else {
+        ""
+    }
+}
+
+fn format_list(values: &[String]) -> String {
+    word_or_quote(&values.join(","))
+}
+
+fn word_or_quote(s: &str) -> String {
+    if s.chars().all(is_word_char) && !s.is_empty() {
+        s.to_owned()
+    } else {
+        quote(s)
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The shortest exact representation, such as `250MB` or `1GiB`.
+fn format_size(size: &Byte) -> String {
+    const UNITS: [(&str, u128); 8] = [
+        ("TiB", 1 << 40),
+        ("TB", 1_000_000_000_000),
+        ("GiB", 1 << 30),
+        ("GB", 1_000_000_000),
+        ("MiB", 1 << 20),
+        ("MB", 1_000_000),
+        ("KiB", 1 << 10),
+        ("KB", 1_000),
+    ];
+    let bytes = size.get_bytes();
+    UNITS
+        .iter()
+        .find(|(_, unit)| bytes != 0 && bytes.is_multiple_of(*unit))
+        .map(|(name, unit)| format!("{}{name}", bytes / unit))
+        .unwrap_or_else(|| format!("{bytes}B"))
+}
+
+/// The shortest exact representation, such as `30d` or `90m`.
+fn format_duration(duration: &Duration) -> String {
+    const UNITS: [(&str, u64); 5] = [
+        ("w", 7 * 24 * 60 * 60),
+        ("d", 24 * 60 * 60),
+        ("h", 60 * 60),
+        ("m", 60),
+        ("s", 1),
+    ];
+    let secs = duration.as_secs();
+    if duration.subsec_nanos() != 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    UNITS
+        .iter()
+        .find(|(_, unit)| secs != 0 && secs.is_multiple_of(*unit))
+        .map(|(name, unit)| format!("{}{name}", secs / unit))
+        .unwrap_or_else(|| "0s".into())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A key, a value or one of `AND`, `OR` and `NOT`.
+    Word(String),
+    /// A value in double quotes.
+    Quoted(String),
+    /// One of `:`, `<`, `>`, `=` and `~`.
+    Op(char),
+    Open,
+    Close,
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !"()<>:=~\"".contains(c)
+}
+
+fn tokenize(query: &str) -> Result<Vec<(Token, Range<usize>)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ':' | '<' | '>' | '=' | '~' => Token::Op(c),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => value.push(c),
+                            None => break,
+                        },
+                        Some((_, c)) => value.push(c),
+                        None => {
+                            return Err(QueryError::new(
+                                "Missing closing quote",
+                                start..query.len(),
+                            ))
+                        }
+                    }
+                }
+                Token::Quoted(value)
+            }
+            _ => {
+                let mut word = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        let end = chars.peek().map_or(query.len(), |(i, _)| *i);
+        tokens.push((token, start..end));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+    /// Length of the query, used for errors at its end.
+    end: usize,
+    /// Tags that the conditions are named after.
+    library: &'a TagLibrary,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(Token, Range<usize>)> {
+        self.tokens.get(self.pos).cloned()
+    }
+    fn next(&mut self) -> Option<(Token, Range<usize>)> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+    fn at_end(&self) -> Range<usize> {
+        self.end..self.end
+    }
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some((Token::Word(word), _)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+    fn or(&mut self) -> Result<TagExpr, QueryError> {
+        let mut exprs = vec![self.and()?];
+        while self.keyword("OR") {
+            exprs.push(self.and()?);
+        }
+        Ok(group(exprs, TagExpr::Or))
+    }
+    fn and(&mut self) -> Result<TagExpr, QueryError> {
+        let mut exprs = vec![self.unary()?];
+        while self.keyword("AND") {
+            exprs.push(self.unary()?);
+        }
+        Ok(group(exprs, TagExpr::And))
+    }
+    fn unary(&mut self) -> Result<TagExpr, QueryError> {
+        if self.keyword("NOT") {
+            return Ok(TagExpr::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some((Token::Open, open)) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some((Token::Close, _)) => Ok(expr),
+                    _ => Err(QueryError::new("Missing closing parenthesis", open)),
+                }
+            }
+            Some((Token::Word(key), key_span)) => self.term(&key, key_span),
+            Some((Token::Close, span)) => Err(QueryError::new("Unexpected `)`", span)),
+            Some((_, span)) => Err(QueryError::new("Expected a key such as `type`", span)),
+            None => Err(QueryError::new("Expected a tag", self.at_end())),
+        }
+    }
+    /// A single condition such as `size<1MB`.
+    fn term(&mut self, key: &str, key_span: Range<usize>) -> Result<TagExpr, QueryError> {
+        let (op, op_span) = match self.next() {
+            Some((Token::Op(op), span)) => (op, span),
+            Some((_, span)) => {
+                return Err(QueryError::new(
+                    format!("Expected `:`, `<`, `>`, `=` or `~` after `{key}`"),
+                    span,
+                ))
+            }
+            None => {
+                return Err(QueryError::new(
+                    format!("Expected `:`, `<`, `>`, `=` or `~` after `{key}`"),
+                    self.at_end(),
+                ))
+            }
+        };
+        let (value, span) = match self.next() {
+            Some((Token::Word(value) | Token::Quoted(value), span)) => (value, span),
+            Some((_, span)) => {
+                return Err(QueryError::new(
+                    format!("Expected a value after `{op}`"),
+                    span,
+                ))
+            }
+            None => {
+                return Err(QueryError::new(
+                    format!("Expected a value after `{op}`"),
+                    self.at_end(),
+                ))
+            }
+        };
+        let error = |message: String| QueryError::new(message, span.clone());
+        let unsupported = |ops: &str| {
+            QueryError::new(
+                format!("`{key}` can only be used with {ops}"),
+                op_span.clone(),
+            )
+        };
+        let key_lower = key.to_ascii_lowercase();
+        // Options follow the key after dots, such as `content.binary`
+        let (key_lower, options) = match key_lower.split_once('.') {
+            Some((key_lower, options)) => (key_lower.to_owned(), options.split('.').collect()),
+            None => (key_lower.clone(), Vec::new()),
+        };
+        if !options.is_empty() && !matches!(key_lower.as_str(), "content" | "icontent") {
+            return Err(QueryError::new(
+                format!("`{key_lower}` has no options"),
+                key_span,
+            ));
+        }
+        // Only used by the keys that match the name
+        let ignore_case = key_lower.starts_with('i');
+        let basis = match (key_lower.as_str(), op) {
+            ("type", ':') => match value.to_ascii_lowercase().as_str() {
+                "file" => Base::Type(FileType::File),
+                "dir" | "folder" => Base::Type(FileType::Dir),
+                "symlink" | "link" => Base::Type(FileType::Symlink),
+                _ => {
+                    return Err(error(format!(
+                        "Unknown type `{value}`, expected file, dir or symlink"
+                    )))
+                }
+            },
+            ("type", _) => return Err(unsupported("`:`")),
+            ("name", ':') => Base::Name(value),
+            ("name", '~') => match Regex::new(&value) {
+                Ok(_) => Base::NameRegex(value),
+                Err(e) => return Err(error(format!("Invalid regular expression: {e}"))),
+            },
+            ("name", _) => return Err(unsupported("`:` or `~`")),
+            ("glob" | "iglob", ':') => match glob::Pattern::new(&value) {
+                Ok(_) => Base::NameGlob {
+                    pattern: value,
+                    ignore_case,
+                },
+                Err(e) => return Err(error(format!("Invalid glob: {e}"))),
+            },
+            ("prefix" | "iprefix", ':') => Base::NamePrefix {
+                prefix: value,
+                ignore_case,
+            },
+            ("suffix" | "isuffix", ':') => Base::NameSuffix {
+                suffix: value,
+                ignore_case,
+            },
+            ("contains" | "icontains", ':') => Base::NameContains {
+                text: value,
+                ignore_case,
+            },
+            ("ext" | "iext", ':') => {
+                let extensions = value
+                    .split(',')
+                    .map(|ext| ext.trim().trim_start_matches('.').to_owned())
+                    .collect::<Vec<_>>();
+                if extensions.iter().any(String::is_empty) {
+                    return Err(error("Extensions cannot be empty".into()));
+                }
+                if ignore_case {
+                    Base::ExtensionIgnoreCase(extensions)
+                } else {
+                    Base::Extension(extensions)
+                }
+            }
+            (
+                "glob" | "iglob" | "prefix" | "iprefix" | "suffix" | "isuffix" | "contains"
+                | "icontains" | "ext" | "iext",
+                _,
+            ) => return Err(unsupported("`:`")),
+            ("size", '<' | '>') => {
+                let size = Byte::from_str(&value)
+                    .map_err(|e| error(format!("Invalid size `{value}`: {e}")))?;
+                if op == '<' {
+                    Base::SizeLT(size)
+                } else {
+                    Base::SizeGT(size)
+                }
+            }
+            ("size", _) => return Err(unsupported("`<` or `>`")),
+            ("children", '<' | '=' | '>') => {
+                let count = value
+                    .parse()
+                    .map_err(|_| error(format!("`{value}` is not a number")))?;
+                match op {
+                    '<' => Base::ChildrenCountLT(count),
+                    '=' => Base::ChildrenCountET(count),
+                    _ => Base::ChildrenCountGT(count),
+                }
+            }
+            ("children", _) => return Err(unsupported("`<`, `=` or `>`")),
+            ("created" | "modified" | "accessed" | "taken", '<' | '>' | ':') => {
+                let timestamp = Timestamp::ALL
+                    .into_iter()
+                    .find(|timestamp| timestamp.name() == key_lower)
+                    .expect("unknown timestamp");
+                let value_lower = value.to_ascii_lowercase();
+                let period = Period::ALL
+                    .into_iter()
+                    .find(|period| period_query(*period) == value_lower);
+                match (op, period) {
+                    (':', Some(period)) => Base::InCurrent(timestamp, period),
+                    (':', None) if value_lower == "weekend" => Base::OnWeekend(timestamp),
+                    (':', None) => {
+                        return Err(error(format!(
+                            "Unknown period `{value}`, expected today, this-week, this-month, this-year or weekend"
+                        )))
+                    }
+                    ('<', Some(period)) => Base::BeforeCurrent(timestamp, period),
+                    ('>', Some(_)) => return Err(unsupported("`<` before a period")),
+                    _ => match NaiveDate::parse_from_str(&value, DATE_FORMAT) {
+                        Ok(date) if op == '<' => Base::DateBefore(timestamp, date),
+                        Ok(date) => Base::DateAfter(timestamp, date),
+                        Err(_) => {
+                            let duration: Duration =
+                                duration_string::DurationString::try_from(value.clone())
+                                    .map_err(|e| {
+                                        error(format!("Invalid duration or date `{value}`: {e}"))
+                                    })?
+                                    .into();
+                            let ordering = if op == '<' {
+                                Ordering::Less
+                            } else {
+                                Ordering::Greater
+                            };
+                            Base::age(timestamp, ordering, duration)
+                        }
+                    },
+                }
+            }
+            ("content" | "icontent", ':' | '~') => {
+                let mut search = ContentSearch::new(value, op == '~', ignore_case);
+                for option in options {
+                    if option == "binary" {
+                        search.binary = true;
+                    } else if let Some(limit) = option.strip_prefix("limit-") {
+                        search.max_size = Byte::from_str(limit).map_err(|e| {
+                            QueryError::new(
+                                format!("Invalid size `{limit}`: {e}"),
+                                key_span.clone(),
+                            )
+                        })?;
+                    } else {
+                        return Err(QueryError::new(
+                            format!("Unknown option `{option}`, expected binary or limit-<size>"),
+                            key_span,
+                        ));
+                    }
+                }
+                if let Err(e) = search.validate() {
+                    return Err(error(format!("Invalid regular expression: {e}")));
+                }
+                Base::Content(search)
+            }
+            ("content" | "icontent", _) => return Err(unsupported("`:` or `~`")),
+            ("camera", ':') => Base::Camera(value),
+            ("orientation", ':') => match value.parse() {
+                Ok(orientation @ 1..=8) => Base::Orientation(orientation),
+                _ => {
+                    return Err(error(format!(
+                        "`{value}` is not an orientation from 1 to 8"
+                    )))
+                }
+            },
+            ("camera" | "orientation", _) => return Err(unsupported("`:`")),
+            ("width" | "height", '<' | '>') => {
+                let pixels = value
+                    .parse()
+                    .map_err(|_| error(format!("`{value}` is not a number of pixels")))?;
+                match (key_lower.as_str(), op) {
+                    ("width", '<') => Base::WidthLT(pixels),
+                    ("width", _) => Base::WidthGT(pixels),
+                    (_, '<') => Base::HeightLT(pixels),
+                    _ => Base::HeightGT(pixels),
+                }
+            }
+            ("megapixels", '<' | '>') => {
+                let megapixels = value
+                    .parse()
+                    .ok()
+                    .filter(|megapixels: &f64| megapixels.is_finite() && *megapixels >= 0.)
+                    .ok_or_else(|| error(format!("`{value}` is not a number of megapixels")))?;
+                if op == '<' {
+                    Base::MegapixelsLT(megapixels)
+                } else {
+                    Base::MegapixelsGT(megapixels)
+                }
+            }
+            ("width" | "height" | "megapixels", _) => return Err(unsupported("`<` or `>`")),
+            ("ratio", ':') => {
+                let (width, height) = parse_ratio(&value).map_err(|e| error(e.to_string()))?;
+                Base::AspectRatio(width, height)
+            }
+            ("ratio", _) => return Err(unsupported("`:`")),
+            ("artist", ':') => Base::Artist(value),
+            ("album", ':') => Base::Album(value),
+            ("genre", ':') => Base::Genre(value),
+            ("artist" | "album" | "genre", _) => return Err(unsupported("`:`")),
+            ("year", '<' | '=' | '>') => {
+                let year = value
+                    .parse()
+                    .map_err(|_| error(format!("`{value}` is not a year")))?;
+                match op {
+                    '<' => Base::YearLT(year),
+                    '=' => Base::YearET(year),
+                    _ => Base::YearGT(year),
+                }
+            }
+            ("year", _) => return Err(unsupported("`<`, `=` or `>`")),
+            ("duration", '<' | '>') => {
+                let duration: Duration = duration_string::DurationString::try_from(value.clone())
+                    .map_err(|e| error(format!("Invalid duration `{value}`: {e}")))?
+                    .into();
+                if op == '<' {
+                    Base::DurationLT(duration)
+                } else {
+                    Base::DurationGT(duration)
+                }
+            }
+            ("duration", _) => return Err(unsupported("`<` or `>`")),
+            ("stable", ':') => {
+                let duration = duration_string::DurationString::try_from(value.clone())
+                    .map_err(|e| error(format!("Invalid duration `{value}`: {e}")))?;
+                Base::StableFor(duration.into())
+            }
+            ("stable", _) => return Err(unsupported("`:`")),
+            ("mime", ':') => match validate_mime(&value) {
+                Ok(()) => Base::Mime(value.to_ascii_lowercase()),
+                Err(e) => return Err(error(e.to_string())),
+            },
+            ("mime", _) => return Err(unsupported("`:`")),
+            ("owner", ':') => Base::User(Account::parse(&value)),
+            ("group", ':') => Base::Group(Account::parse(&value)),
+            ("perm" | "anyperm", ':') => match u32::from_str_radix(&value, 8) {
+                Ok(bits) if bits <= 0o7777 => {
+                    if key_lower == "perm" {
+                        Base::ModeAll(bits)
+                    } else {
+                        Base::ModeAny(bits)
+                    }
+                }
+                _ => {
+                    return Err(error(format!(
+                        "`{value}` is not an octal mode such as 0755"
+                    )))
+                }
+            },
+            ("owner" | "group" | "perm" | "anyperm", _) => return Err(unsupported("`:`")),
+            ("is", ':') => match value.to_ascii_lowercase().as_str() {
+                "image" => Base::IsImage,
+                "video" => Base::IsVideo,
+                "audio" => Base::IsAudio,
+                "document" => Base::IsDocument,
+                "archive" => Base::IsArchive,
+                "book" => Base::IsBook,
+                "duplicate" => Base::IsDuplicate,
+                "geotagged" => Base::HasGps,
+                "portrait" => Base::IsPortrait,
+                "landscape" => Base::IsLandscape,
+                _ => {
+                    return Err(error(format!(
+                        "Unknown kind `{value}`, expected one of {KINDS}"
+                    )))
+                }
+            },
+            ("is", _) => return Err(unsupported("`:`")),
+            _ => {
+                return Err(QueryError::new(
+                    format!("Unknown key `{key}`, expected one of {KEYS}"),
+                    key_span,
+                ))
+            }
+        };
+        if !self.keyword("AS") {
+            return Ok(TagExpr::Tag(tag_for(basis, self.library)));
+        }
+        let name = match self.next() {
+            Some((Token::Word(name) | Token::Quoted(name), span)) => {
+                if name.trim().is_empty() {
+                    return Err(QueryError::new("The name of the tag is empty", span));
+                }
+                name
+            }
+            Some((_, span)) => return Err(QueryError::new("Expected a name after `AS`", span)),
+            None => return Err(QueryError::new("Expected a name after `AS`", self.at_end())),
+        };
+        let desc = match self.peek() {
+            Some((Token::Quoted(desc), _)) => {
+                self.pos += 1;
+                Some(desc)
+            }
+            _ => None,
+        };
+        Ok(TagExpr::Tag(named_tag(basis, name, desc, self.library)))
+    }
+}
+
+fn group(mut exprs: Vec<TagExpr>, f: fn(Vec<TagExpr>) -> TagExpr) -> TagExpr {
+    if exprs.len() == 1 {
+        exprs.pop().expect("no expression")
+    } else {
+        f(exprs)
+    }
+}
+
+/// A tag with the condition and the name, described by `desc`,
+/// or like the tag of the library with the same name if there is no description.
+fn named_tag(basis: Base, name: String, desc: Option<String>, library: &TagLibrary) -> Tag {
+    let desc = desc.unwrap_or_else(|| {
+        library
+            .tags()
+            .find(|tag| tag.name == name && tag.basis == basis)
+            .map_or_else(
+                || tag_for(basis.clone(), library).desc,
+                |tag| tag.desc.clone(),
+            )
+    });
+    Tag { name, desc, basis }
+}
+
+/// The first tag of the library with the condition, or a custom one named after the query.
+fn tag_for(basis: Base, library: &TagLibrary) -> Tag {
+    library
+        .tags()
+        .find(|tag| tag.basis == basis)
+        .cloned()
+        .unwrap_or_else(|| {
+            let query = basis.to_query();
+            Tag {
+                name: query.clone(),
+                desc: format!("A custom tag for files that match `{query}`."),
+                basis,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_query;
+    use crate::lib::{custom_name_tag, library_tag, Base, NameMatch, Tag, TagExpr, TagLibrary};
+
+    #[test]
+    fn round_trip() {
+        let library = TagLibrary::default();
+        let query = r#"type:file AND ext:pdf,djvu AND created>30d AND NOT name~"^invoice""#;
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query = r#"(is:image OR is:video) AND NOT size<1MB AND (name:"a \"b\"" OR children=0 OR (size>1KiB AND created<90m))"#;
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        assert_eq!(
+            parse_query(&expr.to_query(&library), &library).unwrap(),
+            expr
+        );
+        let query =
+            "NOT owner:me AND (group:staff OR owner:1000) AND perm:0002 AND NOT anyperm:6000";
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query = r#"taken:weekend AND camera:"EOS R" AND orientation:6 AND NOT is:geotagged"#;
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query =
+            "(width>1919 AND ratio:16/9) OR (is:portrait AND megapixels<2.5 AND height<3000)";
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query = r#"artist:"Daft Punk" AND NOT genre:house AND year>1999 AND duration<5m"#;
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query = "mime:image/* AND NOT mime:image/gif AND stable:30s";
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query = "modified>12w OR accessed<1d OR (created<2024-01-01 AND modified>2023-06-30) OR created:this-month OR accessed<this-year OR created:weekend";
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query =
+            r#"content:"FATAL" OR icontent.binary~"^tax no\\. 123" OR content.limit-1GiB:"x""#;
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        let query = r#"iglob:"img_*.jp*g" AND prefix:"2023-" AND NOT (isuffix:".bak" OR contains:"draft") AND iext:heic"#;
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+    }
+
+    #[test]
+    fn round_trip_names() {
+        let library = TagLibrary::default();
+        let size = Tag {
+            name: "💾 < 5MB".into(),
+            desc: "A custom tag which includes files that are < 5MB in size.".into(),
+            basis: Base::SizeLT(byte_unit::Byte::from_bytes(5_000_000)),
+        };
+        let name = custom_name_tag(NameMatch::Prefix, "IMG_", true).unwrap();
+        let expr = TagExpr::Or(vec![
+            TagExpr::Tag(size),
+            TagExpr::Not(Box::new(TagExpr::Tag(name))),
+        ]);
+        assert_eq!(
+            parse_query(&expr.to_query(&library), &library).unwrap(),
+            expr
+        );
+        // The description is left out if it is the one the name gets anyway
+        let query = r#"ext:pdf AS "📚 Papers" AND size<1MB"#;
+        let expr = parse_query(query, &library).unwrap();
+        assert_eq!(expr.to_query(&library), query);
+        assert!(matches!(&expr, TagExpr::And(exprs)
+            if matches!(&exprs[0], TagExpr::Tag(tag) if tag.name == "📚 Papers")));
+    }
+
+    #[test]
+    fn library_names() {
+        let mut library = TagLibrary::default();
+        let wallpaper = library_tag("🖥️ Wallpaper", "Wide images.", "ratio:16/9").unwrap();
+        library.save(&wallpaper).unwrap();
+        let expr = parse_query("ratio:16/9", &library).unwrap();
+        assert_eq!(expr, TagExpr::Tag(wallpaper));
+        assert_eq!(expr.to_query(&library), "ratio:16/9");
+        // Without the tag in the library the name is kept in the query
+        let query = expr.to_query(&TagLibrary::default());
+        assert_eq!(query, r#"ratio:16/9 AS "🖥️ Wallpaper" "Wide images.""#);
+        assert_eq!(parse_query(&query, &TagLibrary::default()).unwrap(), expr);
+    }
+
+    #[test]
+    fn precedence() {
+        let library = TagLibrary::default();
+        let expr = parse_query("is:image or is:video and not is:book", &library).unwrap();
+        assert!(matches!(&expr, TagExpr::Or(exprs)
+            if matches!(&exprs[..], [TagExpr::Tag(_), TagExpr::And(_)])));
+        assert_eq!(
+            expr.to_query(&library),
+            "is:image OR (is:video AND NOT is:book)"
+        );
+        let expr = parse_query("size<1MB", &library).unwrap();
+        assert!(matches!(expr, TagExpr::Tag(tag) if tag.name == "💾 < 1MB"));
+        let expr = parse_query("children>3", &library).unwrap();
+        assert!(
+            matches!(expr, TagExpr::Tag(tag) if tag.basis == Base::ChildrenCountGT(3) && tag.name == "children>3")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let library = TagLibrary::default();
+        let error = |query| parse_query(query, &library).unwrap_err();
+        assert_eq!(error("type:file AND colour:red").span, 14..20);
+        assert_eq!(error("size:1MB").span, 4..5);
+        assert_eq!(error("size<1QB").span, 5..8);
+        assert_eq!(error("created:yesterday").span, 8..17);
+        assert_eq!(error("created>today").span, 7..8);
+        assert_eq!(error("content.big:x").span, 0..11);
+        assert_eq!(error("mime:image").span, 5..10);
+        assert_eq!(error("perm:999").span, 5..8);
+        assert_eq!(error("size.binary<1MB").span, 0..11);
+        assert_eq!(error("(is:image OR is:video").span, 0..1);
+        assert_eq!(error("is:image is:video").span, 9..11);
+        assert_eq!(error("is:image AND").span, 12..12);
+        assert_eq!(error(r#"name~"(""#).span, 5..8);
+        assert_eq!(error(r#"name:"abc"#).span, 5..9);
+        assert_eq!(error("size<1MB AS").span, 11..11);
+        assert_eq!(error(r#"size<1MB AS " ""#).span, 12..15);
+        assert_eq!(error("size<1MB AS (").span, 12..13);
+    }
+}
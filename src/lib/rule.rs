@@ -1,6 +1,7 @@
 //! Data structures and utilities related to the rule system.
-use super::Event;
+use super::{Action, Event};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Rule {
@@ -33,4 +34,16 @@ impl Rule {
     pub fn title_mut(&mut self) -> &mut String {
         &mut self.title
     }
+    /// Find out what every event of the rule would do to the folder
+    /// without changing anything on the disk.
+    ///
+    /// Each event is planned as if it was the only one,
+    /// because the changes made by the previous events are not applied.
+    pub fn plan(&self, dir: impl AsRef<Path>) -> anyhow::Result<Vec<Action>> {
+        let mut actions = Vec::new();
+        for event in &self.events {
+            actions.extend(event.plan(dir.as_ref())?);
+        }
+        Ok(actions)
+    }
 }
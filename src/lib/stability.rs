@@ -0,0 +1,161 @@
+//! Telling whether a file is still being written, such as a download in progress.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use lazy_static::lazy_static;
+
+use super::cache::make_room;
+use crate::util::PathExt;
+
+/// Extensions browsers give to downloads in progress,
+/// either to the file itself or to a companion file next to it.
+const IN_PROGRESS_EXTENSIONS: [&str; 3] = ["part", "crdownload", "download"];
+
+lazy_static! {
+    /// Last seen state of every file checked for stability.
+    ///
+    /// Kept for the whole session, so a file is tracked across the runs of the rules.
+    /// They are not saved, so after a restart every file has to settle again.
+    static ref SNAPSHOTS: Mutex<HashMap<PathBuf, Snapshot>> = Mutex::new(HashMap::new());
+}
+
+struct Snapshot {
+    /// Total size of the files inside for folders.
+    size: u64,
+    modified: SystemTime,
+    /// When the file was first seen with this size and modification time.
+    since: Instant,
+}
+
+/// Whether the file has neither changed its size nor its modification time for `duration`.
+///
+/// A file is only known to be stable once it has been checked before,
+/// so the first check of a file is never stable unless `duration` is zero.
+/// Downloads in progress are never stable.
+///
+/// Folders are only walked again once their own modification time changes,
+/// that is when an entry is added, removed or renamed in them.
+pub fn is_stable(path: &Path, duration: Duration) -> anyhow::Result<bool> {
+    if is_in_progress(path) {
+        forget(path);
+        return Ok(false);
+    }
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            forget(path);
+            return Err(e.into());
+        }
+    };
+    let modified = metadata.modified()?;
+    let size = if metadata.is_dir() {
+        let cached = SNAPSHOTS
+            .lock()
+            .expect("unable to aquire mutex")
+            .get(path)
+            .filter(|snapshot| snapshot.modified == modified)
+            .map(|snapshot| snapshot.size);
+        match cached {
+            Some(size) => size,
+            None => fs_extra::dir::get_size(path)?,
+        }
+    } else {
+        metadata.len()
+    };
+    let mut snapshots = SNAPSHOTS.lock().expect("unable to aquire mutex");
+    if !snapshots.contains_key(path) {
+        make_room(&mut snapshots);
+    }
+    let snapshot = snapshots
+        .entry(path.to_owned())
+        .and_modify(|snapshot| {
+            if snapshot.size != size || snapshot.modified != modified {
+                *snapshot = Snapshot {
+                    size,
+                    modified,
+                    since: Instant::now(),
+                };
+            }
+        })
+        .or_insert_with(|| Snapshot {
+            size,
+            modified,
+            since: Instant::now(),
+        });
+    Ok(snapshot.since.elapsed() >= duration)
+}
+
+/// Whether the file is a download in progress or has one next to it,
+/// such as `video.mp4.part` for `video.mp4`.
+pub fn is_in_progress(path: &Path) -> bool {
+    if matches!(path.ext(), Some(ext) if IN_PROGRESS_EXTENSIONS.contains(&ext.as_str())) {
+        return true;
+    }
+    let name = match path.name() {
+        Some(name) => name,
+        None => return false,
+    };
+    IN_PROGRESS_EXTENSIONS
+        .iter()
+        .any(|ext| path.with_file_name(format!("{name}.{ext}")).exists())
+}
+
+fn forget(path: &Path) {
+    if let Ok(mut snapshots) = SNAPSHOTS.lock() {
+        snapshots.remove(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_in_progress, is_stable};
+    use crate::lib::testing::test_dir;
+    use std::time::Duration;
+
+    #[test]
+    fn settle() {
+        let path = test_dir("stability").join("test1.bin");
+        std::fs::write(&path, "a").unwrap();
+        let duration = Duration::from_millis(200);
+        assert!(is_stable(&path, Duration::ZERO).unwrap());
+        assert!(!is_stable(&path, duration).unwrap());
+        std::thread::sleep(duration);
+        assert!(is_stable(&path, duration).unwrap());
+        std::fs::write(&path, "ab").unwrap();
+        assert!(!is_stable(&path, duration).unwrap());
+    }
+
+    #[test]
+    fn settle_folder() {
+        let dir = test_dir("stability").join("test3");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        let duration = Duration::from_millis(200);
+        assert!(!is_stable(&dir, duration).unwrap());
+        std::thread::sleep(duration);
+        assert!(is_stable(&dir, duration).unwrap());
+        std::fs::write(dir.join("b.txt"), "b").unwrap();
+        assert!(!is_stable(&dir, duration).unwrap());
+    }
+
+    #[test]
+    fn downloads() {
+        let path = test_dir("stability").join("test2.mp4");
+        let companion = test_dir("stability").join("test2.mp4.crdownload");
+        std::fs::write(&path, "").unwrap();
+        std::fs::write(&companion, "").unwrap();
+        assert!(is_in_progress(&path));
+        assert!(is_in_progress(&companion));
+        assert!(!is_stable(&path, Duration::ZERO).unwrap());
+        std::fs::remove_file(&companion).unwrap();
+        assert!(!is_in_progress(&path));
+        assert!(is_stable(&path, Duration::ZERO).unwrap());
+    }
+}
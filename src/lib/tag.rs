@@ -1,33 +1,126 @@
 //! Tags represent a category of files that meet a certain criteria.
-use std::{cmp::Ordering, path::Path, time::Duration};
+use std::{cmp::Ordering, collections::HashMap, path::Path, sync::Mutex, time::Duration};
 
 use crate::{lib::Item, util::PathExt};
 
-use anyhow::Context;
 use byte_unit::Byte;
+use chrono::NaiveDate;
+use glob::{MatchOptions, Pattern};
 use infer::MatcherType;
-use serde::{Deserialize, Serialize};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use super::FileType;
+use super::{
+    audio_info, detect_mime, image_dimensions, is_group, is_mime, is_stable, is_tag_match, is_user,
+    is_weekend, local_date, mode, mode_of, original_of, photo_info, today, Account, ContentSearch,
+    Dimensions, FileType, Period, Timestamp,
+};
+
+lazy_static! {
+    /// Regular expressions of the tags, compiled once as they are checked for every file.
+    static ref REGEXES: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
+    static ref GLOBS: Mutex<HashMap<String, Pattern>> = Mutex::new(HashMap::new());
+}
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub enum Base {
     Type(FileType),
     Name(String),
+    /// The name matches a regular expression.
+    NameRegex(String),
+    /// The name matches a glob pattern such as `IMG_*.jp*g`.
+    NameGlob {
+        pattern: String,
+        ignore_case: bool,
+    },
+    NamePrefix {
+        prefix: String,
+        ignore_case: bool,
+    },
+    NameSuffix {
+        suffix: String,
+        ignore_case: bool,
+    },
+    NameContains {
+        text: String,
+        ignore_case: bool,
+    },
     SizeLT(Byte),
     SizeGT(Byte),
     Extension(Vec<String>),
+    /// Like `Extension`, but `JPG` and `jpg` are the same.
+    ExtensionIgnoreCase(Vec<String>),
     ChildrenCountLT(usize),
     ChildrenCountET(usize),
     ChildrenCountGT(usize),
     LifetimeLT(Duration),
     LifetimeGT(Duration),
+    /// Like `LifetimeLT`, but for any of the times of the file.
+    AgeLT(Timestamp, Duration),
+    AgeGT(Timestamp, Duration),
+    /// The local date of the time is before the day.
+    DateBefore(Timestamp, NaiveDate),
+    /// The local date of the time is after the day.
+    DateAfter(Timestamp, NaiveDate),
+    /// The time is within the current day, week, month or year.
+    InCurrent(Timestamp, Period),
+    /// The time is before the start of the current day, week, month or year.
+    BeforeCurrent(Timestamp, Period),
+    OnWeekend(Timestamp),
+    /// The content of the file contains some text.
+    Content(ContentSearch),
+    /// The MIME type matches a pattern such as `image/png` or `image/*`.
+    Mime(String),
+    /// Owned by the user. Only supported on Unix.
+    User(Account),
+    /// Belongs to the group. Only supported on Unix.
+    Group(Account),
+    /// All of the permission bits are set. Only supported on Unix.
+    ModeAll(u32),
+    /// Any of the permission bits is set. Only supported on Unix.
+    ModeAny(u32),
+    /// The make or the model of the camera that took the photo contains the text.
+    Camera(String),
+    /// The photo records where it was taken.
+    HasGps,
+    /// The EXIF orientation of the photo, from `1` to `8`.
+    Orientation(u32),
+    /// Dimensions of images in pixels, as they are shown.
+    WidthLT(u32),
+    WidthGT(u32),
+    HeightLT(u32),
+    HeightGT(u32),
+    MegapixelsLT(f64),
+    MegapixelsGT(f64),
+    /// The width and the height of images are roughly in this ratio, such as 16 to 9.
+    AspectRatio(u32, u32),
+    /// Images that are taller than wide.
+    IsPortrait,
+    /// Images that are wider than tall.
+    IsLandscape,
+    /// The tag of audio files contains the text, ignoring letter case.
+    Artist(String),
+    Album(String),
+    Genre(String),
+    /// The year audio files were released.
+    YearLT(i32),
+    YearET(i32),
+    YearGT(i32),
+    /// How long audio files play.
+    DurationLT(Duration),
+    DurationGT(Duration),
+    /// Neither the size nor the modification time have changed for some time,
+    /// and the file is not a download in progress.
+    StableFor(Duration),
     IsImage,
     IsVideo,
     IsAudio,
     IsDocument,
     IsArchive,
     IsBook,
+    /// Has the same content as an older file in the same folder tree.
+    IsDuplicate,
 }
 
 impl Base {
@@ -35,12 +128,40 @@ impl Base {
         match self {
             Base::Type(file_type) => Ok(item.file_type() == file_type),
             Base::Name(name) => Ok(item.name().as_ref() == Some(name)),
+            Base::NameRegex(regex) => is_name_match(item, regex),
+            Base::NameGlob {
+                pattern,
+                ignore_case,
+            } => is_name_glob(item, pattern, *ignore_case),
+            Base::NamePrefix {
+                prefix,
+                ignore_case,
+            } => Ok(is_name_with(item, prefix, *ignore_case, |name, prefix| {
+                name.starts_with(prefix)
+            })),
+            Base::NameSuffix {
+                suffix,
+                ignore_case,
+            } => Ok(is_name_with(item, suffix, *ignore_case, |name, suffix| {
+                name.ends_with(suffix)
+            })),
+            Base::NameContains { text, ignore_case } => {
+                Ok(is_name_with(item, text, *ignore_case, |name, text| {
+                    name.contains(text)
+                }))
+            }
             Base::Extension(extensions) => Ok(item.file_type() == &FileType::File
                 && item
                     .path()
                     .ext()
                     .map(|ext| extensions.contains(&ext))
                     .unwrap_or(false)),
+            Base::ExtensionIgnoreCase(extensions) => Ok(item.file_type() == &FileType::File
+                && item
+                    .path()
+                    .ext()
+                    .map(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+                    .unwrap_or(false)),
             Base::SizeLT(byte) => is_size(item, Ordering::Less, byte),
             Base::SizeGT(byte) => is_size(item, Ordering::Greater, byte),
             Base::ChildrenCountLT(count) => is_children_count(item.path(), Ordering::Less, count),
@@ -48,116 +169,516 @@ impl Base {
             Base::ChildrenCountGT(count) => {
                 is_children_count(item.path(), Ordering::Greater, count)
             }
-            Base::LifetimeLT(duration) => is_lifetime(item.path(), Ordering::Less, duration),
-            Base::LifetimeGT(duration) => is_lifetime(item.path(), Ordering::Greater, duration),
+            Base::LifetimeLT(duration) => {
+                is_age(item.path(), Timestamp::Created, Ordering::Less, duration)
+            }
+            Base::LifetimeGT(duration) => {
+                is_age(item.path(), Timestamp::Created, Ordering::Greater, duration)
+            }
+            Base::AgeLT(timestamp, duration) => {
+                is_age(item.path(), *timestamp, Ordering::Less, duration)
+            }
+            Base::AgeGT(timestamp, duration) => {
+                is_age(item.path(), *timestamp, Ordering::Greater, duration)
+            }
+            Base::DateBefore(timestamp, date) => Ok(date_of(item.path(), *timestamp)? < *date),
+            Base::DateAfter(timestamp, date) => Ok(date_of(item.path(), *timestamp)? > *date),
+            Base::InCurrent(timestamp, period) => {
+                Ok(period.start(date_of(item.path(), *timestamp)?) == period.start(today()))
+            }
+            Base::BeforeCurrent(timestamp, period) => {
+                Ok(date_of(item.path(), *timestamp)? < period.start(today()))
+            }
+            Base::OnWeekend(timestamp) => Ok(is_weekend(date_of(item.path(), *timestamp)?)),
+            Base::Content(search) => search.is(item.path()),
+            // Files of an unknown type match no pattern
+            Base::Mime(pattern) => Ok(item.file_type() == &FileType::File
+                && detect_mime(item.path())?.is_some_and(|mime| is_mime(pattern, &mime))),
+            Base::User(account) => is_user(item.path(), account),
+            Base::Group(account) => is_group(item.path(), account),
+            Base::ModeAll(bits) => Ok(mode_of(item.path())? & bits == *bits),
+            Base::ModeAny(bits) => Ok(mode_of(item.path())? & bits != 0),
+            Base::StableFor(duration) => is_stable(item.path(), *duration),
+            Base::Camera(text) => Ok(photo_info(item.path())?.is_camera(text)),
+            Base::HasGps => Ok(photo_info(item.path())?.has_gps),
+            Base::Orientation(orientation) => {
+                Ok(photo_info(item.path())?.orientation == Some(*orientation))
+            }
+            Base::WidthLT(width) => is_image_with(item.path(), |image| image.width < *width),
+            Base::WidthGT(width) => is_image_with(item.path(), |image| image.width > *width),
+            Base::HeightLT(height) => is_image_with(item.path(), |image| image.height < *height),
+            Base::HeightGT(height) => is_image_with(item.path(), |image| image.height > *height),
+            Base::MegapixelsLT(megapixels) => {
+                is_image_with(item.path(), |image| image.megapixels() < *megapixels)
+            }
+            Base::MegapixelsGT(megapixels) => {
+                is_image_with(item.path(), |image| image.megapixels() > *megapixels)
+            }
+            Base::AspectRatio(width, height) => {
+                is_image_with(item.path(), |image| image.is_ratio(*width, *height))
+            }
+            Base::IsPortrait => is_image_with(item.path(), Dimensions::is_portrait),
+            Base::IsLandscape => is_image_with(item.path(), Dimensions::is_landscape),
+            Base::Artist(text) => Ok(is_tag_match(&audio_info(item.path())?.artist, text)),
+            Base::Album(text) => Ok(is_tag_match(&audio_info(item.path())?.album, text)),
+            Base::Genre(text) => Ok(is_tag_match(&audio_info(item.path())?.genre, text)),
+            Base::YearLT(year) => Ok(matches!(audio_info(item.path())?.year, Some(y) if y < *year)),
+            Base::YearET(year) => Ok(audio_info(item.path())?.year == Some(*year)),
+            Base::YearGT(year) => Ok(matches!(audio_info(item.path())?.year, Some(y) if y > *year)),
+            Base::DurationLT(duration) => {
+                Ok(matches!(audio_info(item.path())?.duration, Some(d) if d < *duration))
+            }
+            Base::DurationGT(duration) => {
+                Ok(matches!(audio_info(item.path())?.duration, Some(d) if d > *duration))
+            }
             Base::IsImage => is_matcher_type(item.path(), MatcherType::Image),
             Base::IsVideo => is_matcher_type(item.path(), MatcherType::Video),
             Base::IsAudio => is_matcher_type(item.path(), MatcherType::Audio),
             Base::IsDocument => is_matcher_type(item.path(), MatcherType::Doc),
             Base::IsArchive => is_matcher_type(item.path(), MatcherType::Archive),
             Base::IsBook => is_matcher_type(item.path(), MatcherType::Book),
+            Base::IsDuplicate => match item.root() {
+                Some(root) => Ok(original_of(item.path(), root)?.is_some()),
+                None => Ok(false),
+            },
+        }
+    }
+}
+
+impl Base {
+    /// Condition on the age of the file, using the older variants for the creation time.
+    pub fn age(timestamp: Timestamp, ordering: Ordering, duration: Duration) -> Base {
+        match (timestamp, ordering) {
+            (Timestamp::Created, Ordering::Less) => Base::LifetimeLT(duration),
+            (Timestamp::Created, _) => Base::LifetimeGT(duration),
+            (_, Ordering::Less) => Base::AgeLT(timestamp, duration),
+            (_, _) => Base::AgeGT(timestamp, duration),
         }
     }
 }
 
-fn is_lifetime(path: &Path, ordering: Ordering, duration: &Duration) -> anyhow::Result<bool> {
-    let then = std::fs::metadata(path)?.created()?;
+fn is_age(
+    path: &Path,
+    timestamp: Timestamp,
+    ordering: Ordering,
+    duration: &Duration,
+) -> anyhow::Result<bool> {
+    let then = timestamp.read(path)?;
     let now = std::time::SystemTime::now();
     let dur = now.duration_since(then)?;
     Ok(dur.cmp(duration) == ordering)
 }
 
-fn is_size(item: &mut Item, ordering: Ordering, size: &Byte) -> anyhow::Result<bool> {
-    Ok(item.size()?.cmp(size) == ordering)
+fn date_of(path: &Path, timestamp: Timestamp) -> anyhow::Result<NaiveDate> {
+    Ok(local_date(timestamp.read(path)?))
 }
 
-fn is_children_count(path: &Path, ordering: Ordering, count: &usize) -> anyhow::Result<bool> {
-    Ok(path.is_dir() && std::fs::read_dir(path)?.count().cmp(count) == ordering)
+fn is_name_match(item: &Item, pattern: &str) -> anyhow::Result<bool> {
+    let regex = {
+        let mut regexes = REGEXES.lock().expect("unable to aquire mutex");
+        match regexes.get(pattern) {
+            Some(regex) => regex.clone(),
+            None => {
+                let regex = Regex::new(pattern)?;
+                regexes.insert(pattern.to_owned(), regex.clone());
+                regex
+            }
+        }
+    };
+    Ok(item.name().is_some_and(|name| regex.is_match(&name)))
 }
 
-fn is_matcher_type(path: &Path, tp: MatcherType) -> anyhow::Result<bool> {
-    Ok(infer::get_from_path(path)?
-        .with_context(|| "Unknown file format")?
-        .matcher_type()
-        == tp)
+fn is_name_glob(item: &Item, pattern: &str, ignore_case: bool) -> anyhow::Result<bool> {
+    let options = MatchOptions {
+        case_sensitive: !ignore_case,
+        require_literal_separator: false,
+        require_literal_leading_dot: false,
+    };
+    let pattern = {
+        let mut globs = GLOBS.lock().expect("unable to aquire mutex");
+        match globs.get(pattern) {
+            Some(glob) => glob.clone(),
+            None => {
+                let glob = Pattern::new(pattern)?;
+                globs.insert(pattern.to_owned(), glob.clone());
+                glob
+            }
+        }
+    };
+    Ok(item
+        .name()
+        .is_some_and(|name| pattern.matches_with(&name, options)))
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-struct SingleTag {
-    tag: Tag,
-    used: bool,
+fn is_name_with(
+    item: &Item,
+    text: &str,
+    ignore_case: bool,
+    f: impl Fn(&str, &str) -> bool,
+) -> bool {
+    match item.name() {
+        Some(name) if ignore_case => f(&name.to_lowercase(), &text.to_lowercase()),
+        Some(name) => f(&name, text),
+        None => false,
+    }
 }
 
-impl Default for SingleTag {
-    fn default() -> Self {
-        SingleTag {
-            tag: Tag::default(),
-            used: true,
+/// Ways to match the name offered for custom tags.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NameMatch {
+    Glob,
+    Regex,
+    Prefix,
+    Suffix,
+    Contains,
+    Extensions,
+}
+
+impl NameMatch {
+    pub const ALL: [NameMatch; 6] = [
+        NameMatch::Glob,
+        NameMatch::Regex,
+        NameMatch::Prefix,
+        NameMatch::Suffix,
+        NameMatch::Contains,
+        NameMatch::Extensions,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NameMatch::Glob => "Glob",
+            NameMatch::Regex => "Regular expression",
+            NameMatch::Prefix => "Starts with",
+            NameMatch::Suffix => "Ends with",
+            NameMatch::Contains => "Contains",
+            NameMatch::Extensions => "Extensions",
         }
     }
 }
 
-impl SingleTag {
-    fn is(&self, item: &mut Item) -> anyhow::Result<bool> {
-        Ok(self.tag.is(item)? == self.used)
+/// A custom tag that matches the name of a file in one of the ways of [`NameMatch`].
+pub fn custom_name_tag(kind: NameMatch, text: &str, ignore_case: bool) -> anyhow::Result<Tag> {
+    let text = text.trim().to_owned();
+    if text.is_empty() {
+        anyhow::bail!("The name pattern is empty");
     }
-    fn name(&self) -> String {
-        if self.used {
-            self.tag.name().to_owned()
-        } else {
-            format!("NOT({})", self.tag.name())
+    let (basis, name, desc) = match kind {
+        NameMatch::Glob => {
+            Pattern::new(&text)?;
+            let desc =
+                format!("A custom tag which includes files whose names match the glob {text:?}.");
+            (
+                Base::NameGlob {
+                    pattern: text.clone(),
+                    ignore_case,
+                },
+                text,
+                desc,
+            )
+        }
+        NameMatch::Regex => {
+            let regex = if ignore_case {
+                format!("(?i){text}")
+            } else {
+                text.clone()
+            };
+            Regex::new(&regex)?;
+            let desc = format!("A custom tag which includes files whose names match the regular expression {text:?}.");
+            (Base::NameRegex(regex), format!("~ {text}"), desc)
+        }
+        NameMatch::Prefix => {
+            let desc =
+                format!("A custom tag which includes files whose names start with {text:?}.");
+            (
+                Base::NamePrefix {
+                    prefix: text.clone(),
+                    ignore_case,
+                },
+                format!("{text}…"),
+                desc,
+            )
         }
+        NameMatch::Suffix => {
+            let desc = format!("A custom tag which includes files whose names end with {text:?}.");
+            (
+                Base::NameSuffix {
+                    suffix: text.clone(),
+                    ignore_case,
+                },
+                format!("…{text}"),
+                desc,
+            )
+        }
+        NameMatch::Contains => {
+            let desc = format!("A custom tag which includes files whose names contain {text:?}.");
+            (
+                Base::NameContains {
+                    text: text.clone(),
+                    ignore_case,
+                },
+                format!("…{text}…"),
+                desc,
+            )
+        }
+        NameMatch::Extensions => {
+            let extensions = text
+                .split(',')
+                .map(|ext| ext.trim().trim_start_matches('.').to_owned())
+                .filter(|ext| !ext.is_empty())
+                .collect::<Vec<_>>();
+            let name = extensions
+                .iter()
+                .map(|ext| format!(".{ext}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let desc = format!("A custom tag which includes files with the extensions {name}.");
+            let basis = if ignore_case {
+                Base::ExtensionIgnoreCase(extensions)
+            } else {
+                Base::Extension(extensions)
+            };
+            (basis, name, desc)
+        }
+    };
+    let case = if ignore_case {
+        " Letter case is ignored."
+    } else {
+        ""
+    };
+    Ok(Tag {
+        name: format!("🔤 {name}"),
+        basis,
+        desc: desc + case,
+    })
+}
+
+/// A custom tag for files whose `timestamp` is more or less than `duration` ago.
+pub fn custom_age_tag(timestamp: Timestamp, ordering: Ordering, duration: Duration) -> Tag {
+    let text = duration_string::DurationString::from(duration).to_string();
+    let (sign, than) = if ordering == Ordering::Less {
+        ("<", "less")
+    } else {
+        (">", "more")
+    };
+    let verb = match timestamp {
+        Timestamp::Created => "created",
+        Timestamp::Modified => "last modified",
+        Timestamp::Accessed => "last opened",
+        Timestamp::Taken => "taken",
+    };
+    let name = match timestamp {
+        Timestamp::Created => "Created",
+        Timestamp::Modified => "Modified",
+        Timestamp::Accessed => "Opened",
+        Timestamp::Taken => "Taken",
+    };
+    Tag {
+        name: format!("🕒 {name} {sign} {text}"),
+        desc: format!("A custom tag which includes files that were {verb} {than} than {text} ago."),
+        basis: Base::age(timestamp, ordering, duration),
     }
-    fn desc(&self) -> String {
-        self.tag.desc().to_owned()
+}
+
+fn is_size(item: &mut Item, ordering: Ordering, size: &Byte) -> anyhow::Result<bool> {
+    Ok(item.size()?.cmp(size) == ordering)
+}
+
+fn is_children_count(path: &Path, ordering: Ordering, count: &usize) -> anyhow::Result<bool> {
+    Ok(path.is_dir() && std::fs::read_dir(path)?.count().cmp(count) == ordering)
+}
+
+/// Conditions on the dimensions do not hold for the files that are not images.
+fn is_image_with(path: &Path, f: impl FnOnce(&Dimensions) -> bool) -> anyhow::Result<bool> {
+    Ok(image_dimensions(path)?.as_ref().is_some_and(f))
+}
+
+/// Files of an unknown format and folders are of no type.
+fn is_matcher_type(path: &Path, tp: MatcherType) -> anyhow::Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
     }
+    Ok(infer::get_from_path(path)?.is_some_and(|kind| kind.matcher_type() == tp))
+}
+
+/// A condition on tags built from `AND`, `OR` and `NOT`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TagExpr {
+    Tag(Tag),
+    Not(Box<TagExpr>),
+    /// Holds if all of the expressions hold.
+    And(Vec<TagExpr>),
+    /// Holds if any of the expressions holds.
+    Or(Vec<TagExpr>),
 }
 
-#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
-pub struct TagExpr(SingleTag, Vec<SingleTag>);
+impl Default for TagExpr {
+    fn default() -> Self {
+        TagExpr::Tag(Tag::default())
+    }
+}
 
 impl TagExpr {
+    /// An expression of a single tag, which is negated unless `used` is set.
     pub fn new(tag: Tag, used: bool) -> Self {
-        TagExpr(SingleTag { tag, used }, Vec::new())
+        if used {
+            TagExpr::Tag(tag)
+        } else {
+            TagExpr::Not(Box::new(TagExpr::Tag(tag)))
+        }
     }
+    /// Whether the expression holds for the item.
+    ///
+    /// A condition that cannot be checked is an error, which `NOT` passes on,
+    /// so the item is skipped rather than acted on. `OR` holds if any branch holds,
+    /// and only passes on the first error otherwise.
     pub fn is(&self, item: &mut Item) -> anyhow::Result<bool> {
-        let mut result = self.0.is(item)?;
-        for single in &self.1 {
-            result = result && single.is(item)?;
+        match self {
+            TagExpr::Tag(tag) => tag.is(item),
+            TagExpr::Not(expr) => Ok(!expr.is(item)?),
+            TagExpr::And(exprs) => {
+                for expr in exprs {
+                    if !expr.is(item)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            TagExpr::Or(exprs) => {
+                let mut error = None;
+                for expr in exprs {
+                    match expr.is(item) {
+                        Ok(true) => return Ok(true),
+                        Ok(false) => {}
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                error.map_or(Ok(false), Err)
+            }
         }
-        Ok(result)
     }
     pub fn name(&self) -> String {
-        std::iter::once(&self.0)
-            .chain(self.1.iter())
-            .map(|single| single.name())
-            .collect::<Vec<_>>()
-            .join(" AND ")
+        match self {
+            TagExpr::Tag(tag) => tag.name().to_owned(),
+            TagExpr::Not(expr) => format!("NOT({})", expr.name()),
+            TagExpr::And(exprs) => join_names(exprs, " AND "),
+            TagExpr::Or(exprs) => join_names(exprs, " OR "),
+        }
     }
     pub fn desc(&self) -> String {
-        if self.1.is_empty() {
-            self.0.desc()
+        let tags = self.tags();
+        if let [tag] = &tags[..] {
+            tag.desc().to_owned()
         } else {
-            std::iter::once(&self.0)
-                .chain(self.1.iter())
-                .map(|single| format!("{}: {}", single.tag.name(), single.desc()))
+            tags.iter()
+                .map(|tag| format!("{}: {}", tag.name(), tag.desc()))
                 .collect::<Vec<_>>()
                 .join("\n")
         }
     }
+    /// All of the distinct tags used in the expression.
+    pub fn tags(&self) -> Vec<&Tag> {
+        let mut tags = Vec::new();
+        self.collect_tags(&mut tags);
+        tags
+    }
+    fn collect_tags<'a>(&'a self, tags: &mut Vec<&'a Tag>) {
+        match self {
+            TagExpr::Tag(tag) => {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            TagExpr::Not(expr) => expr.collect_tags(tags),
+            TagExpr::And(exprs) | TagExpr::Or(exprs) => {
+                exprs.iter().for_each(|expr| expr.collect_tags(tags))
+            }
+        }
+    }
     pub fn has(&self, t: &Tag) -> bool {
-        &self.0.tag == t || self.1.iter().any(|single| &single.tag == t)
+        self.tags().contains(&t)
     }
+    /// Remove every use of the tag, unless it is the only one in the expression.
     pub fn remove(&mut self, t: &Tag) {
-        if &self.0.tag == t && !self.1.is_empty() {
-            self.0 = self.1.remove(0);
-        } else if let Some(index) = self.1.iter().position(|single| &single.tag == t) {
-            self.1.remove(index);
+        if let Some(expr) = self.clone().without(t) {
+            *self = expr;
+        }
+    }
+    fn without(self, t: &Tag) -> Option<TagExpr> {
+        let group = |exprs: Vec<TagExpr>, f: fn(Vec<TagExpr>) -> TagExpr| {
+            let mut exprs = exprs
+                .into_iter()
+                .filter_map(|expr| expr.without(t))
+                .collect::<Vec<_>>();
+            match exprs.len() {
+                0 => None,
+                1 => exprs.pop(),
+                _ => Some(f(exprs)),
+            }
+        };
+        match self {
+            TagExpr::Tag(tag) => (&tag != t).then_some(TagExpr::Tag(tag)),
+            TagExpr::Not(expr) => expr.without(t).map(|expr| TagExpr::Not(Box::new(expr))),
+            TagExpr::And(exprs) => group(exprs, TagExpr::And),
+            TagExpr::Or(exprs) => group(exprs, TagExpr::Or),
         }
     }
-    pub fn push(&mut self, tag: Tag, used: bool) {
-        self.1.push(SingleTag { tag, used })
+    /// Require `expr` to hold as well.
+    pub fn and(&mut self, expr: TagExpr) {
+        match self {
+            TagExpr::And(exprs) => exprs.push(expr),
+            _ => *self = TagExpr::And(vec![std::mem::take(self), expr]),
+        }
+    }
+    /// Allow `expr` as an alternative to the last group of the expression,
+    /// so `A AND B` becomes `A AND (B OR expr)`.
+    pub fn or(&mut self, expr: TagExpr) {
+        match self {
+            TagExpr::And(exprs) if !exprs.is_empty() => {
+                exprs.last_mut().expect("no last expression").or(expr)
+            }
+            TagExpr::Or(exprs) => exprs.push(expr),
+            _ => *self = TagExpr::Or(vec![std::mem::take(self), expr]),
+        }
+    }
+}
+
+fn join_names(exprs: &[TagExpr], separator: &str) -> String {
+    exprs
+        .iter()
+        .map(|expr| match expr {
+            TagExpr::And(_) | TagExpr::Or(_) => format!("({})", expr.name()),
+            TagExpr::Tag(_) | TagExpr::Not(_) => expr.name(),
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Accepts both the expression tree and the list of tags of older rule files,
+/// where every tag could only be negated and all of them had to hold.
+pub fn deserialize_tag_expr<'de, D: Deserializer<'de>>(d: D) -> Result<TagExpr, D::Error> {
+    #[derive(Deserialize)]
+    struct SingleTag {
+        tag: Tag,
+        used: bool,
     }
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Tree(TagExpr),
+        List(SingleTag, Vec<SingleTag>),
+    }
+    Ok(match Repr::deserialize(d)? {
+        Repr::Tree(expr) => expr,
+        Repr::List(first, rest) if rest.is_empty() => TagExpr::new(first.tag, first.used),
+        Repr::List(first, rest) => TagExpr::And(
+            std::iter::once(first)
+                .chain(rest)
+                .map(|single| TagExpr::new(single.tag, single.used))
+                .collect(),
+        ),
+    })
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
@@ -203,6 +724,7 @@ pub fn all_tags_sorted_by_columns() -> [Vec<Tag>; 4] {
             Tag { name: "🗃️ Archive".into(), basis: Base::IsArchive, desc: "A compressed file format.".into() },
             Tag { name: "📃 Document".into(), basis: Base::IsDocument, desc: "A file recognizable by an office suite, such as a text document, presentation or a spreadsheet.".into() },
             Tag { name: "📚 Book".into(), basis: Base::IsBook, desc: "A document that is recognizable by book readers.".into() },
+            Tag { name: "📍 Geotagged".into(), basis: Base::HasGps, desc: "A photo that records where it was taken.".into() },
         ],
         vec![
             Tag { name: "💾 < 1KB".into(), basis: Base::SizeLT(Byte::from_str("1KB").unwrap()), desc: "Various files that have their total size less than 1KB. Size for folders is calculated recursively.".into()    },
@@ -217,7 +739,168 @@ pub fn all_tags_sorted_by_columns() -> [Vec<Tag>; 4] {
         ],
         vec![
             Tag { name: "📂 Empty Folder".into(),  basis: Base::ChildrenCountET(0), desc: "An empty folder.".into() },
+            Tag { name: "👯 Duplicate".into(), basis: Base::IsDuplicate, desc: "A file with exactly the same content as an older file in the same folder or its subfolders. The oldest copy is never considered a duplicate.".into() },
+            Tag { name: "⏳ Settled".into(), basis: Base::StableFor(Duration::from_secs(60)), desc: "A file that has not changed for at least a minute and is not a download in progress. A file has to be seen by two runs of the rules to settle.".into() },
+            Tag { name: "👤 Mine".into(), basis: Base::User(Account::Current), desc: "A file owned by you. Only supported on Unix.".into() },
+            Tag { name: "🌍 World-writable".into(), basis: Base::ModeAll(mode::WORLD_WRITABLE), desc: "A file that any user of the computer can change. Only supported on Unix.".into() },
             Tag::dummy(),
         ]
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{custom_age_tag, custom_name_tag, Base, NameMatch, Tag, TagExpr};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Item, Timestamp};
+    use std::{
+        cmp::Ordering,
+        fs::FileTimes,
+        time::{Duration, SystemTime},
+    };
+
+    fn name_tag(name: &str) -> Tag {
+        Tag {
+            name: name.into(),
+            desc: String::new(),
+            basis: Base::Name(name.into()),
+        }
+    }
+
+    #[test]
+    fn match_name() {
+        let path = test_dir("tag").join("IMG_0042.JPG");
+        std::fs::write(&path, "").unwrap();
+        let mut item = Item::new(&path).unwrap();
+        let mut is = |kind, text, ignore_case| {
+            custom_name_tag(kind, text, ignore_case)
+                .unwrap()
+                .basis
+                .is(&mut item)
+                .unwrap()
+        };
+        assert!(is(NameMatch::Glob, "IMG_*.JPG", false));
+        assert!(!is(NameMatch::Glob, "img_*.jpg", false));
+        assert!(is(NameMatch::Glob, "img_*.jpg", true));
+        assert!(is(NameMatch::Regex, r"^IMG_\d+", false));
+        assert!(is(NameMatch::Regex, r"^img_\d+", true));
+        assert!(is(NameMatch::Prefix, "IMG_", false));
+        assert!(!is(NameMatch::Suffix, ".jpg", false));
+        assert!(is(NameMatch::Suffix, ".jpg", true));
+        assert!(is(NameMatch::Contains, "0042", false));
+        assert!(!is(NameMatch::Extensions, "jpg, png", false));
+        assert!(is(NameMatch::Extensions, ".jpg, .png", true));
+        assert!(custom_name_tag(NameMatch::Glob, "[", false).is_err());
+        assert!(custom_name_tag(NameMatch::Regex, "(", false).is_err());
+        assert!(custom_name_tag(NameMatch::Prefix, " ", false).is_err());
+    }
+
+    #[test]
+    fn age() {
+        let path = test_dir("tag").join("old.txt");
+        std::fs::write(&path, "").unwrap();
+        let long_ago = SystemTime::now() - Duration::from_secs(100 * 24 * 60 * 60);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_times(
+                FileTimes::new()
+                    .set_accessed(long_ago)
+                    .set_modified(long_ago),
+            )
+            .unwrap();
+        let mut item = Item::new(&path).unwrap();
+        let ninety_days = Duration::from_secs(90 * 24 * 60 * 60);
+        let mut is = |timestamp, ordering| {
+            custom_age_tag(timestamp, ordering, ninety_days)
+                .basis
+                .is(&mut item)
+                .unwrap()
+        };
+        assert!(is(Timestamp::Accessed, Ordering::Greater));
+        assert!(is(Timestamp::Modified, Ordering::Greater));
+        assert!(!is(Timestamp::Modified, Ordering::Less));
+        assert_eq!(
+            custom_age_tag(Timestamp::Created, Ordering::Less, ninety_days).basis,
+            Base::LifetimeLT(ninety_days)
+        );
+    }
+
+    #[test]
+    fn evaluate() {
+        let path = test_dir("tag").join("a.txt");
+        std::fs::write(&path, "").unwrap();
+        let mut item = Item::new(&path).unwrap();
+        let (a, b, c) = (name_tag("a.txt"), name_tag("b.txt"), name_tag("c.txt"));
+        // (a OR b) AND NOT c
+        let mut expr = TagExpr::new(a.clone(), true);
+        expr.or(TagExpr::new(b.clone(), true));
+        expr.and(TagExpr::new(c.clone(), false));
+        assert_eq!(expr.name(), "(a.txt OR b.txt) AND NOT(c.txt)");
+        assert!(expr.is(&mut item).unwrap());
+        // (a OR b) AND (NOT c OR c) AND b
+        expr.or(TagExpr::new(c.clone(), true));
+        expr.and(TagExpr::new(b.clone(), true));
+        assert!(!expr.is(&mut item).unwrap());
+        expr.remove(&b);
+        assert_eq!(expr.name(), "a.txt AND (NOT(c.txt) OR c.txt)");
+        assert!(expr.is(&mut item).unwrap());
+    }
+
+    #[test]
+    fn evaluate_unknown_format() {
+        let path = test_dir("tag").join("notes.txt");
+        std::fs::write(&path, "plain text").unwrap();
+        let mut item = Item::new(&path).unwrap();
+        let tag = |basis| Tag {
+            name: String::new(),
+            desc: String::new(),
+            basis,
+        };
+        let image = TagExpr::new(tag(Base::IsImage), true);
+        let txt = TagExpr::new(tag(Base::Extension(vec!["txt".into()])), true);
+        // An invalid regular expression cannot be checked at all
+        let broken = TagExpr::new(tag(Base::NameRegex("(".into())), true);
+        assert!(!image.is(&mut item).unwrap());
+        assert!(TagExpr::new(tag(Base::IsImage), false)
+            .is(&mut item)
+            .unwrap());
+        assert!(TagExpr::Or(vec![image.clone(), txt.clone()])
+            .is(&mut item)
+            .unwrap());
+        assert!(TagExpr::Or(vec![broken.clone(), txt])
+            .is(&mut item)
+            .unwrap());
+        assert!(TagExpr::Or(vec![broken.clone(), image.clone()])
+            .is(&mut item)
+            .is_err());
+        // The item is skipped instead of counting as not matching
+        assert!(TagExpr::Not(Box::new(broken.clone()))
+            .is(&mut item)
+            .is_err());
+        assert!(TagExpr::new(tag(Base::NameRegex("(".into())), false)
+            .is(&mut item)
+            .is_err());
+        assert!(broken.is(&mut item).is_err());
+    }
+
+    #[test]
+    fn migrate_list() {
+        #[derive(serde::Deserialize)]
+        struct Event {
+            #[serde(deserialize_with = "super::deserialize_tag_expr")]
+            expr: TagExpr,
+        }
+        let (a, b) = (name_tag("a"), name_tag("b"));
+        let legacy = serde_json::json!({
+            "expr": [{ "tag": a, "used": true }, [{ "tag": b, "used": false }]]
+        });
+        let expected = TagExpr::And(vec![TagExpr::new(a, true), TagExpr::new(b, false)]);
+        let event: Event = serde_json::from_value(legacy).unwrap();
+        assert_eq!(event.expr, expected);
+        let current = serde_json::json!({ "expr": expected });
+        let event: Event = serde_json::from_value(current).unwrap();
+        assert_eq!(event.expr, expected);
+    }
+}
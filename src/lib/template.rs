@@ -0,0 +1,420 @@
+//! Templates with placeholders that are expanded separately for every item.
+use super::{audio_info, Item, Timestamp};
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, path::PathBuf};
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A string with placeholders such as `{stem}` or `{created:%Y-%m-%d}`.
+///
+/// Supported placeholders:
+/// - `{path}`: full path to the file,
+/// - `{dir}`: folder that contains the file,
+/// - `{name}`: file name with the extension,
+/// - `{stem}`: file name without the extension,
+/// - `{ext}`: extension of the file, a dot right before it is left out
+///   for files without an extension and for folders,
+/// - `{created}` and `{modified}`: timestamps, optionally followed by
+///   a `chrono` format string, e.g. `{modified:%Y}`,
+/// - `{taken}`: when a photo was taken, or the modification time of files
+///   without the date in their metadata, with an optional format too,
+/// - `{artist}`, `{album}`, `{genre}` and `{year}`: tags of audio files,
+///   `Unknown` if the tag is missing or the text after a colon, e.g. `{album:Singles}`,
+/// - `{counter}`: empty at first, becomes `-1`, `-2` and so on
+///   if the expanded name collides with an existing file.
+///
+/// Templates are also used as target folders of copy and move events,
+/// e.g. `~/Pictures/{modified:%Y}/{modified:%m}`.
+///
+/// Use `{{` and `}}` to insert the braces themselves.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Template(String);
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Literal(String),
+    Placeholder {
+        key: &'a str,
+        format: Option<&'a str>,
+    },
+}
+
+impl Template {
+    pub fn new(s: impl Into<String>) -> Self {
+        Template(s.into())
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// Check that the template is well-formed and only uses known placeholders.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for segment in self.segments()? {
+            if let Segment::Placeholder { key, format } = segment {
+                if !is_known(key) {
+                    anyhow::bail!("Unknown placeholder {{{key}}}");
+                }
+                if matches!(key, "created" | "modified" | "taken") {
+                    format_time(std::time::SystemTime::now(), format)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Whether the template can resolve collisions by itself.
+    pub fn has_counter(&self) -> bool {
+        matches!(self.segments(), Ok(segments) if segments.iter().any(|segment| matches!(segment, Segment::Placeholder { key: "counter", .. })))
+    }
+    /// Expand every placeholder using the information about `item`.
+    ///
+    /// `counter` is the value of the `{counter}` placeholder,
+    /// `0` means that the placeholder is left empty.
+    pub fn expand(&self, item: &Item, counter: usize) -> anyhow::Result<String> {
+        let mut result = String::new();
+        for segment in self.segments()? {
+            match segment {
+                Segment::Literal(s) => result.push_str(&s),
+                Segment::Placeholder { key, format } => {
+                    let value = expand_placeholder(item, key, format, counter)?;
+                    if key == "ext" && value.is_empty() && result.ends_with('.') {
+                        result.pop();
+                    }
+                    result.push_str(&value)
+                }
+            }
+        }
+        Ok(result)
+    }
+    /// Whether the item is already named as the template would name it,
+    /// whatever its name was before.
+    ///
+    /// `{stem}` and `{name}` match any text and `{counter}` matches any of its values.
+    pub fn matches_name(&self, item: &Item) -> anyhow::Result<bool> {
+        let mut pattern = String::from("^");
+        let mut literal = String::new();
+        for segment in self.segments()? {
+            match segment {
+                Segment::Literal(s) => literal.push_str(&s),
+                Segment::Placeholder {
+                    key: key @ ("stem" | "name" | "counter"),
+                    ..
+                } => {
+                    pattern.push_str(&regex::escape(&std::mem::take(&mut literal)));
+                    pattern.push_str(if key == "counter" { "(-[0-9]+)?" } else { ".+" });
+                }
+                Segment::Placeholder { key, format } => {
+                    let value = expand_placeholder(item, key, format, 0)?;
+                    if key == "ext" && value.is_empty() && literal.ends_with('.') {
+                        literal.pop();
+                    }
+                    literal.push_str(&value);
+                }
+            }
+        }
+        pattern.push_str(&regex::escape(&literal));
+        pattern.push('$');
+        Ok(Regex::new(&pattern)?.is_match(&item.name().unwrap_or_default()))
+    }
+    /// Expand the template as a path.
+    ///
+    /// A leading `~` stands for the home directory of the user.
+    pub fn expand_path(&self, item: &Item) -> anyhow::Result<PathBuf> {
+        let expanded = self.expand(item, 0)?;
+        match expanded.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with(std::path::MAIN_SEPARATOR) => {
+                Ok(dirs::home_dir()
+                    .with_context(|| "Unable to find user home directory")?
+                    .join(rest.trim_start_matches(std::path::MAIN_SEPARATOR)))
+            }
+            _ => Ok(PathBuf::from(expanded)),
+        }
+    }
+    /// Turn the template into a shell script that reads the values
+    /// of the placeholders from its positional arguments.
+    ///
+    /// Returns the script and a template for each of the arguments.
+    pub fn to_shell_script(&self) -> anyhow::Result<(String, Vec<Template>)> {
+        let mut script = String::new();
+        let mut args = Vec::new();
+        for segment in self.segments()? {
+            match segment {
+                Segment::Literal(s) => script.push_str(&s),
+                Segment::Placeholder { key, format } => {
+                    args.push(match format {
+                        Some(format) => Template(format!("{{{key}:{format}}}")),
+                        None => Template(format!("{{{key}}}")),
+                    });
+                    script.push_str(&format!("\"${}\"", args.len()));
+                }
+            }
+        }
+        Ok((script, args))
+    }
+    fn segments(&self) -> anyhow::Result<Vec<Segment<'_>>> {
+        let s = self.0.as_str();
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '{' if matches!(chars.peek(), Some((_, '{'))) => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if matches!(chars.peek(), Some((_, '}'))) => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let end = s[index..]
+                        .find('}')
+                        .map(|end| index + end)
+                        .with_context(|| format!("Unclosed placeholder at {index}"))?;
+                    let inner = &s[index + 1..end];
+                    let (key, format) = match inner.split_once(':') {
+                        Some((key, format)) => (key, Some(format)),
+                        None => (inner, None),
+                    };
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder { key, format });
+                    while matches!(chars.peek(), Some((i, _)) if *i <= end) {
+                        chars.next();
+                    }
+                }
+                '}' => anyhow::bail!("Unexpected '}}' at {index}"),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(segments)
+    }
+}
+
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn is_known(key: &str) -> bool {
+    matches!(
+        key,
+        "path"
+            | "dir"
+            | "name"
+            | "stem"
+            | "ext"
+            | "created"
+            | "modified"
+            | "taken"
+            | "artist"
+            | "album"
+            | "genre"
+            | "year"
+            | "counter"
+    )
+}
+
+fn expand_placeholder(
+    item: &Item,
+    key: &str,
+    format: Option<&str>,
+    counter: usize,
+) -> anyhow::Result<String> {
+    Ok(match key {
+        "path" => item.path().to_string_lossy().into_owned(),
+        "dir" => item
+            .path()
+            .parent()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        "name" => item.name().unwrap_or_default(),
+        "stem" => item.stem().unwrap_or_default(),
+        "ext" => item.ext().unwrap_or_default(),
+        "created" => format_time(item.created(), format)?,
+        "modified" => format_time(item.modified(), format)?,
+        "taken" => format_time(
+            Timestamp::Taken
+                .read(item.path())
+                .unwrap_or_else(|_| item.modified()),
+            format,
+        )?,
+        "artist" | "album" | "genre" | "year" => {
+            let info = audio_info(item.path()).unwrap_or_default();
+            let tag = match key {
+                "artist" => info.artist,
+                "album" => info.album,
+                "genre" => info.genre,
+                _ => info.year.map(|year| year.to_string()),
+            };
+            tag.and_then(|tag| name_part(&tag))
+                .unwrap_or_else(|| format.unwrap_or("Unknown").to_owned())
+        }
+        "counter" if counter == 0 => String::new(),
+        "counter" => format!("-{counter}"),
+        key => anyhow::bail!("Unknown placeholder {{{key}}}"),
+    })
+}
+
+/// Turn the text of a tag into a part of a single file name.
+///
+/// Tags such as `AC/DC` must not create folders, and tags such as `..`
+/// must not lead out of the target folder, so they are left out.
+fn name_part(tag: &str) -> Option<String> {
+    let name = tag
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if matches!(c, '/' | '\\') { '-' } else { c })
+        .collect::<String>();
+    let name = name.trim();
+    (!name.is_empty() && !name.chars().all(|c| c == '.')).then(|| name.to_owned())
+}
+
+fn format_time(time: std::time::SystemTime, format: Option<&str>) -> anyhow::Result<String> {
+    let format = format.unwrap_or(DEFAULT_DATE_FORMAT);
+    let mut result = String::new();
+    // Formatting fails instead of panicking if the format string is invalid
+    write!(result, "{}", DateTime::<Local>::from(time).format(format))
+        .map_err(|_| anyhow::anyhow!("Invalid date format \"{format}\""))?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{name_part, Template};
+    use crate::lib::{testing::test_dir, Item};
+
+    fn test_item() -> Item {
+        let file = test_dir("template").join("report.final.pdf");
+        if !file.exists() {
+            std::fs::File::create(&file).unwrap();
+        }
+        Item::new(file).unwrap()
+    }
+
+    #[test]
+    fn expand_names() {
+        let item = test_item();
+        let template = Template::new("{stem}_{counter}.{ext}");
+        assert_eq!(template.expand(&item, 0).unwrap(), "report.final_.pdf");
+        assert_eq!(template.expand(&item, 2).unwrap(), "report.final_-2.pdf");
+        assert_eq!(
+            Template::new("{name}").expand(&item, 0).unwrap(),
+            "report.final.pdf"
+        );
+    }
+
+    #[test]
+    fn expand_without_extension() {
+        let dir = test_dir("template");
+        let file = dir.join("README");
+        if !file.exists() {
+            std::fs::File::create(&file).unwrap();
+        }
+        let folder = dir.join("photos");
+        std::fs::create_dir_all(&folder).unwrap();
+        let template = Template::new("{stem}-{counter}.{ext}");
+        for path in [file, folder] {
+            let item = Item::new(path).unwrap();
+            let stem = item.stem().unwrap();
+            assert_eq!(template.expand(&item, 1).unwrap(), format!("{stem}--1"));
+        }
+        assert_eq!(
+            Template::new("{stem}.{ext}.bak")
+                .expand(&Item::new(dir.join("README")).unwrap(), 0)
+                .unwrap(),
+            "README.bak"
+        );
+    }
+
+    #[test]
+    fn match_names() {
+        let item = test_item();
+        assert!(Template::new("{stem}.{ext}").matches_name(&item).unwrap());
+        assert!(Template::new("report.final{counter}.{ext}")
+            .matches_name(&item)
+            .unwrap());
+        assert!(Template::new("{stem}.final.pdf")
+            .matches_name(&item)
+            .unwrap());
+        assert!(!Template::new("{stem}-{modified:%Y}{counter}.{ext}")
+            .matches_name(&item)
+            .unwrap());
+        assert!(!Template::new("{stem}.{ext}.bak")
+            .matches_name(&item)
+            .unwrap());
+    }
+
+    #[test]
+    fn expand_escapes() {
+        let item = test_item();
+        let template = Template::new("{{{ext}}}");
+        assert_eq!(template.expand(&item, 0).unwrap(), "{pdf}");
+    }
+
+    #[test]
+    fn expand_dates() {
+        let item = test_item();
+        let year = chrono::Local::now().format("%Y").to_string();
+        let template = Template::new("{modified:%Y}");
+        assert_eq!(template.expand(&item, 0).unwrap(), year);
+        // Files that are not photos fall back to the modification time
+        let template = Template::new("{taken:%Y}");
+        assert_eq!(template.expand(&item, 0).unwrap(), year);
+    }
+
+    #[test]
+    fn expand_paths() {
+        let item = test_item();
+        let year = chrono::Local::now().format("%Y").to_string();
+        let template = Template::new("~/Documents/{modified:%Y}");
+        assert_eq!(
+            template.expand_path(&item).unwrap(),
+            dirs::home_dir().unwrap().join("Documents").join(year)
+        );
+        let template = Template::new("/tmp/{ext}");
+        assert_eq!(
+            template.expand_path(&item).unwrap(),
+            std::path::PathBuf::from("/tmp/pdf")
+        );
+    }
+
+    #[test]
+    fn name_parts() {
+        assert_eq!(name_part("AC/DC").as_deref(), Some("AC-DC"));
+        assert_eq!(name_part(" Nul\0l ").as_deref(), Some("Null"));
+        assert_eq!(name_part(".."), None);
+        assert_eq!(name_part(" . "), None);
+        assert_eq!(name_part("\n"), None);
+    }
+
+    #[test]
+    fn shell_script() {
+        let template = Template::new("cp {path} {{backup}}/{modified:%Y}");
+        let (script, args) = template.to_shell_script().unwrap();
+        assert_eq!(script, r#"cp "$1" {backup}/"$2""#);
+        assert_eq!(
+            args,
+            vec![Template::new("{path}"), Template::new("{modified:%Y}")]
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Template::new("{stem}-{created:%Y}{counter}.{ext}")
+            .validate()
+            .is_ok());
+        assert!(Template::new("{unknown}").validate().is_err());
+        assert!(Template::new("{stem").validate().is_err());
+        assert!(Template::new("stem}").validate().is_err());
+        assert!(Template::new("{created:%Q}").validate().is_err());
+        assert!(!Template::new("{stem}").has_counter());
+        assert!(Template::new("{counter}").has_counter());
+    }
+}
@@ -0,0 +1,19 @@
+//! Helpers shared by the tests.
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+
+lazy_static! {
+    /// A temporary folder of its own for every test run, left for the system to clean up.
+    static ref ROOT: PathBuf = tempfile::Builder::new()
+        .prefix("course_oop-test-")
+        .tempdir()
+        .expect("unable to create a temporary folder")
+        .keep();
+}
+
+/// A folder for the files of the tests of a module.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = ROOT.join(name);
+    std::fs::create_dir_all(&dir).expect("unable to create a test folder");
+    dir
+}
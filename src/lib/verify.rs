@@ -0,0 +1,369 @@
+//! Copying files between file systems without trusting the copy blindly.
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{Progress, Status};
+
+/// Number of bytes copied at once, between the checks for cancellation.
+const COPY_CHUNK_LEN: usize = 64 * 1024;
+
+/// Move a file or folder to exactly `dest`, which may be on another file system.
+///
+/// The content is first copied under a temporary name next to `dest` and flushed to the disk.
+/// Then the size and the BLAKE3 hash of every copied file are compared with the original.
+/// Only if they all match is the copy renamed to `dest` and the original removed,
+/// otherwise the copy is deleted and the original is left untouched.
+pub fn verified_move(
+    from: &Path,
+    dest: &Path,
+    overwrite: bool,
+    progress: &Progress,
+) -> anyhow::Result<()> {
+    if !overwrite && dest.symlink_metadata().is_ok() {
+        anyhow::bail!("{dest:?} already exists");
+    }
+    let temp = temp_path(dest)?;
+    // Leftovers of a move that was interrupted before
+    if temp.symlink_metadata().is_ok() {
+        remove_path(&temp)?;
+    }
+    if let Err(e) =
+        copy_tracked(from, &temp, false, true, progress).and_then(|()| verify(from, &temp))
+    {
+        let _ = remove_path(&temp);
+        return Err(e.context(format!(
+            "Unable to move {from:?} to {dest:?}, the original is left untouched"
+        )));
+    }
+    // Renaming replaces files, but not folders
+    if overwrite && dest.is_dir() {
+        remove_path(dest)?;
+    }
+    std::fs::rename(&temp, dest)?;
+    if let Some(parent) = dest.parent() {
+        sync_dir(parent)?;
+    }
+    remove_path(from)
+}
+
+/// Check that `copy` has the same structure, sizes and content as `original`.
+pub fn verify(original: &Path, copy: &Path) -> anyhow::Result<()> {
+    let metadata = original.symlink_metadata()?;
+    if metadata.is_symlink() {
+        if std::fs::read_link(original)? != std::fs::read_link(copy)? {
+            anyhow::bail!("{copy:?} does not link to the same path as {original:?}");
+        }
+        return Ok(());
+    }
+    if metadata.is_dir() {
+        let mut names = std::fs::read_dir(original)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut copied = std::fs::read_dir(copy)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.sort();
+        copied.sort();
+        if names != copied {
+            anyhow::bail!("{copy:?} does not contain the same files as {original:?}");
+        }
+        for name in names {
+            verify(&original.join(&name), &copy.join(&name))?;
+        }
+        return Ok(());
+    }
+    let (size, copied_size) = (
+        std::fs::metadata(original)?.len(),
+        std::fs::metadata(copy)?.len(),
+    );
+    if size != copied_size {
+        anyhow::bail!("{copy:?} has {copied_size} bytes instead of {size}");
+    }
+    if checksum(original)? != checksum(copy)? {
+        anyhow::bail!("The content of {copy:?} differs from {original:?}");
+    }
+    Ok(())
+}
+
+/// Hash the content of the file.
+///
+/// Unlike duplicate detection this never reuses earlier results,
+/// as the file has to be read again to be trusted.
+fn checksum(path: &Path) -> anyhow::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize())
+}
+
+/// Copy a file or folder to exactly `to`, reporting every file and byte to `progress`.
+///
+/// A folder is merged into an existing one at `to`, and symbolic links are copied as links.
+/// With `sync` every copied file and folder is flushed to the disk.
+/// The copy stops as soon as `progress` is cancelled, without leaving a partly copied file.
+///
+/// The progress handlers of `fs_extra` are not used, as they follow symbolic links
+/// and cannot stop the copy of a large file midway.
+pub fn copy_tracked(
+    from: &Path,
+    to: &Path,
+    overwrite: bool,
+    sync: bool,
+    progress: &Progress,
+) -> anyhow::Result<()> {
+    let (total_bytes, total_files) = content_size(from)?;
+    let mut status = progress.start(from, total_bytes, total_files);
+    copy_entry(from, to, overwrite, sync, progress, &mut status)
+}
+
+fn copy_entry(
+    from: &Path,
+    to: &Path,
+    overwrite: bool,
+    sync: bool,
+    progress: &Progress,
+    status: &mut Status,
+) -> anyhow::Result<()> {
+    let metadata = from.symlink_metadata()?;
+    if metadata.is_symlink() {
+        progress.check(&status.item)?;
+        status.file = from.to_owned();
+        if to.symlink_metadata().is_ok() {
+            if !overwrite || to.is_dir() {
+                anyhow::bail!("{to:?} already exists");
+            }
+            std::fs::remove_file(to)?;
+        }
+        copy_link(from, to)?;
+        status.copied_files += 1;
+        progress.report(status);
+    } else if metadata.is_dir() {
+        if !to.is_dir() {
+            std::fs::create_dir(to)?;
+        }
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            let path = entry.path();
+            copy_entry(
+                &path,
+                &to.join(entry.file_name()),
+                overwrite,
+                sync,
+                progress,
+                status,
+            )?;
+        }
+        if sync {
+            sync_dir(to)?;
+        }
+    } else {
+        progress.check(&status.item)?;
+        status.file = from.to_owned();
+        let copied_before = status.copied_bytes;
+        if !overwrite && to.symlink_metadata().is_ok() {
+            anyhow::bail!("{to:?} already exists");
+        }
+        if let Err(e) = copy_file(from, to, sync, progress, status) {
+            let _ = std::fs::remove_file(to);
+            status.copied_bytes = copied_before;
+            return Err(e);
+        }
+        status.copied_files += 1;
+        progress.report(status);
+    }
+    Ok(())
+}
+
+/// Total size and number of the files and links in a folder, without following the links.
+fn content_size(path: &Path) -> anyhow::Result<(u64, usize)> {
+    let metadata = path.symlink_metadata()?;
+    if !metadata.is_dir() {
+        return Ok((
+            if metadata.is_symlink() {
+                0
+            } else {
+                metadata.len()
+            },
+            1,
+        ));
+    }
+    let mut total = (0, 0);
+    for entry in std::fs::read_dir(path)? {
+        let (bytes, files) = content_size(&entry?.path())?;
+        total = (total.0 + bytes, total.1 + files);
+    }
+    Ok(total)
+}
+
+/// Create a link at `to` that points where the link at `from` does.
+fn copy_link(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let target = std::fs::read_link(from)?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, to)?;
+    #[cfg(windows)]
+    if from.is_dir() {
+        std::os::windows::fs::symlink_dir(target, to)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, to)?;
+    }
+    Ok(())
+}
+
+/// Copy the content of a single file in chunks, checking for cancellation after each of them.
+fn copy_file(
+    from: &Path,
+    to: &Path,
+    sync: bool,
+    progress: &Progress,
+    status: &mut Status,
+) -> anyhow::Result<()> {
+    let mut reader = File::open(from)?;
+    let mut writer = File::create(to)?;
+    let mut buf = vec![0; COPY_CHUNK_LEN];
+    loop {
+        progress.check(&status.item)?;
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        writer.write_all(&buf[..read])?;
+        status.copied_bytes += read as u64;
+        progress.report(status);
+    }
+    writer.set_permissions(reader.metadata()?.permissions())?;
+    if sync {
+        writer.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Make sure the entries of the folder are written to the disk.
+fn sync_dir(dir: &Path) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// A hidden path next to `dest` to copy to before the copy takes its place.
+pub fn temp_path(dest: &Path) -> anyhow::Result<PathBuf> {
+    let file_name = dest
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{dest:?} has no file name"))?;
+    Ok(dest.with_file_name(format!(".{}.part", file_name.to_string_lossy())))
+}
+
+/// Remove a file or a folder with everything inside it.
+pub fn remove_path(path: &Path) -> anyhow::Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_entry, verified_move, verify, COPY_CHUNK_LEN};
+    use crate::lib::testing::test_dir;
+    use crate::lib::{Cancelled, Progress};
+    use std::time::Duration;
+
+    #[test]
+    fn cancel_copy() {
+        let from = test_dir("verify").join("test4-from.bin");
+        let to = test_dir("verify").join("test4-to.bin");
+        std::fs::write(&from, vec![1; COPY_CHUNK_LEN * 3]).unwrap();
+        let progress = Progress::default();
+        let canceller = progress.clone();
+        let progress = progress.with_handler(move |_| canceller.cancel());
+        let mut status = progress.start(&from, COPY_CHUNK_LEN as u64 * 3, 1);
+        // Let the first chunk be reported right away
+        status.started -= Duration::from_secs(60);
+        let e = copy_entry(&from, &to, false, false, &progress, &mut status).unwrap_err();
+        assert!(e.is::<Cancelled>());
+        assert!(!to.exists());
+        assert_eq!(status.copied_bytes, 0);
+        assert!(progress.was_cancelled(&from));
+    }
+
+    #[test]
+    fn move_folder() {
+        let from = test_dir("verify").join("test1-from");
+        let dest = test_dir("verify").join("test1-dest");
+        for dir in [&from, &dest] {
+            if dir.exists() {
+                std::fs::remove_dir_all(dir).unwrap();
+            }
+        }
+        std::fs::create_dir_all(from.join("nested")).unwrap();
+        std::fs::write(from.join("file.txt"), "content").unwrap();
+        std::fs::write(from.join("nested").join("file.txt"), "nested").unwrap();
+        verified_move(&from, &dest, false, &Progress::default()).unwrap();
+        assert!(!from.exists());
+        assert_eq!(
+            std::fs::read_to_string(dest.join("nested").join("file.txt")).unwrap(),
+            "nested"
+        );
+        assert!(!test_dir("verify").join(".test1-dest.part").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn move_links() {
+        let from = test_dir("verify").join("test5-from");
+        let dest = test_dir("verify").join("test5-dest");
+        for dir in [&from, &dest] {
+            if dir.exists() {
+                std::fs::remove_dir_all(dir).unwrap();
+            }
+        }
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::write(from.join("file.txt"), "content").unwrap();
+        std::os::unix::fs::symlink("file.txt", from.join("link.txt")).unwrap();
+        // A link to the folder itself is not followed forever
+        std::os::unix::fs::symlink(".", from.join("loop")).unwrap();
+        verified_move(&from, &dest, false, &Progress::default()).unwrap();
+        assert!(!from.exists());
+        assert_eq!(
+            std::fs::read_link(dest.join("link.txt")).unwrap(),
+            std::path::Path::new("file.txt")
+        );
+        assert_eq!(
+            std::fs::read_link(dest.join("loop")).unwrap(),
+            std::path::Path::new(".")
+        );
+    }
+
+    #[test]
+    fn keep_existing() {
+        let from = test_dir("verify").join("test2-from.txt");
+        let dest = test_dir("verify").join("test2-dest.txt");
+        std::fs::write(&from, "new").unwrap();
+        std::fs::write(&dest, "old").unwrap();
+        assert!(verified_move(&from, &dest, false, &Progress::default()).is_err());
+        assert!(from.exists());
+        verified_move(&from, &dest, true, &Progress::default()).unwrap();
+        assert!(!from.exists());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "new");
+    }
+
+    #[test]
+    fn detect_mismatch() {
+        let original = test_dir("verify").join("test3-original.txt");
+        let copy = test_dir("verify").join("test3-copy.txt");
+        std::fs::write(&original, "content").unwrap();
+        std::fs::write(&copy, "CONTENT").unwrap();
+        assert!(verify(&original, &copy).is_err());
+        std::fs::write(&copy, "conten").unwrap();
+        assert!(verify(&original, &copy).is_err());
+        std::fs::write(&copy, "content").unwrap();
+        assert!(verify(&original, &copy).is_ok());
+    }
+}
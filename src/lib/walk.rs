@@ -0,0 +1,160 @@
+//! Settings that control which files inside the rule folder an event looks at.
+use glob::{MatchOptions, Pattern};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+lazy_static! {
+    /// Patterns of the walks, compiled once as they are checked for every entry.
+    static ref PATTERNS: Mutex<HashMap<String, Pattern>> = Mutex::new(HashMap::new());
+}
+
+/// How deep an event goes into the rule folder and which entries it considers.
+///
+/// Patterns are globs such as `*.log` or `*/target`, checked by [`Walk::validate`]
+/// when the event is edited. Invalid patterns match nothing.
+/// A pattern without a `/` is matched against the file name,
+/// otherwise it is matched against the path relative to the rule folder,
+/// where `*` stays within one folder and `**` crosses any number of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Walk {
+    /// How many levels of folders are read, `1` means only the rule folder itself.
+    /// `None` means there is no limit.
+    pub depth: Option<usize>,
+    /// Only the entries that match one of the patterns are candidates.
+    /// Everything is a candidate if there are no patterns.
+    pub include: Vec<String>,
+    /// Entries that match one of the patterns are neither candidates nor entered.
+    pub exclude: Vec<String>,
+    /// Whether folders themselves are candidates, or only the files inside them.
+    pub dirs: bool,
+}
+
+impl Default for Walk {
+    fn default() -> Self {
+        Walk {
+            depth: Some(1),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            dirs: true,
+        }
+    }
+}
+
+impl Walk {
+    /// Check that all of the patterns are valid globs.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for pattern in self.include.iter().chain(self.exclude.iter()) {
+            Pattern::new(pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid pattern {pattern:?}: {e}"))?;
+        }
+        Ok(())
+    }
+    /// Whether the folder at the given level (starting from `1`) should be read.
+    pub fn enters(&self, level: usize) -> bool {
+        !matches!(self.depth, Some(depth) if level > depth)
+    }
+    /// Whether the entry and everything inside it is skipped.
+    pub fn excludes(&self, relative: &Path) -> bool {
+        matches_any(&self.exclude, relative)
+    }
+    /// Whether the entry should be checked against the tags of the event.
+    pub fn accepts(&self, relative: &Path, is_dir: bool) -> bool {
+        (self.dirs || !is_dir) && (self.include.is_empty() || matches_any(&self.include, relative))
+    }
+    /// Short description of the settings, shown next to the event.
+    pub fn summary(&self) -> String {
+        let depth = match self.depth {
+            Some(1) => "top level".to_owned(),
+            Some(depth) => format!("{depth} levels deep"),
+            None => "all levels".to_owned(),
+        };
+        let mut parts = vec![depth];
+        if !self.include.is_empty() {
+            parts.push(format!("only {}", self.include.join(", ")));
+        }
+        if !self.exclude.is_empty() {
+            parts.push(format!("except {}", self.exclude.join(", ")));
+        }
+        if !self.dirs {
+            parts.push("files only".to_owned());
+        }
+        format!("({})", parts.join("; "))
+    }
+}
+
+fn matches_any(patterns: &[String], relative: &Path) -> bool {
+    let name = relative.file_name().map(Path::new).unwrap_or(relative);
+    let mut compiled = PATTERNS.lock().expect("unable to aquire mutex");
+    patterns.iter().any(|pattern| {
+        let path = if pattern.contains('/') {
+            relative
+        } else {
+            name
+        };
+        if !compiled.contains_key(pattern) {
+            match Pattern::new(pattern) {
+                Ok(glob) => compiled.insert(pattern.clone(), glob),
+                Err(_) => return false,
+            };
+        }
+        compiled[pattern].matches_path_with(path, MATCH_OPTIONS)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Walk;
+    use std::path::Path;
+
+    #[test]
+    fn patterns() {
+        let walk = Walk {
+            include: vec!["*/target".into(), "*.log".into()],
+            exclude: vec![".git".into()],
+            ..Walk::default()
+        };
+        assert!(walk.accepts(Path::new("project/target"), true));
+        assert!(!walk.accepts(Path::new("project/nested/target"), true));
+        assert!(walk.accepts(Path::new("project/nested/build.log"), false));
+        assert!(!walk.accepts(Path::new("project/src"), true));
+        assert!(walk.excludes(Path::new("project/.git")));
+        assert!(!walk.excludes(Path::new("project/.github")));
+    }
+
+    #[test]
+    fn dirs_and_depth() {
+        let walk = Walk {
+            depth: Some(2),
+            dirs: false,
+            ..Walk::default()
+        };
+        assert!(!walk.accepts(Path::new("folder"), true));
+        assert!(walk.accepts(Path::new("folder/file"), false));
+        assert!(walk.enters(2));
+        assert!(!walk.enters(3));
+        assert!(Walk {
+            depth: None,
+            ..walk
+        }
+        .enters(100));
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Walk::default().validate().is_ok());
+        let walk = Walk {
+            include: vec!["[".into()],
+            ..Walk::default()
+        };
+        assert!(walk.validate().is_err());
+        assert!(!walk.accepts(Path::new("["), false));
+    }
+}
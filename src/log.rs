@@ -1,20 +1,156 @@
-use crate::lib::Event;
+use crate::lib::{Event, FileState, Item};
+use anyhow::Context;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
-pub struct Log(Vec<LogEntry>);
+#[serde(from = "Vec<LogEntry>", into = "Vec<LogEntry>")]
+pub struct Log {
+    entries: Vec<LogEntry>,
+    /// Histories of the events and the number of entries they were built from,
+    /// so the log is not read from the start on every run.
+    histories: Vec<(Event, History, usize)>,
+}
+
+impl From<Vec<LogEntry>> for Log {
+    fn from(entries: Vec<LogEntry>) -> Self {
+        Log {
+            entries,
+            histories: Vec::new(),
+        }
+    }
+}
+
+impl From<Log> for Vec<LogEntry> {
+    fn from(log: Log) -> Self {
+        log.entries
+    }
+}
 
 impl Log {
     pub fn new() -> Self {
-        Log(Vec::new())
+        Log::default()
     }
     pub fn push(&mut self, entry: LogEntry) {
-        self.0.push(entry);
+        self.entries.push(entry);
     }
     pub fn entries(&self) -> &[LogEntry] {
-        &self.0
+        &self.entries
+    }
+    /// Identifier for the next run of the rules.
+    pub fn next_run(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.run)
+            .max()
+            .map_or(0, |run| run + 1)
+    }
+    /// What the event has done so far, leaving out the actions that were undone.
+    pub fn history(&mut self, event: &Event) -> History {
+        let index = match self.histories.iter().position(|(e, ..)| e == event) {
+            Some(index) => index,
+            None => {
+                self.histories.push((event.clone(), History::default(), 0));
+                self.histories.len() - 1
+            }
+        };
+        let (_, history, read) = &mut self.histories[index];
+        for entry in self.entries[*read..]
+            .iter()
+            .filter(|entry| &entry.event == event && !entry.undone && !entry.undo)
+        {
+            if let Some(result) = &entry.result {
+                history.results.insert(result.clone());
+            }
+            // Later entries replace the earlier ones
+            history.processed.insert(entry.file.clone(), entry.time);
+        }
+        *read = self.entries.len();
+        history.clone()
+    }
+    /// Revert the action of the entry at `index` and log the undo as a new entry.
+    pub fn undo(&mut self, index: usize) -> anyhow::Result<()> {
+        let entry = self
+            .entries
+            .get(index)
+            .with_context(|| format!("There is no log entry at {index}"))?;
+        if entry.undone {
+            anyhow::bail!("The action has already been undone");
+        }
+        if entry.undo {
+            anyhow::bail!("An undo cannot be undone");
+        }
+        let restored = entry.event.undo(
+            &entry.file,
+            entry.result(),
+            entry.state.as_ref(),
+            entry.time,
+        )?;
+        let undo = LogEntry {
+            undo: true,
+            run: None,
+            details: Some(format!(
+                "Undo of the action from {}",
+                entry.time.format("%Y-%m-%d %H:%M:%S")
+            )),
+            ..LogEntry::new(
+                &entry.event,
+                restored.as_deref().and_then(Path::parent),
+                entry.result().unwrap_or(&entry.file),
+            )
+            .with_result(restored)
+        };
+        self.entries[index].undone = true;
+        self.entries.push(undo);
+        // The undone action has to be left out of the history again
+        self.histories.clear();
+        Ok(())
+    }
+    /// Revert all of the actions of the run, starting from the last one.
+    ///
+    /// Every action is attempted, even if some of them fail.
+    pub fn undo_run(&mut self, run: u64) -> anyhow::Result<()> {
+        let indices = (0..self.entries.len())
+            .rev()
+            .filter(|&index| self.entries[index].run == Some(run) && self.entries[index].can_undo())
+            .collect::<Vec<_>>();
+        let errors = indices
+            .into_iter()
+            .filter_map(|index| self.undo(index).err())
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(errors.join("\n")))
+        }
+    }
+}
+
+/// The actions an event has performed before, according to the log.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    /// Paths of the files the event has produced.
+    results: HashSet<PathBuf>,
+    /// When the event was last applied to each of the files, whether it succeeded or not.
+    processed: HashMap<PathBuf, DateTime<Local>>,
+}
+
+impl History {
+    /// Whether the file is a result of the event,
+    /// such as a file that was renamed by it already.
+    pub fn has_produced(&self, path: &Path) -> bool {
+        self.results.contains(path)
+    }
+    /// Whether the event was applied to the file since it was last modified.
+    pub fn has_processed(&self, item: &Item) -> bool {
+        self.processed
+            .get(item.path())
+            .is_some_and(|time| DateTime::<Local>::from(item.modified()) <= *time)
     }
 }
 
@@ -23,7 +159,26 @@ pub struct LogEntry {
     event: Event,
     source: Option<PathBuf>,
     file: PathBuf,
+    #[serde(default)]
+    result: Option<PathBuf>,
+    #[serde(default)]
+    details: Option<String>,
     time: DateTime<Local>,
+    /// Run of the rules the action was performed in.
+    #[serde(default)]
+    run: Option<u64>,
+    /// Whether the action has been reverted since.
+    #[serde(default)]
+    undone: bool,
+    /// Whether the entry records the revert of another action.
+    #[serde(default)]
+    undo: bool,
+    /// Whether the action has not succeeded, with the reason in the details.
+    #[serde(default)]
+    failed: bool,
+    /// The state of the result right after the action, to tell whether it has changed since.
+    #[serde(default)]
+    state: Option<FileState>,
 }
 
 impl LogEntry {
@@ -32,10 +187,22 @@ impl LogEntry {
             event: event.clone(),
             source: source.map(|path| path.as_ref().to_owned()),
             file: file.as_ref().to_owned(),
+            result: None,
+            details: None,
             time: Local::now(),
+            run: None,
+            undone: false,
+            undo: false,
+            failed: false,
+            state: None,
         }
     }
 
+    /// Record the path of the file produced by the event.
+    pub fn with_result(self, result: Option<PathBuf>) -> Self {
+        LogEntry { result, ..self }
+    }
+
     /// Get a reference to the log entry's event.
     pub fn event(&self) -> &Event {
         &self.event
@@ -51,8 +218,66 @@ impl LogEntry {
         &self.file
     }
 
+    /// Attach additional information, such as the output of a command.
+    pub fn with_details(self, details: Option<String>) -> Self {
+        LogEntry { details, ..self }
+    }
+
+    /// Record the state of the result right after the action.
+    pub fn with_state(self, state: Option<FileState>) -> Self {
+        LogEntry { state, ..self }
+    }
+
+    /// Record whether the action has not succeeded.
+    pub fn with_failed(self, failed: bool) -> Self {
+        LogEntry { failed, ..self }
+    }
+
+    /// Whether the action has not succeeded.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Get a reference to the path of the file produced by the event.
+    pub fn result(&self) -> Option<&Path> {
+        self.result.as_deref()
+    }
+
+    /// Get a reference to the additional information about the action.
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
     /// Get a reference to the log entry's time.
     pub fn time(&self) -> DateTime<Local> {
         self.time
     }
+
+    /// Record the run of the rules the action belongs to.
+    pub fn with_run(self, run: u64) -> Self {
+        LogEntry {
+            run: Some(run),
+            ..self
+        }
+    }
+
+    /// Get the run of the rules the action belongs to.
+    pub fn run(&self) -> Option<u64> {
+        self.run
+    }
+
+    /// Whether the action has been reverted.
+    pub fn is_undone(&self) -> bool {
+        self.undone
+    }
+
+    /// Whether the entry records the revert of another action.
+    pub fn is_undo(&self) -> bool {
+        self.undo
+    }
+
+    /// Whether the action can still be reverted.
+    pub fn can_undo(&self) -> bool {
+        !self.undone && !self.undo && self.event.can_undo()
+    }
 }
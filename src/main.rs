@@ -4,7 +4,9 @@ use components::edit_rule_window::{EditMode, EditRuleOutput, EditRuleWindow};
 use components::executor::Executor;
 use components::log_window::LogWindow;
 use components::property_window::PropertyWindow;
-use lib::{Event, FileType, Item, Rule, Tag, TagExpr, Var};
+use components::run_progress::{RunProgress, RunProgressOutput};
+use components::tag_library_window::{TagLibraryOutput, TagLibraryWindow};
+use lib::{Base, Event, FileType, Item, Rule, Tag, TagExpr, TagLibrary, Var, Walk};
 
 mod db;
 use db::Database;
@@ -25,16 +27,14 @@ use relm4::gtk::prelude::{
     BoxExt, Cast, GestureSingleExt, IsA, PopoverExt, SelectionModelExt, StaticType,
 };
 use relm4::{
-    adw, component, gtk, view, Component, ComponentParts, ComponentSender, RelmApp,
-    RelmRemoveAllExt, SimpleComponent, WidgetPlus,
+    adw, component, gtk, view, Component, ComponentController, ComponentParts, ComponentSender,
+    Controller, RelmApp, RelmRemoveAllExt, SimpleComponent, WidgetPlus,
 };
 use serde::{Deserialize, Serialize};
 
 use gtk::prelude::{ButtonExt, GtkWindowExt, OrientableExt, WidgetExt};
 use util::SENDER;
 
-use crate::lib::{all_tags, all_tags_sorted_by_columns};
-
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum AppMsg {
     Error(String, String),
@@ -48,6 +48,8 @@ pub enum AppMsg {
     EditRule(usize, Rule),
     DeleteRule(usize),
     ShowLog,
+    ShowTags,
+    CancelRun,
     OpenPropertiesAt(usize),
     Ignore,
     Quit,
@@ -79,6 +81,7 @@ impl AppData {
 pub struct App {
     pub data: AppData,
     pub executor: Executor,
+    pub progress: Controller<RunProgress>,
     pub root: gtk::ApplicationWindow,
     pub is_active: bool,
 }
@@ -134,6 +137,13 @@ impl SimpleComponent for App {
                         sender.input(AppMsg::ShowLog);
                     },
                 },
+                pack_end = &gtk::Button {
+                    set_icon_name: "bookmark-new-symbolic",
+                    set_tooltip_text: Some("Tags"),
+                    connect_clicked[sender] => move |_| {
+                        sender.input(AppMsg::ShowTags);
+                    },
+                },
                 pack_end = &gtk::Button {
                     set_icon_name: "view-refresh",
                     connect_clicked[sender] => move |_| {
@@ -142,7 +152,9 @@ impl SimpleComponent for App {
                 }
             },
 
-            gtk::Paned {
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                gtk::Paned {
                     set_shrink_start_child: false,
                     set_shrink_end_child: false,
                     set_start_child = &gtk::ScrolledWindow {
@@ -187,7 +199,10 @@ impl SimpleComponent for App {
                             set_vexpand: true,
                             set_enable_rubberband: true,
                             #[watch]
-                            set_model: Some(&selection_model(model.data.explorer.items())),
+                            set_model: Some(&selection_model(
+                                model.data.explorer.items(),
+                                &model.data.db.tags().lock().expect("unable to aquire mutex"),
+                            )),
                             set_factory: Some(&factory_identity()),
                             connect_activate[sender] => move |_, index| {
                                 sender.input(AppMsg::OpenAt(index as usize))
@@ -195,6 +210,8 @@ impl SimpleComponent for App {
                         }
                     }
                 }
+                append: model.progress.widget(),
+            }
         }
     }
 
@@ -211,8 +228,14 @@ impl SimpleComponent for App {
         sender: &ComponentSender<App>,
     ) -> ComponentParts<Self> {
         let data = AppData::new(db);
+        let progress = RunProgress::builder()
+            .launch(())
+            .forward(&sender.input, |output| match output {
+                RunProgressOutput::Cancel => AppMsg::CancelRun,
+            });
         let mut model = App {
-            executor: Executor::new(data.db.log()),
+            executor: Executor::new(data.db.log(), progress.sender()),
+            progress,
             data,
             root: root.clone(),
             is_active: true,
@@ -232,6 +255,7 @@ impl SimpleComponent for App {
             executor,
             root,
             is_active,
+            ..
         } = self;
 
         match message {
@@ -268,7 +292,12 @@ impl SimpleComponent for App {
                 let rule = Rule::default();
                 EditRuleWindow::builder()
                     .transient_for(root)
-                    .launch((rule, EditMode::Create))
+                    .launch((
+                        rule,
+                        EditMode::Create,
+                        data.explorer.dir().path().to_owned(),
+                        data.db.tags().clone(),
+                    ))
                     .forward(&sender.input, move |output| match output {
                         EditRuleOutput::Save(rule) => AppMsg::NewRule(rule),
                         _ => AppMsg::Ignore,
@@ -283,7 +312,12 @@ impl SimpleComponent for App {
                     .clone();
                 EditRuleWindow::builder()
                     .transient_for(root)
-                    .launch((rule, EditMode::Edit))
+                    .launch((
+                        rule,
+                        EditMode::Edit,
+                        data.explorer.dir().path().to_owned(),
+                        data.db.tags().clone(),
+                    ))
                     .forward(&sender.input, move |output| match output {
                         EditRuleOutput::Save(rule) => AppMsg::EditRule(index, rule),
                         EditRuleOutput::Cancel => AppMsg::Ignore,
@@ -315,9 +349,20 @@ impl SimpleComponent for App {
                     .transient_for(root)
                     .launch(data.db.log().clone());
             }
+            AppMsg::ShowTags => {
+                TagLibraryWindow::builder()
+                    .transient_for(root)
+                    .launch(data.db.tags().clone())
+                    .forward(&sender.input, |output| match output {
+                        TagLibraryOutput::Changed => AppMsg::Refresh,
+                    });
+            }
+            AppMsg::CancelRun => executor.cancel(),
             AppMsg::OpenPropertiesAt(index) => {
                 let item = data.explorer.items()[index].clone();
-                PropertyWindow::builder().transient_for(root).launch(item);
+                PropertyWindow::builder()
+                    .transient_for(root)
+                    .launch((item, data.db.tags().clone()));
             }
             AppMsg::Ignore => {}
         }
@@ -335,23 +380,19 @@ fn main() {
 }
 
 /// A selection model for the file view.
-fn selection_model(items: &[Item]) -> gtk::MultiSelection {
+fn selection_model(items: &[Item], library: &TagLibrary) -> gtk::MultiSelection {
     let list_model = gtk::gio::ListStore::new(gtk::Box::static_type());
     let selection_model = gtk::MultiSelection::new(Some(&list_model));
     for (index, item) in items.iter().enumerate() {
         let item = item.clone();
 
-        // For the use inside tooltips, we skip the second column
-        // which contains the size requirements, so we don't have to
-        // calculate them for every item.
-        let tags = {
-            let mut columns = all_tags_sorted_by_columns().into_iter().collect::<Vec<_>>();
-            columns.remove(1);
-            columns
-        }
-        .into_iter()
-        .flatten()
-        .collect::<Vec<_>>();
+        // For the use inside tooltips, we skip the tags with size requirements,
+        // so we don't have to calculate them for every item.
+        let tags = library
+            .tags()
+            .filter(|tag| !matches!(tag.basis, Base::SizeLT(_) | Base::SizeGT(_)))
+            .cloned()
+            .collect::<Vec<_>>();
 
         view! {
             gtk_box = gtk::Box {
@@ -437,6 +478,40 @@ pub fn var_view(var: &Var) -> impl IsA<gtk::Widget> {
                 .css_classes(vec!["link".into()])
                 .build(),
         )),
+        Var::Template(template) => bin.set_child(Some(
+            &gtk::Label::builder()
+                .label(template.as_str())
+                .css_classes(vec!["monospace".into()])
+                .build(),
+        )),
+        Var::Command(command) => bin.set_child(Some(
+            &gtk::Label::builder()
+                .label(&command.line())
+                .max_width_chars(30)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .tooltip_text(&command.line())
+                .css_classes(vec!["monospace".into()])
+                .build(),
+        )),
+        Var::Choice {
+            options, selected, ..
+        } => bin.set_child(Some(
+            &gtk::Label::builder()
+                .label(options[*selected])
+                .css_classes(vec!["opaque".into()])
+                .build(),
+        )),
+        // Only mention the walk settings if they differ from reading the top level
+        Var::Walk(walk) => {
+            if walk != &Walk::default() {
+                bin.set_child(Some(
+                    &gtk::Label::builder()
+                        .label(&walk.summary())
+                        .css_classes(vec!["opaque".into()])
+                        .build(),
+                ))
+            }
+        }
     }
     bin
 }
@@ -1,6 +1,7 @@
 /// An extension to `std::path::Path` to reduce boilerplate cote.
 pub trait PathExt {
     fn name(&self) -> Option<String>;
+    fn stem(&self) -> Option<String>;
     fn ext(&self) -> Option<String>;
 }
 
@@ -10,6 +11,11 @@ impl<P: AsRef<std::path::Path>> PathExt for P {
             .file_name()
             .map(|name| name.to_string_lossy().into_owned())
     }
+    fn stem(&self) -> Option<String> {
+        self.as_ref()
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+    }
     fn ext(&self) -> Option<String> {
         self.as_ref()
             .extension()